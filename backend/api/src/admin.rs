@@ -0,0 +1,218 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{get, post, web, HttpResponse};
+use fantastic_giggle_sql::{
+    Account, AuditAction, AuditActor, AuditLog, AuditRecord, Identity, PausedUser, PgPool, Profile,
+    RelationKind, RelationshipSync, Session, SyncRequest,
+};
+use fantastic_giggle_worker::{ActivityTracker, SupervisorHandle, UserActivity, WorkerState};
+use serde::Serialize;
+
+use crate::{session::AdminSession, ApiError, Result};
+
+#[derive(Serialize)]
+struct SyncBody {
+    kind: String,
+    finished_at: i64,
+}
+
+#[derive(Serialize)]
+struct UserBody {
+    user_id: i64,
+    account_id: i64,
+    provider: String,
+    screen_name: Option<String>,
+    admin: bool,
+    paused: bool,
+    tokens_revoked: bool,
+    last_synced: Vec<SyncBody>,
+    activity: UserActivity,
+}
+
+#[derive(Serialize)]
+struct WorkerBody {
+    name: String,
+    /// `running`, `restarting` or `gave_up`.
+    state: &'static str,
+    restarting_at: Option<i64>,
+    started_at: i64,
+    restarts: u32,
+    last_error: Option<String>,
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+async fn find_identity(pool: &PgPool, user_id: i64) -> Result<Identity> {
+    Identity::find_by_id(pool, user_id)
+        .await?
+        .ok_or(ApiError::NotFound)
+}
+
+async fn audit(
+    pool: &PgPool,
+    session: &AdminSession,
+    user_id: i64,
+    action: AuditAction,
+) -> Result<()> {
+    let record = AuditRecord::new(user_id, AuditActor::Admin, action)
+        .parameters(serde_json::json!({ "admin_id": session.0.user_id }).to_string());
+    AuditLog::append(pool, &record).await?;
+    Ok(())
+}
+
+/// Every identity with the state of its workers.
+#[get("/api/admin/users")]
+pub(crate) async fn list_users(
+    _session: AdminSession,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<ActivityTracker>,
+) -> Result<HttpResponse> {
+    let pool = pool.as_ref();
+    let identities = Identity::find_all(pool).await?;
+    let ids = identities.iter().map(|i| i.id).collect::<Vec<_>>();
+    let paused = PausedUser::find_all(pool)
+        .await?
+        .into_iter()
+        .map(|p| p.user_id)
+        .collect::<BTreeSet<_>>();
+    let screen_names = Profile::find_by_ids(pool, &ids)
+        .await?
+        .into_iter()
+        .map(|p| (p.id, p.screen_name))
+        .collect::<BTreeMap<_, _>>();
+    let mut last_synced = BTreeMap::<i64, Vec<SyncBody>>::new();
+    for sync in RelationshipSync::find_by_source_ids(pool, &ids).await? {
+        last_synced
+            .entry(sync.source_id)
+            .or_default()
+            .push(SyncBody {
+                kind: sync.kind,
+                finished_at: sync.finished_at.unix_timestamp(),
+            });
+    }
+    let mut admins = BTreeMap::new();
+    let mut users = vec![];
+    for identity in identities {
+        let admin = match admins.get(&identity.account_id) {
+            Some(&admin) => admin,
+            None => {
+                let admin = Account::find_by_id(pool, identity.account_id)
+                    .await?
+                    .is_some_and(|account| account.is_admin());
+                admins.insert(identity.account_id, admin);
+                admin
+            }
+        };
+        users.push(UserBody {
+            user_id: identity.id,
+            account_id: identity.account_id,
+            provider: identity.provider,
+            screen_name: screen_names.get(&identity.id).cloned(),
+            admin,
+            paused: paused.contains(&identity.id),
+            tokens_revoked: identity.access_key.is_empty(),
+            last_synced: last_synced.remove(&identity.id).unwrap_or_default(),
+            activity: tracker.get(identity.id),
+        });
+    }
+    Ok(HttpResponse::Ok().json(users))
+}
+
+/// Stops every worker of the user until resumed.
+#[post("/api/admin/users/{id}/pause")]
+pub(crate) async fn pause_user(
+    session: AdminSession,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let pool = pool.as_ref();
+    let identity = find_identity(pool, id.into_inner()).await?;
+    if PausedUser::pause(pool, identity.id).await? {
+        audit(pool, &session, identity.id, AuditAction::Pause).await?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/api/admin/users/{id}/resume")]
+pub(crate) async fn resume_user(
+    session: AdminSession,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let pool = pool.as_ref();
+    let identity = find_identity(pool, id.into_inner()).await?;
+    if PausedUser::resume(pool, identity.id).await? {
+        audit(pool, &session, identity.id, AuditAction::Resume).await?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Restarts the synchronization of every relation kind from the first page.
+#[post("/api/admin/users/{id}/resync")]
+pub(crate) async fn resync_user(
+    session: AdminSession,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let pool = pool.as_ref();
+    let identity = find_identity(pool, id.into_inner()).await?;
+    let mut tx = pool.begin().await?;
+    for kind in RelationKind::ALL {
+        SyncRequest::save(&mut tx, identity.id, kind).await?;
+    }
+    tx.commit().await?;
+    audit(pool, &session, identity.id, AuditAction::Resync).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Forgets the tokens of the user and logs the account out. The workers stop until the user logs
+/// in again.
+#[post("/api/admin/users/{id}/revoke")]
+pub(crate) async fn revoke_user(
+    session: AdminSession,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let pool = pool.as_ref();
+    let identity = find_identity(pool, id.into_inner()).await?;
+    let mut tx = pool.begin().await?;
+    Identity::revoke_tokens(&mut tx, identity.id).await?;
+    Session::delete_by_account_id(&mut tx, identity.account_id).await?;
+    tx.commit().await?;
+    audit(pool, &session, identity.id, AuditAction::RevokeTokens).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The workers run by the supervisor.
+#[get("/api/admin/workers")]
+pub(crate) async fn list_workers(
+    _session: AdminSession,
+    supervisor: web::Data<SupervisorHandle>,
+) -> Result<HttpResponse> {
+    let workers = supervisor
+        .statuses()
+        .into_iter()
+        .map(|status| {
+            let (state, restarting_at) = match status.state {
+                WorkerState::Running => ("running", None),
+                WorkerState::Restarting { at } => ("restarting", Some(unix_seconds(at))),
+                WorkerState::GaveUp => ("gave_up", None),
+            };
+            WorkerBody {
+                name: status.name,
+                state,
+                restarting_at,
+                started_at: unix_seconds(status.started_at),
+                restarts: status.restarts,
+                last_error: status.last_error,
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(workers))
+}
@@ -0,0 +1,85 @@
+use actix_web::{get, web, HttpResponse};
+use fantastic_giggle_sql::{AuditAction, AuditLog, PgPool};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, ApiError, Result};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub(crate) struct AuditQuery {
+    /// Returns the entries older than this ID.
+    before: Option<i64>,
+    limit: Option<i64>,
+    action: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AuditEntryBody {
+    id: i64,
+    actor: String,
+    action: String,
+    target_id: Option<i64>,
+    parameters: serde_json::Value,
+    /// `succeeded`, `dry_run` or `failed`.
+    result: &'static str,
+    error: Option<String>,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct AuditBody {
+    entries: Vec<AuditEntryBody>,
+    next: Option<i64>,
+}
+
+/// The actions taken on behalf of the user, newest first. Archived entries are not included.
+#[get("/api/audit")]
+pub(crate) async fn list_audit(
+    session: Session,
+    query: web::Query<AuditQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let action = match query.action.as_deref() {
+        Some(action) => Some(
+            AuditAction::parse(action)
+                .ok_or_else(|| ApiError::validation(format!("unknown action: {}", action)))?,
+        ),
+        None => None,
+    };
+
+    let entries =
+        AuditLog::find_page_by_user_id(pool.as_ref(), session.user_id, query.before, action, limit)
+            .await?;
+    let next = if entries.len() as i64 == limit {
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+    let entries = entries
+        .into_iter()
+        .map(|entry| AuditEntryBody {
+            id: entry.id,
+            actor: entry.actor,
+            action: entry.action,
+            target_id: entry.target_id,
+            parameters: serde_json::from_str(&entry.parameters).unwrap_or(serde_json::Value::Null),
+            result: match (&entry.error, entry.dry_run) {
+                (Some(_), _) => "failed",
+                (None, true) => "dry_run",
+                (None, false) => "succeeded",
+            },
+            error: entry.error,
+            created_at: entry.created_at.unix_timestamp(),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(AuditBody { entries, next }))
+}
@@ -1,15 +1,25 @@
-use crate::Result;
+use crate::{
+    session::{SESSION_COOKIE, SESSION_MAX_AGE},
+    ApiError, Result,
+};
 use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
     get,
     http::header::LOCATION,
+    post,
     web::{self},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
 use egg_mode::{
     auth::{access_token, authorize_url, request_token},
     KeyPair, Token,
 };
-use fantastic_giggle_sql::{PgPool, User};
+use fantastic_giggle_sql::{
+    Account, AuditAction, AuditActor, AuditLog, AuditRecord, ExternalAccount, Identity,
+    MastodonApp, OffsetDateTime, PgPool, Session,
+};
+use fantastic_giggle_worker::{instance_url, BlueskyClient, MastodonClient, BLUESKY, MASTODON};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
 
 #[get("/api/login")]
@@ -30,6 +40,7 @@ pub(crate) struct CallbackQuery {
 
 #[get("/api/callback")]
 pub(crate) async fn callback(
+    request: HttpRequest,
     query: web::Query<CallbackQuery>,
     pool: web::Data<PgPool>,
     consumer: web::Data<KeyPair>,
@@ -46,15 +57,252 @@ pub(crate) async fn callback(
         access,
     } = token
     {
-        User::save(
+        let user_id = user_id as i64;
+        let account_id = link_account(&request, pool.as_ref(), user_id).await?;
+        save_identity(
             pool.as_ref(),
-            User {
-                id: user_id as i64,
+            Identity {
+                id: user_id,
+                account_id,
+                provider: "twitter".to_string(),
                 access_key: access.key.to_string(),
                 access_secret: access.secret.to_string(),
+                token_expires_at: None,
             },
         )
         .await?;
+
+        response.cookie(start_session(pool.as_ref(), account_id, user_id).await?);
     }
     Ok(response.finish())
 }
+
+/// Holds `<state>:<instance>` between the Mastodon login and the callback.
+const MASTODON_COOKIE: &str = "mastodon_login";
+
+#[derive(Deserialize)]
+pub(crate) struct MastodonLoginQuery {
+    instance: String,
+}
+
+#[get("/api/mastodon/login")]
+pub(crate) async fn mastodon_login(
+    query: web::Query<MastodonLoginQuery>,
+    pool: web::Data<PgPool>,
+    client: web::Data<MastodonClient>,
+) -> Result<HttpResponse> {
+    let instance = instance_url(&query.instance)
+        .ok_or_else(|| ApiError::validation("instance must be a domain or a URL"))?;
+    // The OAuth application is registered on each server at the first login.
+    let app = match MastodonApp::find_by_instance(pool.as_ref(), &instance).await? {
+        Some(app) => app,
+        None => {
+            let app = client.register_app(&instance).await?;
+            MastodonApp::save(
+                pool.as_ref(),
+                &app.instance,
+                &app.client_id,
+                &app.client_secret,
+            )
+            .await?;
+            app
+        }
+    };
+
+    let state = random_string();
+    let auth_url = client.authorize_url(&app, &state)?;
+    Ok(HttpResponse::Found()
+        .append_header((LOCATION, auth_url))
+        .cookie(
+            Cookie::build(MASTODON_COOKIE, format!("{}:{}", state, instance))
+                .path("/api/mastodon")
+                .http_only(true)
+                .secure(true)
+                .same_site(SameSite::Lax)
+                .max_age(Duration::minutes(10))
+                .finish(),
+        )
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MastodonCallbackQuery {
+    code: String,
+    state: String,
+}
+
+#[get("/api/mastodon/callback")]
+pub(crate) async fn mastodon_callback(
+    request: HttpRequest,
+    query: web::Query<MastodonCallbackQuery>,
+    pool: web::Data<PgPool>,
+    client: web::Data<MastodonClient>,
+) -> Result<HttpResponse> {
+    let pending = request
+        .cookie(MASTODON_COOKIE)
+        .ok_or_else(|| ApiError::validation("login has expired"))?;
+    let instance = match pending.value().split_once(':') {
+        Some((state, instance)) if state == query.state => instance.to_string(),
+        _ => return Err(ApiError::validation("state does not match")),
+    };
+    let app = MastodonApp::find_by_instance(pool.as_ref(), &instance)
+        .await?
+        .ok_or_else(|| ApiError::validation("unknown instance"))?;
+
+    let access_token = client.exchange_code(&app, &query.code).await?;
+    let external_id = client
+        .verify_credentials(&instance, &Token::Bearer(access_token.clone()))
+        .await?;
+    let user_id = ExternalAccount::resolve(pool.as_ref(), MASTODON, &instance, &[external_id])
+        .await?
+        .pop()
+        .ok_or(ApiError::NotFound)?;
+    let account_id = link_account(&request, pool.as_ref(), user_id).await?;
+    save_identity(
+        pool.as_ref(),
+        Identity {
+            id: user_id,
+            account_id,
+            provider: MASTODON.to_string(),
+            access_key: access_token,
+            access_secret: String::new(),
+            token_expires_at: None,
+        },
+    )
+    .await?;
+
+    let mut expired = Cookie::build(MASTODON_COOKIE, "")
+        .path("/api/mastodon")
+        .finish();
+    expired.make_removal();
+    Ok(HttpResponse::Found()
+        .append_header((LOCATION, "/"))
+        .cookie(start_session(pool.as_ref(), account_id, user_id).await?)
+        .cookie(expired)
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct BlueskyLoginBody {
+    /// A handle or an email address.
+    identifier: String,
+    /// An app password.
+    password: String,
+}
+
+#[post("/api/bluesky/login")]
+pub(crate) async fn bluesky_login(
+    request: HttpRequest,
+    body: web::Json<BlueskyLoginBody>,
+    pool: web::Data<PgPool>,
+    client: web::Data<BlueskyClient>,
+) -> Result<HttpResponse> {
+    let session = client
+        .create_session(&body.identifier, &body.password)
+        .await?;
+    let user_id = ExternalAccount::resolve(pool.as_ref(), BLUESKY, "", &[session.did])
+        .await?
+        .pop()
+        .ok_or(ApiError::NotFound)?;
+    let account_id = link_account(&request, pool.as_ref(), user_id).await?;
+    save_identity(
+        pool.as_ref(),
+        Identity {
+            id: user_id,
+            account_id,
+            provider: BLUESKY.to_string(),
+            access_key: session.access_jwt,
+            access_secret: session.refresh_jwt,
+            token_expires_at: Some(session.expires_at),
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent()
+        .cookie(start_session(pool.as_ref(), account_id, user_id).await?)
+        .finish())
+}
+
+/// Returns the account to link the identity to: the account of the current session, so that
+/// logging in while logged in links another identity, the account the identity is already
+/// linked to, or a new account.
+pub(crate) async fn link_account(
+    request: &HttpRequest,
+    pool: &PgPool,
+    identity_id: i64,
+) -> Result<i64> {
+    if let Some(cookie) = request.cookie(SESSION_COOKIE) {
+        if let Some(session) = Session::find_by_id(pool, cookie.value()).await? {
+            return Ok(session.account_id);
+        }
+    }
+    match Identity::find_by_id(pool, identity_id).await? {
+        Some(identity) => Ok(identity.account_id),
+        None => Ok(Account::create(pool).await?),
+    }
+}
+
+/// Saves the identity, and records in the audit log when it is linked to an account for the first
+/// time or moved from another account.
+pub(crate) async fn save_identity(pool: &PgPool, identity: Identity) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let previous_account_id = Identity::find_by_id_for_update(&mut tx, identity.id)
+        .await?
+        .map(|identity| identity.account_id);
+    let record = AuditRecord::new(identity.id, AuditActor::User, AuditAction::IdentityLink)
+        .parameters(
+            serde_json::json!({
+                "account_id": identity.account_id,
+                "previous_account_id": previous_account_id,
+                "provider": identity.provider,
+            })
+            .to_string(),
+        );
+    let linked = previous_account_id != Some(identity.account_id);
+    Identity::save(&mut tx, identity).await?;
+    if linked {
+        AuditLog::append(&mut tx, &record).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Creates a session with the identity selected and returns its cookie.
+pub(crate) async fn start_session(
+    pool: &PgPool,
+    account_id: i64,
+    identity_id: i64,
+) -> Result<Cookie<'static>> {
+    Session::delete_expired(pool).await?;
+    let session_id = random_string();
+    let expires_at = OffsetDateTime::now_utc() + SESSION_MAX_AGE;
+    Session::save(pool, &session_id, account_id, identity_id, expires_at).await?;
+    let record = AuditRecord::new(identity_id, AuditActor::User, AuditAction::Login)
+        .parameters(serde_json::json!({ "account_id": account_id }).to_string());
+    AuditLog::append(pool, &record).await?;
+    Ok(Cookie::build(SESSION_COOKIE, session_id)
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(SESSION_MAX_AGE)
+        .finish())
+}
+
+#[post("/api/logout")]
+pub(crate) async fn logout(request: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpResponse> {
+    if let Some(cookie) = request.cookie(SESSION_COOKIE) {
+        Session::delete(pool.as_ref(), cookie.value()).await?;
+    }
+    let mut expired = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    expired.make_removal();
+    Ok(HttpResponse::NoContent().cookie(expired).finish())
+}
+
+fn random_string() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
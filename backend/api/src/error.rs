@@ -1,23 +1,139 @@
 use std::fmt::Display;
 
-pub type Result<T> = std::result::Result<T, ActixError>;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+
+pub type Result<T> = std::result::Result<T, ApiError>;
 
 #[derive(Debug)]
-pub struct ActixError {
-    cause: Box<dyn std::error::Error>,
+pub enum ApiError {
+    Twitter(egg_mode::error::Error),
+    Database(fantastic_giggle_sql::Error),
+    /// Errors of the worker crate which are neither of the above, whatever the provider.
+    Internal(anyhow::Error),
+    Validation(String),
+    Unauthorized,
+    Forbidden,
+    NotFound,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: String,
 }
 
-impl Display for ActixError {
+impl ApiError {
+    pub fn validation<S: Into<String>>(message: S) -> Self {
+        Self::Validation(message.into())
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::Twitter(egg_mode::error::Error::RateLimit(_)) => "twitter_rate_limited",
+            ApiError::Twitter(_) if self.status_code() == StatusCode::UNAUTHORIZED => {
+                "twitter_unauthorized"
+            }
+            ApiError::Twitter(_) => "twitter_error",
+            ApiError::Database(fantastic_giggle_sql::Error::RowNotFound) => "not_found",
+            ApiError::Database(_) => "database_error",
+            ApiError::Internal(_) => "internal_error",
+            ApiError::Validation(_) => "invalid_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::NotFound => "not_found",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::Twitter(egg_mode::error::Error::RateLimit(_)) => {
+                "Twitter rate limit exceeded. Please try again later.".to_string()
+            }
+            ApiError::Twitter(_) if self.status_code() == StatusCode::UNAUTHORIZED => {
+                "Twitter rejected the credentials. Please log in again.".to_string()
+            }
+            ApiError::Twitter(_) => "Failed to communicate with Twitter.".to_string(),
+            ApiError::Database(fantastic_giggle_sql::Error::RowNotFound) | ApiError::NotFound => {
+                "Not found.".to_string()
+            }
+            ApiError::Database(_) | ApiError::Internal(_) => "Internal server error.".to_string(),
+            ApiError::Validation(message) => message.clone(),
+            ApiError::Unauthorized => "Login required.".to_string(),
+            ApiError::Forbidden => "Permission denied.".to_string(),
+        }
+    }
+}
+
+impl Display for ApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.cause)
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        use egg_mode::error::Error;
+        match self {
+            ApiError::Twitter(Error::RateLimit(_)) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Twitter(Error::BadStatus(status)) if status.as_u16() == 401 => {
+                StatusCode::UNAUTHORIZED
+            }
+            // 89: Invalid or expired token.
+            ApiError::Twitter(Error::TwitterError(_, errors))
+                if errors.errors.iter().any(|e| e.code == 89) =>
+            {
+                StatusCode::UNAUTHORIZED
+            }
+            ApiError::Twitter(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Database(fantastic_giggle_sql::Error::RowNotFound) => StatusCode::NOT_FOUND,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        match self {
+            ApiError::Twitter(e) => log::error!("twitter error: {:?}", e),
+            ApiError::Database(e) => log::error!("database error: {:?}", e),
+            ApiError::Internal(e) => log::error!("internal error: {:?}", e),
+            _ => log::debug!("{:?}", self),
+        }
+        HttpResponse::build(status).json(ErrorBody {
+            code: self.code(),
+            message: self.message(),
+        })
     }
 }
-impl actix_web::error::ResponseError for ActixError {}
 
-impl<E: 'static + std::error::Error> From<E> for ActixError {
-    fn from(error: E) -> Self {
-        Self {
-            cause: Box::new(error),
+impl From<egg_mode::error::Error> for ApiError {
+    fn from(error: egg_mode::error::Error) -> Self {
+        Self::Twitter(error)
+    }
+}
+
+/// For the errors of the worker crate, which wraps the ones above.
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<egg_mode::error::Error>() {
+            Ok(error) => return Self::Twitter(error),
+            Err(error) => error,
+        };
+        match error.downcast::<fantastic_giggle_sql::Error>() {
+            Ok(error) => Self::Database(error),
+            Err(error) => Self::Internal(error),
         }
     }
 }
+
+impl From<fantastic_giggle_sql::Error> for ApiError {
+    fn from(error: fantastic_giggle_sql::Error) -> Self {
+        Self::Database(error)
+    }
+}
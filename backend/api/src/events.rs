@@ -0,0 +1,41 @@
+use std::{convert::Infallible, time::Duration};
+
+use actix_web::{get, http::header::CACHE_CONTROL, web, HttpResponse};
+use fantastic_giggle_worker::EventBus;
+use futures_util::stream::unfold;
+use tokio::{sync::broadcast::error::RecvError, time::timeout};
+
+use crate::{session::Session, Result};
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[get("/api/events")]
+pub(crate) async fn events(session: Session, events: web::Data<EventBus>) -> Result<HttpResponse> {
+    let user_id = session.user_id;
+    let receiver = events.subscribe();
+    let stream = unfold(receiver, move |mut receiver| async move {
+        loop {
+            let message = match timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Err(_) => ": keep-alive\n\n".to_string(),
+                Ok(Ok(event)) if event.user_id() != user_id => continue,
+                Ok(Ok(event)) => match serde_json::to_string(&event) {
+                    Ok(data) => format!("data: {}\n\n", data),
+                    Err(e) => {
+                        log::error!("failed to serialize {:?}: {:?}", event, e);
+                        continue;
+                    }
+                },
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    log::warn!("event stream lagged: {} events skipped", skipped);
+                    continue;
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((Ok::<_, Infallible>(web::Bytes::from(message)), receiver));
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header((CACHE_CONTROL, "no-cache"))
+        .streaming(stream))
+}
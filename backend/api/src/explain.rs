@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use actix_web::{get, web, HttpResponse};
+use fantastic_giggle_sql::{
+    FollowAction, FollowBackRequest, OffsetDateTime, PgPool, Profile, Relationship,
+    RelationshipSync, UserSettings,
+};
+use fantastic_giggle_worker::{load_targets, ActivityTracker, Explanation, Fact, UserActivity};
+use serde::Serialize;
+
+use crate::{rules::find_rules, session::Session, ApiError, Result};
+
+const ATTEMPT_LIMIT: i64 = 20;
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize)]
+struct RelationshipBody {
+    kind: String,
+    first_seen_at: i64,
+    last_seen_at: i64,
+}
+
+#[derive(Serialize)]
+struct SyncBody {
+    kind: String,
+    finished_at: i64,
+}
+
+#[derive(Serialize)]
+struct ProfileBody {
+    screen_name: String,
+    name: String,
+    followers_count: i32,
+    friends_count: i32,
+    default_profile_image: bool,
+    protected: bool,
+    account_created_at: i64,
+    looked_up_at: i64,
+}
+
+#[derive(Serialize)]
+struct AttemptBody {
+    created_at: i64,
+    dry_run: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CapBody {
+    follow_back_enabled: bool,
+    dry_run: bool,
+    daily_follow_cap: i32,
+    followed_today: i64,
+    reached: bool,
+}
+
+#[derive(Serialize)]
+struct ExplainBody {
+    target_id: i64,
+    relationships: Vec<RelationshipBody>,
+    whitelisted: bool,
+    blocklisted: bool,
+    blocking: bool,
+    muting: bool,
+    /// Reported by the webhook and waiting for the next follow-back round.
+    follow_back_requested: bool,
+    last_synced: Vec<SyncBody>,
+    /// The last lookup of the target, or `None` if it has never been looked up.
+    profile: Option<ProfileBody>,
+    follow_attempts: Vec<AttemptBody>,
+    cap: CapBody,
+    /// The rate limits and the follow-back queue of the running workers.
+    activity: UserActivity,
+    /// Includes the candidate filter of the settings unless the user saved their own rules.
+    rules: Explanation,
+}
+
+/// Resolves `@screen_name`, or a user ID or a screen name, among the accounts the user has a
+/// relationship with. Screen names can be all digits, so a number is taken as an ID only when it
+/// is the ID of such an account, or when no screen name matches.
+async fn resolve_target(pool: &PgPool, user_id: i64, target: &str) -> Result<i64> {
+    let target = target.trim();
+    let (screen_name, id) = match target.strip_prefix('@') {
+        Some(screen_name) => (screen_name, None),
+        None => (target, target.parse::<i64>().ok()),
+    };
+    if let Some(id) = id {
+        if !Relationship::find_by_target_ids(pool, user_id, &[id])
+            .await?
+            .is_empty()
+        {
+            return Ok(id);
+        }
+    }
+    let mut profiles = Profile::find_by_screen_name(pool, user_id, screen_name).await?;
+    match (profiles.pop(), id) {
+        (Some(_), _) if !profiles.is_empty() => Err(ApiError::validation(
+            "more than one account has had this screen name; use the user ID instead",
+        )),
+        (Some(profile), _) => Ok(profile.id),
+        (None, Some(id)) => Ok(id),
+        (None, None) => Err(ApiError::NotFound),
+    }
+}
+
+/// Explains why the target was or wasn't followed back, from what the workers have recorded.
+#[get("/api/explain/{target}")]
+pub(crate) async fn explain(
+    session: Session,
+    target: web::Path<String>,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<ActivityTracker>,
+) -> Result<HttpResponse> {
+    let pool = pool.as_ref();
+    let user_id = session.user_id;
+    let target_id = resolve_target(pool, user_id, &target).await?;
+    let now = OffsetDateTime::now_utc();
+
+    let target = load_targets(pool, user_id, &[target_id])
+        .await?
+        .remove(&target_id)
+        .unwrap_or_default();
+    let relationships = Relationship::find_by_target_ids(pool, user_id, &[target_id])
+        .await?
+        .into_iter()
+        .map(|r| RelationshipBody {
+            kind: r.kind,
+            first_seen_at: r.created_at.unix_timestamp(),
+            last_seen_at: r.updated_at.unix_timestamp(),
+        })
+        .collect();
+    let follow_back_requested = FollowBackRequest::is_pending(pool, user_id, target_id).await?;
+    let last_synced = RelationshipSync::find_by_source_id(pool, user_id)
+        .await?
+        .into_iter()
+        .map(|s| SyncBody {
+            kind: s.kind,
+            finished_at: s.finished_at.unix_timestamp(),
+        })
+        .collect();
+    let follow_attempts = FollowAction::find_by_target_id(pool, user_id, target_id, ATTEMPT_LIMIT)
+        .await?
+        .into_iter()
+        .map(|a| AttemptBody {
+            created_at: a.created_at.unix_timestamp(),
+            dry_run: a.dry_run,
+            error: a.error,
+        })
+        .collect();
+
+    let settings = UserSettings::find_or_default(pool, user_id).await?;
+    let followed_today = FollowAction::count_since(pool, user_id, now - DAY).await?;
+    let cap = CapBody {
+        follow_back_enabled: settings.follow_back_enabled,
+        dry_run: settings.dry_run,
+        daily_follow_cap: settings.daily_follow_cap,
+        followed_today,
+        reached: followed_today >= settings.daily_follow_cap as i64,
+    };
+    let (rules, _) = find_rules(pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(ExplainBody {
+        target_id,
+        relationships,
+        whitelisted: target.has(Fact::Whitelisted),
+        blocklisted: target.has(Fact::Blocklisted),
+        blocking: target.has(Fact::Blocking),
+        muting: target.has(Fact::Muting),
+        follow_back_requested,
+        last_synced,
+        profile: target.profile.as_ref().map(|p| ProfileBody {
+            screen_name: p.screen_name.clone(),
+            name: p.name.clone(),
+            followers_count: p.followers_count,
+            friends_count: p.friends_count,
+            default_profile_image: p.default_profile_image,
+            protected: p.protected,
+            account_created_at: p.account_created_at.unix_timestamp(),
+            looked_up_at: p.updated_at.unix_timestamp(),
+        }),
+        follow_attempts,
+        cap,
+        activity: tracker.get(user_id),
+        rules: rules.explain(&target, now),
+    }))
+}
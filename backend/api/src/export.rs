@@ -0,0 +1,328 @@
+use std::str::FromStr;
+
+use actix_web::{
+    get,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    web, HttpResponse,
+};
+use fantastic_giggle_sql::{
+    PgPool, ProfiledHistory, ProfiledRelationship, Relationship, RelationshipHistory,
+    RelationshipSet,
+};
+use futures_util::{
+    future::ready,
+    stream::{once, Stream, StreamExt, TryStreamExt},
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{session::Session, ApiError, Result};
+
+const RELATIONSHIP_COLUMNS: &[&str] = &[
+    "id",
+    "screen_name",
+    "name",
+    "followers_count",
+    "friends_count",
+    "first_seen_at",
+    "last_seen_at",
+];
+const HISTORY_COLUMNS: &[&str] = &[
+    "id",
+    "screen_name",
+    "name",
+    "followers_count",
+    "friends_count",
+    "kind",
+    "event",
+    "created_at",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+    Graphml,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Graphml => "application/graphml+xml",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Graphml => "graphml",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ApiError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "graphml" => Ok(ExportFormat::Graphml),
+            _ => Err(ApiError::validation(format!("unknown format: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportSet {
+    Relationships(RelationshipSet),
+    History,
+}
+
+impl ExportSet {
+    fn name(self) -> &'static str {
+        match self {
+            ExportSet::Relationships(RelationshipSet::Followers) => "followers",
+            ExportSet::Relationships(RelationshipSet::Friends) => "friends",
+            ExportSet::Relationships(RelationshipSet::Mutuals) => "mutuals",
+            ExportSet::Relationships(RelationshipSet::Fans) => "fans",
+            ExportSet::Relationships(RelationshipSet::NonFollowers) => "non-followers",
+            ExportSet::History => "history",
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            ExportSet::Relationships(_) => RELATIONSHIP_COLUMNS,
+            ExportSet::History => HISTORY_COLUMNS,
+        }
+    }
+}
+
+impl FromStr for ExportSet {
+    type Err = ApiError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "followers" => Ok(ExportSet::Relationships(RelationshipSet::Followers)),
+            "friends" => Ok(ExportSet::Relationships(RelationshipSet::Friends)),
+            "mutuals" => Ok(ExportSet::Relationships(RelationshipSet::Mutuals)),
+            "fans" => Ok(ExportSet::Relationships(RelationshipSet::Fans)),
+            "non-followers" => Ok(ExportSet::Relationships(RelationshipSet::NonFollowers)),
+            "history" => Ok(ExportSet::History),
+            _ => Err(ApiError::validation(format!("unknown export: {}", s))),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Encoder {
+    format: ExportFormat,
+    set: ExportSet,
+    source_id: i64,
+}
+
+impl Encoder {
+    fn header(&self) -> String {
+        let columns = self.set.columns();
+        match self.format {
+            ExportFormat::Csv => csv_line(columns.iter().map(|c| c.to_string())),
+            ExportFormat::Jsonl => String::new(),
+            ExportFormat::Graphml => {
+                let mut header = String::from(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                     <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+                );
+                for column in columns.iter().skip(1) {
+                    let attr_type = match *column {
+                        "screen_name" | "name" => "string",
+                        _ => "long",
+                    };
+                    header.push_str(&format!(
+                        "  <key id=\"{0}\" for=\"node\" attr.name=\"{0}\" attr.type=\"{1}\"/>\n",
+                        column, attr_type
+                    ));
+                }
+                header.push_str("  <graph edgedefault=\"directed\">\n");
+                header.push_str(&format!("    <node id=\"{}\"/>\n", self.source_id));
+                header
+            }
+        }
+    }
+
+    fn row(&self, values: Vec<Value>) -> String {
+        let columns = self.set.columns();
+        match self.format {
+            ExportFormat::Csv => csv_line(values.iter().map(|value| match value {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            })),
+            ExportFormat::Jsonl => {
+                let object = columns
+                    .iter()
+                    .map(|c| c.to_string())
+                    .zip(values)
+                    .collect::<Map<_, _>>();
+                format!("{}\n", Value::Object(object))
+            }
+            ExportFormat::Graphml => {
+                let id = &values[0];
+                let mut node = format!("    <node id=\"{}\">", id);
+                for (column, value) in columns.iter().zip(&values).skip(1) {
+                    let value = match value {
+                        Value::Null => continue,
+                        Value::String(s) => escape_xml(s),
+                        value => value.to_string(),
+                    };
+                    node.push_str(&format!("<data key=\"{}\">{}</data>", column, value));
+                }
+                node.push_str("</node>\n");
+
+                let source_id = self.source_id;
+                let (incoming, outgoing) = match self.set {
+                    ExportSet::Relationships(RelationshipSet::Followers)
+                    | ExportSet::Relationships(RelationshipSet::Fans) => (true, false),
+                    ExportSet::Relationships(RelationshipSet::Friends)
+                    | ExportSet::Relationships(RelationshipSet::NonFollowers) => (false, true),
+                    ExportSet::Relationships(RelationshipSet::Mutuals) => (true, true),
+                    ExportSet::History => (false, false),
+                };
+                if incoming {
+                    node.push_str(&format!(
+                        "    <edge source=\"{}\" target=\"{}\"/>\n",
+                        id, source_id
+                    ));
+                }
+                if outgoing {
+                    node.push_str(&format!(
+                        "    <edge source=\"{}\" target=\"{}\"/>\n",
+                        source_id, id
+                    ));
+                }
+                node
+            }
+        }
+    }
+
+    fn footer(&self) -> String {
+        match self.format {
+            ExportFormat::Graphml => "  </graph>\n</graphml>\n".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn csv_line<I: Iterator<Item = String>>(values: I) -> String {
+    let values = values
+        .map(|value| {
+            if value.contains(&[',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("{}\n", values.join(","))
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn relationship_values(r: ProfiledRelationship) -> Vec<Value> {
+    vec![
+        json!(r.target_id),
+        json!(r.screen_name),
+        json!(r.name),
+        json!(r.followers_count),
+        json!(r.friends_count),
+        r.created_at.unix_timestamp().into(),
+        r.updated_at.unix_timestamp().into(),
+    ]
+}
+
+fn history_values(h: ProfiledHistory) -> Vec<Value> {
+    vec![
+        json!(h.target_id),
+        json!(h.screen_name),
+        json!(h.name),
+        json!(h.followers_count),
+        json!(h.friends_count),
+        json!(h.kind),
+        json!(h.event),
+        h.created_at.unix_timestamp().into(),
+    ]
+}
+
+/// Streams the encoded export of a user's relationships straight from the database.
+pub fn export(
+    pool: PgPool,
+    source_id: i64,
+    set: ExportSet,
+    format: ExportFormat,
+) -> Result<impl Stream<Item = Result<web::Bytes>>> {
+    if set == ExportSet::History && format == ExportFormat::Graphml {
+        return Err(ApiError::validation(
+            "history can not be exported as graphml",
+        ));
+    }
+
+    let encoder = Encoder {
+        format,
+        set,
+        source_id,
+    };
+    let rows = match set {
+        ExportSet::Relationships(set) => Relationship::stream_by_source_id(pool, source_id, set)
+            .map_ok(relationship_values)
+            .boxed(),
+        ExportSet::History => RelationshipHistory::stream_by_source_id(pool, source_id)
+            .map_ok(history_values)
+            .boxed(),
+    };
+
+    let header = once(ready(Ok(encoder.header())));
+    let footer = once(ready(Ok(encoder.footer())));
+    let row_encoder = encoder.clone();
+    let rows = rows.map(move |row| match row {
+        Ok(values) => Ok(row_encoder.row(values)),
+        Err(e) => Err(ApiError::from(e)),
+    });
+    Ok(header.chain(rows).chain(footer).map_ok(web::Bytes::from))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ExportQuery {
+    format: ExportFormat,
+}
+
+#[get("/api/export/{set}")]
+pub(crate) async fn export_relationships(
+    session: Session,
+    set: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let set = set.parse::<ExportSet>()?;
+    let format = query.format;
+    let stream = export(pool.as_ref().clone(), session.user_id, set, format)?;
+    let stream = stream.inspect_err(|e| log::error!("export failed: {:?}", e));
+    Ok(HttpResponse::Ok()
+        .append_header((CONTENT_TYPE, format.content_type()))
+        .append_header((
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                set.name(),
+                format.extension()
+            ),
+        ))
+        .streaming(stream))
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use actix_web::{delete, get, post, web, HttpResponse};
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, Identity, PgPool, Profile,
+};
+use serde::Serialize;
+
+use crate::{session::Session, ApiError, Result};
+
+#[derive(Serialize)]
+struct IdentityBody {
+    id: i64,
+    provider: String,
+    /// Known once the profile has been looked up.
+    screen_name: Option<String>,
+    selected: bool,
+}
+
+#[get("/api/identities")]
+pub(crate) async fn list_identities(
+    session: Session,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let identities = Identity::find_by_account_id(pool.as_ref(), session.account_id).await?;
+    let ids = identities.iter().map(|i| i.id).collect::<Vec<_>>();
+    let screen_names = Profile::find_by_ids(pool.as_ref(), &ids)
+        .await?
+        .into_iter()
+        .map(|p| (p.id, p.screen_name))
+        .collect::<BTreeMap<_, _>>();
+    let body = identities
+        .into_iter()
+        .map(|identity| IdentityBody {
+            id: identity.id,
+            screen_name: screen_names.get(&identity.id).cloned(),
+            selected: identity.id == session.user_id,
+            provider: identity.provider,
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Switches the identity whose data and settings the session works on.
+#[post("/api/identities/{id}/select")]
+pub(crate) async fn select_identity(
+    session: Session,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let id = id.into_inner();
+    let identity = Identity::find_by_id(pool.as_ref(), id).await?;
+    if identity.is_none_or(|identity| identity.account_id != session.account_id) {
+        return Err(ApiError::NotFound);
+    }
+    fantastic_giggle_sql::Session::select_identity(pool.as_ref(), &session.id, id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/api/identities/{id}")]
+pub(crate) async fn unlink_identity(
+    session: Session,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let id = id.into_inner();
+    if id == session.user_id {
+        return Err(ApiError::validation(
+            "the selected identity cannot be unlinked",
+        ));
+    }
+    let mut tx = pool.begin().await?;
+    if !Identity::delete(&mut tx, session.account_id, id).await? {
+        return Err(ApiError::NotFound);
+    }
+    let record = AuditRecord::new(
+        session.user_id,
+        AuditActor::User,
+        AuditAction::IdentityUnlink,
+    )
+    .target(id)
+    .parameters(serde_json::json!({ "account_id": session.account_id }).to_string());
+    AuditLog::append(&mut tx, &record).await?;
+    tx.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
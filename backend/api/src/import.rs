@@ -0,0 +1,221 @@
+use std::collections::BTreeSet;
+
+use actix_web::{post, web, HttpResponse};
+use egg_mode::{list::ListID, KeyPair};
+use fantastic_giggle_sql::PgPool;
+use fantastic_giggle_worker::TwitterV2Client;
+use futures_util::stream::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lookup::resolve_screen_names, session::Session, user_list::UserList, ApiError, Result,
+};
+
+/// Twitter lists hold at most 5000 members, so larger imports are most likely a mistake.
+const MAX_IMPORT: usize = 5000;
+const CSV_HEADERS: &[&str] = &["id", "user_id", "screen_name", "username"];
+
+#[derive(Deserialize)]
+pub(crate) struct ImportQuery {
+    /// Only the diff is returned unless this is set.
+    #[serde(default)]
+    commit: bool,
+}
+
+#[derive(Serialize)]
+struct ImportResponse {
+    new_user_ids: Vec<i64>,
+    existing_user_ids: Vec<i64>,
+    unresolved: Vec<String>,
+    imported: Option<u64>,
+}
+
+/// Splits the first column of each row into user IDs and screen names.
+fn parse_csv(body: &str) -> (Vec<i64>, Vec<String>) {
+    let mut user_ids = vec![];
+    let mut screen_names = vec![];
+    let mut rows = body
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(first_cell)
+        .filter(|cell| !cell.is_empty())
+        .peekable();
+    if rows
+        .peek()
+        .is_some_and(|cell| CSV_HEADERS.contains(&cell.to_lowercase().as_str()))
+    {
+        rows.next();
+    }
+    for cell in rows {
+        match cell.parse::<i64>() {
+            Ok(user_id) => user_ids.push(user_id),
+            Err(_) => screen_names.push(cell),
+        }
+    }
+    (user_ids, screen_names)
+}
+
+fn first_cell(line: &str) -> String {
+    let line = line.trim();
+    let cell = match line.strip_prefix('"') {
+        Some(quoted) => {
+            let mut cell = String::new();
+            let mut chars = quoted.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    '"' => break,
+                    c => cell.push(c),
+                }
+            }
+            cell
+        }
+        None => line.split(',').next().unwrap_or_default().to_string(),
+    };
+    cell.trim().to_string()
+}
+
+/// `via` is recorded in the audit log with the imported users.
+async fn import(
+    list: UserList,
+    via: &str,
+    session: &Session,
+    pool: &PgPool,
+    user_ids: Vec<i64>,
+    unresolved: Vec<String>,
+    commit: bool,
+) -> Result<HttpResponse> {
+    let user_ids = user_ids.into_iter().collect::<BTreeSet<_>>();
+    if user_ids.len() > MAX_IMPORT {
+        return Err(ApiError::validation(format!(
+            "at most {} users can be imported at once",
+            MAX_IMPORT
+        )));
+    }
+
+    let user_ids = user_ids.into_iter().collect::<Vec<_>>();
+    let existing_user_ids = list
+        .find_existing(pool, session.user_id, &user_ids)
+        .await?
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    let new_user_ids = user_ids
+        .into_iter()
+        .filter(|id| !existing_user_ids.contains(id))
+        .collect::<Vec<_>>();
+    let imported = if commit {
+        Some(
+            list.save_all(pool, session.user_id, &new_user_ids, via)
+                .await?,
+        )
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(ImportResponse {
+        new_user_ids,
+        existing_user_ids: existing_user_ids.into_iter().collect(),
+        unresolved,
+        imported,
+    }))
+}
+
+#[post("/api/import/{list}/csv")]
+pub(crate) async fn import_csv(
+    session: Session,
+    list: web::Path<UserList>,
+    query: web::Query<ImportQuery>,
+    body: String,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    let (mut user_ids, screen_names) = parse_csv(&body);
+    if user_ids.len() + screen_names.len() > MAX_IMPORT {
+        return Err(ApiError::validation(format!(
+            "at most {} users can be imported at once",
+            MAX_IMPORT
+        )));
+    }
+
+    let mut unresolved = vec![];
+    if !screen_names.is_empty() {
+        let token = session
+            .token(
+                pool.as_ref(),
+                consumer.as_ref(),
+                twitter_v2.as_ref().map(|client| client.as_ref()),
+            )
+            .await?;
+        let (resolved, names) = resolve_screen_names(&token, &screen_names).await?;
+        user_ids.extend(resolved);
+        unresolved = names;
+    }
+    import(
+        list.into_inner(),
+        "csv",
+        &session,
+        pool.as_ref(),
+        user_ids,
+        unresolved,
+        query.commit,
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TwitterList {
+    list_id: Option<u64>,
+    owner_screen_name: Option<String>,
+    slug: Option<String>,
+}
+
+impl TwitterList {
+    fn list_id(self) -> Result<ListID> {
+        match (self.list_id, self.owner_screen_name, self.slug) {
+            (Some(list_id), None, None) => Ok(ListID::from_id(list_id)),
+            (None, Some(owner), Some(slug)) => Ok(ListID::from_slug(owner, slug)),
+            _ => Err(ApiError::validation(
+                "either list_id or owner_screen_name and slug must be given",
+            )),
+        }
+    }
+}
+
+#[post("/api/import/{list}/twitter-list")]
+pub(crate) async fn import_twitter_list(
+    session: Session,
+    list: web::Path<UserList>,
+    query: web::Query<ImportQuery>,
+    twitter_list: web::Json<TwitterList>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    let list_id = twitter_list.into_inner().list_id()?;
+    let token = session
+        .token(
+            pool.as_ref(),
+            consumer.as_ref(),
+            twitter_v2.as_ref().map(|client| client.as_ref()),
+        )
+        .await?;
+    let user_ids = egg_mode::list::members(list_id, &token)
+        .with_page_size(5000)
+        .take(MAX_IMPORT)
+        .map_ok(|user| user.id as i64)
+        .try_collect::<Vec<_>>()
+        .await?;
+    import(
+        list.into_inner(),
+        "twitter_list",
+        &session,
+        pool.as_ref(),
+        user_ids,
+        vec![],
+        query.commit,
+    )
+    .await
+}
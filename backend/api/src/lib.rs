@@ -1,10 +1,84 @@
+mod admin;
+mod audit;
 mod auth;
+mod events;
+mod explain;
+pub mod export;
+mod identity;
+mod import;
+mod lookup;
+mod moderation;
+mod oauth2;
+mod relationship;
+mod rules;
+mod session;
+mod settings;
+mod stats;
+mod user_list;
+mod webhook;
+mod welcome_message;
 
 mod error;
+pub use error::ApiError;
 pub(crate) use error::Result;
 
-use actix_web::web::ServiceConfig;
+use actix_web::web::{self, ServiceConfig};
 
 pub fn config_services(cfg: &mut ServiceConfig) {
-    cfg.service(auth::login).service(auth::callback);
+    cfg.app_data(
+        web::QueryConfig::default()
+            .error_handler(|e, _| ApiError::validation(e.to_string()).into()),
+    )
+    .app_data(
+        web::JsonConfig::default().error_handler(|e, _| ApiError::validation(e.to_string()).into()),
+    )
+    .app_data(
+        web::PathConfig::default().error_handler(|e, _| ApiError::validation(e.to_string()).into()),
+    )
+    .service(auth::login)
+    .service(auth::callback)
+    .service(auth::mastodon_login)
+    .service(auth::mastodon_callback)
+    .service(auth::bluesky_login)
+    .service(auth::logout)
+    .service(identity::list_identities)
+    .service(identity::select_identity)
+    .service(identity::unlink_identity)
+    .service(oauth2::login)
+    .service(oauth2::callback)
+    .service(user_list::list_whitelist)
+    .service(user_list::add_whitelist)
+    .service(user_list::remove_whitelist)
+    .service(user_list::list_blocklist)
+    .service(user_list::add_blocklist)
+    .service(user_list::remove_blocklist)
+    .service(import::import_csv)
+    .service(import::import_twitter_list)
+    .service(relationship::followers)
+    .service(relationship::friends)
+    .service(relationship::mutuals)
+    .service(relationship::fans)
+    .service(relationship::non_followers)
+    .service(events::events)
+    .service(settings::get_settings)
+    .service(settings::put_settings)
+    .service(stats::stats)
+    .service(export::export_relationships)
+    .service(webhook::crc)
+    .service(webhook::events)
+    .service(welcome_message::preview)
+    .service(moderation::list_moderation)
+    .service(moderation::undo)
+    .service(rules::get_rules)
+    .service(rules::put_rules)
+    .service(rules::reset_rules)
+    .service(rules::explain_rules)
+    .service(explain::explain)
+    .service(audit::list_audit)
+    .service(admin::list_users)
+    .service(admin::pause_user)
+    .service(admin::resume_user)
+    .service(admin::resync_user)
+    .service(admin::revoke_user)
+    .service(admin::list_workers);
 }
@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+
+use egg_mode::{user::lookup, Token};
+
+use crate::Result;
+
+const USER_LOOKUP_LIMIT: usize = 100;
+
+/// Resolves screen names to user IDs, returning the IDs and the names which could not be found.
+pub(crate) async fn resolve_screen_names(
+    token: &Token,
+    screen_names: &[String],
+) -> Result<(Vec<i64>, Vec<String>)> {
+    let mut remaining = screen_names
+        .iter()
+        .map(|name| {
+            let name = name.trim().trim_start_matches('@');
+            (name.to_lowercase(), name.to_string())
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect::<BTreeMap<_, _>>();
+
+    let mut user_ids = vec![];
+    let names = remaining.values().cloned().collect::<Vec<_>>();
+    for chunk in names.chunks(USER_LOOKUP_LIMIT) {
+        let users = lookup(chunk.to_vec(), token).await?;
+        for user in users.iter() {
+            if remaining.remove(&user.screen_name.to_lowercase()).is_some() {
+                user_ids.push(user.id as i64);
+            }
+        }
+    }
+    Ok((user_ids, remaining.into_values().collect()))
+}
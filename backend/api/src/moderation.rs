@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use actix_web::{get, post, web, HttpResponse};
+use egg_mode::KeyPair;
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, Identity, ModerationAction, ModerationKind,
+    PgPool, Profile,
+};
+use fantastic_giggle_worker::{undo_moderation, Credentials, TwitterClient, TwitterV2Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, ApiError, Result};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub(crate) struct ModerationQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ModerationBody {
+    id: i64,
+    target_id: i64,
+    screen_name: Option<String>,
+    action: String,
+    reason: String,
+    dry_run: bool,
+    error: Option<String>,
+    pending_unblock: bool,
+    created_at: i64,
+    undone_at: Option<i64>,
+}
+
+/// The audit trail of the followers muted or blocked as spam, newest first.
+#[get("/api/moderation")]
+pub(crate) async fn list_moderation(
+    session: Session,
+    query: web::Query<ModerationQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let actions =
+        ModerationAction::find_by_source_id(pool.as_ref(), session.user_id, limit).await?;
+    let ids = actions.iter().map(|a| a.target_id).collect::<Vec<_>>();
+    let screen_names = Profile::find_by_ids(pool.as_ref(), &ids)
+        .await?
+        .into_iter()
+        .map(|p| (p.id, p.screen_name))
+        .collect::<BTreeMap<_, _>>();
+    let body = actions
+        .into_iter()
+        .map(|a| ModerationBody {
+            id: a.id,
+            target_id: a.target_id,
+            screen_name: screen_names.get(&a.target_id).cloned(),
+            action: a.action,
+            reason: a.reason,
+            dry_run: a.dry_run,
+            error: a.error,
+            pending_unblock: a.pending_unblock,
+            created_at: a.created_at.unix_timestamp(),
+            undone_at: a.undone_at.map(|at| at.unix_timestamp()),
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Unmutes or unblocks the follower. Dry runs and actions which failed before anything went out
+/// are only marked as undone.
+#[post("/api/moderation/{id}/undo")]
+pub(crate) async fn undo(
+    session: Session,
+    id: web::Path<i64>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    let id = id.into_inner();
+    let pool = pool.as_ref();
+    let action = ModerationAction::find_by_id(pool, session.user_id, id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    if action.undone_at.is_some() {
+        return Err(ApiError::validation("the action has already been undone"));
+    }
+    let kind = ModerationKind::parse(&action.action).ok_or(ApiError::NotFound)?;
+
+    // A soft block still blocks the follower until its unblock goes out.
+    let in_place = !action.dry_run
+        && match kind {
+            ModerationKind::SoftBlock => action.pending_unblock,
+            _ => action.error.is_none(),
+        };
+    if kind == ModerationKind::SoftBlock && !action.dry_run && action.error.is_none() && !in_place {
+        return Err(ApiError::validation(
+            "a soft block cannot be undone, since the follower has to follow again",
+        ));
+    }
+    let mut record = None;
+    if in_place {
+        let identity = Identity::find_by_id(pool, session.user_id)
+            .await?
+            .ok_or(ApiError::Unauthorized)?;
+        match twitter_v2 {
+            Some(client) => {
+                let client = client.as_ref().clone();
+                let token = Credentials::OAuth2(client.clone())
+                    .token(pool, identity)
+                    .await?;
+                undo_moderation(&client, pool, &token, &action).await?;
+            }
+            None => {
+                let token = Credentials::OAuth1(consumer.as_ref().clone())
+                    .token(pool, identity)
+                    .await?;
+                let client = TwitterClient::default();
+                undo_moderation(&client, pool, &token, &action).await?;
+            }
+        }
+        let undo = match kind {
+            ModerationKind::Mute => AuditAction::Unmute,
+            _ => AuditAction::Unblock,
+        };
+        record = Some(
+            AuditRecord::new(session.user_id, AuditActor::User, undo)
+                .target(action.target_id)
+                .parameters(serde_json::json!({ "moderation_action_id": id }).to_string()),
+        );
+    }
+
+    let mut tx = pool.begin().await?;
+    ModerationAction::mark_undone(&mut tx, session.user_id, id).await?;
+    if let Some(record) = record {
+        AuditLog::append(&mut tx, &record).await?;
+    }
+    tx.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
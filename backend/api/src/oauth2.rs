@@ -0,0 +1,87 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    get,
+    http::header::LOCATION,
+    web, HttpRequest, HttpResponse,
+};
+use egg_mode::Token;
+use fantastic_giggle_sql::{Identity, PgPool};
+use fantastic_giggle_worker::{pkce_challenge, pkce_verifier, TwitterV2Client};
+use serde::Deserialize;
+
+use crate::{
+    auth::{link_account, save_identity, start_session},
+    ApiError, Result,
+};
+
+/// Holds `<state>:<code verifier>` between the login and the callback.
+const PKCE_COOKIE: &str = "oauth2_pkce";
+
+#[get("/api/oauth2/login")]
+pub(crate) async fn login(client: Option<web::Data<TwitterV2Client>>) -> Result<HttpResponse> {
+    let client = client.ok_or(ApiError::NotFound)?;
+    let state = pkce_verifier();
+    let verifier = pkce_verifier();
+    let auth_url = client.authorize_url(&state, &pkce_challenge(&verifier))?;
+    Ok(HttpResponse::Found()
+        .append_header((LOCATION, auth_url))
+        .cookie(
+            Cookie::build(PKCE_COOKIE, format!("{}:{}", state, verifier))
+                .path("/api/oauth2")
+                .http_only(true)
+                .secure(true)
+                .same_site(SameSite::Lax)
+                .max_age(Duration::minutes(10))
+                .finish(),
+        )
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CallbackQuery {
+    code: String,
+    state: String,
+}
+
+#[get("/api/oauth2/callback")]
+pub(crate) async fn callback(
+    request: HttpRequest,
+    query: web::Query<CallbackQuery>,
+    pool: web::Data<PgPool>,
+    client: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    let client = client.ok_or(ApiError::NotFound)?;
+    let pkce = request
+        .cookie(PKCE_COOKIE)
+        .ok_or_else(|| ApiError::validation("login has expired"))?;
+    let verifier = match pkce.value().split_once(':') {
+        Some((state, verifier)) if state == query.state => verifier.to_string(),
+        _ => return Err(ApiError::validation("state does not match")),
+    };
+
+    let token = client.exchange_code(&query.code, &verifier).await?;
+    let user_id = client
+        .me(&Token::Bearer(token.access_token.clone()))
+        .await?;
+    let account_id = link_account(&request, pool.as_ref(), user_id).await?;
+    save_identity(
+        pool.as_ref(),
+        Identity {
+            id: user_id,
+            account_id,
+            provider: "twitter".to_string(),
+            access_key: token.access_token,
+            access_secret: token.refresh_token,
+            token_expires_at: Some(token.expires_at),
+        },
+    )
+    .await?;
+
+    let mut expired = Cookie::build(PKCE_COOKIE, "").path("/api/oauth2").finish();
+    expired.make_removal();
+    Ok(HttpResponse::Found()
+        .append_header((LOCATION, "/"))
+        .cookie(start_session(pool.as_ref(), account_id, user_id).await?)
+        .cookie(expired)
+        .finish())
+}
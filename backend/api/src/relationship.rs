@@ -0,0 +1,168 @@
+use actix_web::{get, web, HttpResponse};
+use fantastic_giggle_sql::{
+    OffsetDateTime, PageCursor, PgPool, Relationship, RelationshipSet, SortOrder,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, ApiError, Result};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PageQuery {
+    cursor: Option<String>,
+    limit: Option<i64>,
+    order: Option<Order>,
+}
+
+#[derive(Serialize)]
+struct UserEntry {
+    id: i64,
+    first_seen_at: i64,
+    last_seen_at: i64,
+}
+
+#[derive(Serialize)]
+struct PageResponse {
+    users: Vec<UserEntry>,
+    next_cursor: Option<String>,
+}
+
+fn encode_cursor(relationship: &Relationship) -> String {
+    let micros = relationship.created_at.unix_timestamp_nanos() / 1000;
+    format!("{}_{}", micros, relationship.target_id)
+}
+
+fn decode_cursor(cursor: &str) -> Result<PageCursor> {
+    let invalid = || ApiError::validation("invalid cursor");
+    let (micros, target_id) = cursor.split_once('_').ok_or_else(invalid)?;
+    let micros = micros.parse::<i128>().map_err(|_| invalid())?;
+    let target_id = target_id.parse::<i64>().map_err(|_| invalid())?;
+    let nanos = micros.checked_mul(1000).ok_or_else(invalid)?;
+    let created_at = OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| invalid())?;
+    Ok(PageCursor {
+        created_at,
+        target_id,
+    })
+}
+
+async fn find_page(
+    session: Session,
+    query: PageQuery,
+    pool: &PgPool,
+    set: RelationshipSet,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let order = match query.order.unwrap_or(Order::Desc) {
+        Order::Asc => SortOrder::Ascending,
+        Order::Desc => SortOrder::Descending,
+    };
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+
+    let relationships =
+        Relationship::find_page_by_source_id(pool, session.user_id, set, order, after, limit)
+            .await?;
+    let next_cursor = if relationships.len() as i64 == limit {
+        relationships.last().map(encode_cursor)
+    } else {
+        None
+    };
+    let users = relationships
+        .into_iter()
+        .map(|r| UserEntry {
+            id: r.target_id,
+            first_seen_at: r.created_at.unix_timestamp(),
+            last_seen_at: r.updated_at.unix_timestamp(),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(PageResponse { users, next_cursor }))
+}
+
+#[get("/api/followers")]
+pub(crate) async fn followers(
+    session: Session,
+    query: web::Query<PageQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    find_page(
+        session,
+        query.into_inner(),
+        pool.as_ref(),
+        RelationshipSet::Followers,
+    )
+    .await
+}
+
+#[get("/api/friends")]
+pub(crate) async fn friends(
+    session: Session,
+    query: web::Query<PageQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    find_page(
+        session,
+        query.into_inner(),
+        pool.as_ref(),
+        RelationshipSet::Friends,
+    )
+    .await
+}
+
+#[get("/api/mutuals")]
+pub(crate) async fn mutuals(
+    session: Session,
+    query: web::Query<PageQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    find_page(
+        session,
+        query.into_inner(),
+        pool.as_ref(),
+        RelationshipSet::Mutuals,
+    )
+    .await
+}
+
+#[get("/api/fans")]
+pub(crate) async fn fans(
+    session: Session,
+    query: web::Query<PageQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    find_page(
+        session,
+        query.into_inner(),
+        pool.as_ref(),
+        RelationshipSet::Fans,
+    )
+    .await
+}
+
+#[get("/api/non-followers")]
+pub(crate) async fn non_followers(
+    session: Session,
+    query: web::Query<PageQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    find_page(
+        session,
+        query.into_inner(),
+        pool.as_ref(),
+        RelationshipSet::NonFollowers,
+    )
+    .await
+}
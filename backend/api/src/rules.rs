@@ -0,0 +1,119 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, FollowBackRules, OffsetDateTime, PgPool,
+    UserSettings,
+};
+use fantastic_giggle_worker::{load_targets, Explanation, Fact, RuleSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, ApiError, Result};
+
+#[derive(Serialize)]
+struct RulesBody {
+    rules: RuleSet,
+    /// `false` while the default rules are used.
+    custom: bool,
+}
+
+/// Returns the rules of the user, or the default ones made from the settings.
+pub(crate) async fn find_rules(pool: &PgPool, user_id: i64) -> Result<(RuleSet, bool)> {
+    match FollowBackRules::find_by_user_id(pool, user_id).await? {
+        Some(rules) => {
+            let rules = serde_json::from_str(&rules.rules)
+                .map_err(|e| ApiError::validation(format!("invalid saved rules: {}", e)))?;
+            Ok((rules, true))
+        }
+        None => {
+            let settings = UserSettings::find_or_default(pool, user_id).await?;
+            Ok((RuleSet::from_settings(&settings), false))
+        }
+    }
+}
+
+#[get("/api/rules")]
+pub(crate) async fn get_rules(session: Session, pool: web::Data<PgPool>) -> Result<HttpResponse> {
+    let (rules, custom) = find_rules(pool.as_ref(), session.user_id).await?;
+    Ok(HttpResponse::Ok().json(RulesBody { rules, custom }))
+}
+
+#[put("/api/rules")]
+pub(crate) async fn put_rules(
+    session: Session,
+    body: web::Json<RuleSet>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let rules = body.into_inner();
+    rules.validate().map_err(ApiError::validation)?;
+    let json = serde_json::to_string(&rules).map_err(|e| ApiError::validation(e.to_string()))?;
+    let mut tx = pool.begin().await?;
+    FollowBackRules::save(&mut tx, session.user_id, &json).await?;
+    let record = AuditRecord::new(session.user_id, AuditActor::User, AuditAction::RulesChange)
+        .parameters(json);
+    AuditLog::append(&mut tx, &record).await?;
+    tx.commit().await?;
+    Ok(HttpResponse::Ok().json(RulesBody {
+        rules,
+        custom: true,
+    }))
+}
+
+/// Goes back to the default rules.
+#[delete("/api/rules")]
+pub(crate) async fn reset_rules(session: Session, pool: web::Data<PgPool>) -> Result<HttpResponse> {
+    let mut tx = pool.begin().await?;
+    if FollowBackRules::delete(&mut tx, session.user_id).await? {
+        let record = AuditRecord::new(session.user_id, AuditActor::User, AuditAction::RulesReset);
+        AuditLog::append(&mut tx, &record).await?;
+    }
+    tx.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ExplainBody {
+    target_id: i64,
+    /// Explains unsaved rules instead of the saved ones.
+    rules: Option<RuleSet>,
+}
+
+#[derive(Serialize)]
+struct ExplainResponse {
+    #[serde(flatten)]
+    explanation: Explanation,
+    facts: Vec<Fact>,
+    /// Profile attributes are unknown until the target has been looked up.
+    profile_cached: bool,
+}
+
+/// Evaluates the rules against what is known about the target, without following anyone.
+#[post("/api/rules/explain")]
+pub(crate) async fn explain_rules(
+    session: Session,
+    body: web::Json<ExplainBody>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let body = body.into_inner();
+    let rules = match body.rules {
+        Some(rules) => {
+            rules.validate().map_err(ApiError::validation)?;
+            rules
+        }
+        None => find_rules(pool.as_ref(), session.user_id).await?.0,
+    };
+    let target = load_targets(pool.as_ref(), session.user_id, &[body.target_id])
+        .await?
+        .remove(&body.target_id)
+        .unwrap_or_default();
+    let explanation = rules.explain(&target, OffsetDateTime::now_utc());
+    let mut facts = target.facts.iter().copied().collect::<Vec<_>>();
+    for fact in [Fact::DefaultProfileImage, Fact::Protected] {
+        if target.has(fact) {
+            facts.push(fact);
+        }
+    }
+    Ok(HttpResponse::Ok().json(ExplainResponse {
+        explanation,
+        facts,
+        profile_cached: target.profile.is_some(),
+    }))
+}
@@ -0,0 +1,92 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{cookie::time::Duration, dev::Payload, web, FromRequest, HttpRequest};
+use egg_mode::{KeyPair, Token};
+use fantastic_giggle_sql::{Account, Identity, PgPool};
+use fantastic_giggle_worker::{Credentials, TwitterV2Client};
+
+use crate::{ApiError, Result};
+
+pub(crate) const SESSION_COOKIE: &str = "session";
+pub(crate) const SESSION_MAX_AGE: Duration = Duration::days(30);
+
+pub(crate) struct Session {
+    pub(crate) id: String,
+    pub(crate) account_id: i64,
+    /// The selected identity.
+    pub(crate) user_id: i64,
+}
+
+impl Session {
+    /// Returns the Twitter token of the selected identity, refreshing it first when the server
+    /// uses the v2 API.
+    pub(crate) async fn token(
+        &self,
+        pool: &PgPool,
+        consumer: &KeyPair,
+        twitter_v2: Option<&TwitterV2Client>,
+    ) -> Result<Token> {
+        let identity = Identity::find_by_id(pool, self.user_id)
+            .await?
+            .ok_or(ApiError::Unauthorized)?;
+        let credentials = match (identity.provider.as_str(), twitter_v2) {
+            ("twitter", Some(client)) => Credentials::OAuth2(client.clone()),
+            ("twitter", None) => Credentials::OAuth1(consumer.clone()),
+            (provider, _) => {
+                return Err(ApiError::validation(format!(
+                    "this is not supported for {} accounts",
+                    provider
+                )))
+            }
+        };
+        Ok(credentials.token(pool, identity).await?)
+    }
+}
+
+impl FromRequest for Session {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let session_id = req.cookie(SESSION_COOKIE).map(|c| c.value().to_string());
+        Box::pin(async move {
+            let (pool, session_id) = match (pool, session_id) {
+                (Some(pool), Some(session_id)) => (pool, session_id),
+                _ => return Err(ApiError::Unauthorized),
+            };
+            let session = fantastic_giggle_sql::Session::find_by_id(pool.as_ref(), &session_id)
+                .await?
+                .ok_or(ApiError::Unauthorized)?;
+            Ok(Session {
+                id: session.id,
+                account_id: session.account_id,
+                user_id: session.identity_id,
+            })
+        })
+    }
+}
+
+/// A session of an admin account.
+pub(crate) struct AdminSession(pub(crate) Session);
+
+impl FromRequest for AdminSession {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let session = Session::from_request(req, payload);
+        Box::pin(async move {
+            let session = session.await?;
+            let pool = pool.ok_or(ApiError::Unauthorized)?;
+            let account = Account::find_by_id(pool.as_ref(), session.account_id)
+                .await?
+                .ok_or(ApiError::Unauthorized)?;
+            if !account.is_admin() {
+                return Err(ApiError::Forbidden);
+            }
+            Ok(AdminSession(session))
+        })
+    }
+}
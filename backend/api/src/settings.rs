@@ -0,0 +1,240 @@
+use actix_web::{get, put, web, HttpResponse};
+use chrono_tz::Tz;
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, ModerationKind, PgPool, UserSettings,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, ApiError, Result};
+
+const MAX_DAILY_FOLLOW_CAP: i32 = 400;
+const MAX_BIO_KEYWORDS: usize = 50;
+const MAX_BIO_KEYWORD_LENGTH: usize = 100;
+/// The length limit of a direct message.
+pub(crate) const MAX_WELCOME_MESSAGE_LENGTH: usize = 10000;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SettingsBody {
+    follow_back_enabled: bool,
+    dry_run: bool,
+    daily_follow_cap: i32,
+    skip_default_profile_image: bool,
+    min_followers_count: i32,
+    max_friends_followers_ratio: Option<f64>,
+    min_account_age_days: i32,
+    blocked_bio_keywords: Vec<String>,
+    quiet_hours_start: Option<i16>,
+    quiet_hours_end: Option<i16>,
+    timezone: String,
+    #[serde(default)]
+    welcome_message_enabled: bool,
+    #[serde(default)]
+    welcome_message_template: String,
+    #[serde(default = "default_spam_action")]
+    spam_action: String,
+    #[serde(default)]
+    spam_default_profile_image: bool,
+    #[serde(default)]
+    spam_min_followers_count: i32,
+    #[serde(default)]
+    spam_max_friends_followers_ratio: Option<f64>,
+    #[serde(default)]
+    spam_min_account_age_days: i32,
+    #[serde(default)]
+    spam_bio_keywords: Vec<String>,
+}
+
+fn default_spam_action() -> String {
+    "none".to_string()
+}
+
+impl From<UserSettings> for SettingsBody {
+    fn from(settings: UserSettings) -> Self {
+        Self {
+            follow_back_enabled: settings.follow_back_enabled,
+            dry_run: settings.dry_run,
+            daily_follow_cap: settings.daily_follow_cap,
+            skip_default_profile_image: settings.skip_default_profile_image,
+            min_followers_count: settings.min_followers_count,
+            max_friends_followers_ratio: settings.max_friends_followers_ratio,
+            min_account_age_days: settings.min_account_age_days,
+            blocked_bio_keywords: settings.blocked_bio_keywords,
+            quiet_hours_start: settings.quiet_hours_start,
+            quiet_hours_end: settings.quiet_hours_end,
+            timezone: settings.timezone,
+            welcome_message_enabled: settings.welcome_message_enabled,
+            welcome_message_template: settings.welcome_message_template,
+            spam_action: settings.spam_action,
+            spam_default_profile_image: settings.spam_default_profile_image,
+            spam_min_followers_count: settings.spam_min_followers_count,
+            spam_max_friends_followers_ratio: settings.spam_max_friends_followers_ratio,
+            spam_min_account_age_days: settings.spam_min_account_age_days,
+            spam_bio_keywords: settings.spam_bio_keywords,
+        }
+    }
+}
+
+impl SettingsBody {
+    fn into_settings(self, user_id: i64) -> Result<UserSettings> {
+        if !(0..=MAX_DAILY_FOLLOW_CAP).contains(&self.daily_follow_cap) {
+            return Err(ApiError::validation(format!(
+                "daily_follow_cap must be between 0 and {}",
+                MAX_DAILY_FOLLOW_CAP
+            )));
+        }
+        validate_filter(
+            "",
+            self.min_followers_count,
+            self.max_friends_followers_ratio,
+            self.min_account_age_days,
+        )?;
+        let blocked_bio_keywords =
+            validate_keywords("blocked_bio_keywords", self.blocked_bio_keywords)?;
+        match (self.quiet_hours_start, self.quiet_hours_end) {
+            (None, None) => {}
+            (Some(start), Some(end)) if (0..24).contains(&start) && (0..24).contains(&end) => {}
+            _ => {
+                return Err(ApiError::validation(
+                    "quiet_hours_start and quiet_hours_end must both be hours between 0 and 23, or both be null",
+                ))
+            }
+        }
+        if self.timezone.parse::<Tz>().is_err() {
+            return Err(ApiError::validation(format!(
+                "unknown timezone: {}",
+                self.timezone
+            )));
+        }
+        if self.welcome_message_template.chars().count() > MAX_WELCOME_MESSAGE_LENGTH {
+            return Err(ApiError::validation(format!(
+                "welcome_message_template must be at most {} characters",
+                MAX_WELCOME_MESSAGE_LENGTH
+            )));
+        }
+        if self.welcome_message_enabled && self.welcome_message_template.trim().is_empty() {
+            return Err(ApiError::validation(
+                "welcome_message_template must not be empty to send welcome messages",
+            ));
+        }
+        if self.spam_action != "none" && ModerationKind::parse(&self.spam_action).is_none() {
+            return Err(ApiError::validation(
+                "spam_action must be one of none, mute, block and soft_block",
+            ));
+        }
+        validate_filter(
+            "spam_",
+            self.spam_min_followers_count,
+            self.spam_max_friends_followers_ratio,
+            self.spam_min_account_age_days,
+        )?;
+        let spam_bio_keywords = validate_keywords("spam_bio_keywords", self.spam_bio_keywords)?;
+
+        Ok(UserSettings {
+            user_id,
+            follow_back_enabled: self.follow_back_enabled,
+            dry_run: self.dry_run,
+            daily_follow_cap: self.daily_follow_cap,
+            skip_default_profile_image: self.skip_default_profile_image,
+            min_followers_count: self.min_followers_count,
+            max_friends_followers_ratio: self.max_friends_followers_ratio,
+            min_account_age_days: self.min_account_age_days,
+            blocked_bio_keywords,
+            quiet_hours_start: self.quiet_hours_start,
+            quiet_hours_end: self.quiet_hours_end,
+            timezone: self.timezone,
+            welcome_message_enabled: self.welcome_message_enabled,
+            welcome_message_template: self.welcome_message_template,
+            spam_action: self.spam_action,
+            spam_default_profile_image: self.spam_default_profile_image,
+            spam_min_followers_count: self.spam_min_followers_count,
+            spam_max_friends_followers_ratio: self.spam_max_friends_followers_ratio,
+            spam_min_account_age_days: self.spam_min_account_age_days,
+            spam_bio_keywords,
+        })
+    }
+}
+
+/// Validates the thresholds the rules are built from, whose fields are named with `prefix`.
+fn validate_filter(
+    prefix: &str,
+    min_followers_count: i32,
+    max_friends_followers_ratio: Option<f64>,
+    min_account_age_days: i32,
+) -> Result<()> {
+    if min_followers_count < 0 {
+        return Err(ApiError::validation(format!(
+            "{}min_followers_count must not be negative",
+            prefix
+        )));
+    }
+    if let Some(ratio) = max_friends_followers_ratio {
+        if !ratio.is_finite() || ratio <= 0.0 {
+            return Err(ApiError::validation(format!(
+                "{}max_friends_followers_ratio must be a positive number",
+                prefix
+            )));
+        }
+    }
+    if min_account_age_days < 0 {
+        return Err(ApiError::validation(format!(
+            "{}min_account_age_days must not be negative",
+            prefix
+        )));
+    }
+    Ok(())
+}
+
+fn validate_keywords(name: &str, keywords: Vec<String>) -> Result<Vec<String>> {
+    if keywords.len() > MAX_BIO_KEYWORDS {
+        return Err(ApiError::validation(format!(
+            "at most {} {} are allowed",
+            MAX_BIO_KEYWORDS, name
+        )));
+    }
+    let keywords = keywords
+        .into_iter()
+        .map(|keyword| keyword.trim().to_string())
+        .collect::<Vec<_>>();
+    if keywords
+        .iter()
+        .any(|keyword| keyword.is_empty() || keyword.chars().count() > MAX_BIO_KEYWORD_LENGTH)
+    {
+        return Err(ApiError::validation(format!(
+            "{} must be between 1 and {} characters",
+            name, MAX_BIO_KEYWORD_LENGTH
+        )));
+    }
+    Ok(keywords)
+}
+
+#[get("/api/settings")]
+pub(crate) async fn get_settings(
+    session: Session,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let settings = UserSettings::find_or_default(pool.as_ref(), session.user_id).await?;
+    Ok(HttpResponse::Ok().json(SettingsBody::from(settings)))
+}
+
+#[put("/api/settings")]
+pub(crate) async fn put_settings(
+    session: Session,
+    body: web::Json<SettingsBody>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let settings = body.into_inner().into_settings(session.user_id)?;
+    let body = SettingsBody::from(settings.clone());
+    let parameters =
+        serde_json::to_string(&body).map_err(|e| ApiError::validation(e.to_string()))?;
+    let mut tx = pool.begin().await?;
+    UserSettings::save(&mut tx, &settings).await?;
+    let record = AuditRecord::new(
+        session.user_id,
+        AuditActor::User,
+        AuditAction::SettingsChange,
+    )
+    .parameters(parameters);
+    AuditLog::append(&mut tx, &record).await?;
+    tx.commit().await?;
+    Ok(HttpResponse::Ok().json(body))
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use actix_web::{get, web, HttpResponse};
+use fantastic_giggle_sql::{FollowAction, OffsetDateTime, PgPool, RelationshipSnapshot};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, ApiError, Result};
+
+const DEFAULT_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 365;
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize)]
+pub(crate) struct StatsQuery {
+    days: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct DailyStats {
+    date: String,
+    followers: i32,
+    friends: i32,
+    mutuals: i32,
+    followers_gained: i32,
+    followers_lost: i32,
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    from: String,
+    to: String,
+    daily: Vec<DailyStats>,
+    follower_growth: i64,
+    followers_gained: i64,
+    followers_lost: i64,
+    churn_rate: Option<f64>,
+    followed: i64,
+    followed_back: i64,
+    follow_back_conversion_rate: Option<f64>,
+    mutual_ratio: Option<f64>,
+}
+
+fn ratio(numerator: i64, denominator: i64) -> Option<f64> {
+    if denominator > 0 {
+        Some(numerator as f64 / denominator as f64)
+    } else {
+        None
+    }
+}
+
+#[get("/api/stats")]
+pub(crate) async fn stats(
+    session: Session,
+    query: web::Query<StatsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(ApiError::validation(format!(
+            "days must be between 1 and {}",
+            MAX_DAYS
+        )));
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let to = now.date();
+    let from = to - DAY * (days as u32 - 1);
+    let snapshots =
+        RelationshipSnapshot::find_by_source_id_between(pool.as_ref(), session.user_id, from, to)
+            .await?;
+    let conversion = FollowAction::count_conversions(
+        pool.as_ref(),
+        session.user_id,
+        from.midnight().assume_utc(),
+        now,
+    )
+    .await?;
+
+    let followers_gained = snapshots
+        .iter()
+        .map(|s| s.followers_gained as i64)
+        .sum::<i64>();
+    let followers_lost = snapshots
+        .iter()
+        .map(|s| s.followers_lost as i64)
+        .sum::<i64>();
+    let (follower_growth, churn_rate, mutual_ratio) = match (snapshots.first(), snapshots.last()) {
+        (Some(first), Some(last)) => {
+            // The first snapshot already includes the changes of its day.
+            let initial_followers = first.follower_count as i64 - first.followers_gained as i64
+                + first.followers_lost as i64;
+            (
+                last.follower_count as i64 - initial_followers,
+                ratio(followers_lost, initial_followers),
+                ratio(last.mutual_count as i64, last.follower_count as i64),
+            )
+        }
+        _ => (0, None, None),
+    };
+
+    let daily = snapshots
+        .into_iter()
+        .map(|s| DailyStats {
+            date: s.date.to_string(),
+            followers: s.follower_count,
+            friends: s.friend_count,
+            mutuals: s.mutual_count,
+            followers_gained: s.followers_gained,
+            followers_lost: s.followers_lost,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(StatsResponse {
+        from: from.to_string(),
+        to: to.to_string(),
+        daily,
+        follower_growth,
+        followers_gained,
+        followers_lost,
+        churn_rate,
+        followed: conversion.followed,
+        followed_back: conversion.converted,
+        follow_back_conversion_rate: ratio(conversion.converted, conversion.followed),
+        mutual_ratio,
+    }))
+}
@@ -0,0 +1,349 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use egg_mode::KeyPair;
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, BlockList, PgPool, WhiteList,
+};
+use fantastic_giggle_worker::TwitterV2Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{lookup::resolve_screen_names, session::Session, ApiError, Result};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+const MAX_TARGETS: usize = 1000;
+
+/// A per-user list of accounts, either never to be unfollowed or never to be followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum UserList {
+    Whitelist,
+    Blocklist,
+}
+
+impl UserList {
+    async fn find_page(
+        self,
+        pool: &PgPool,
+        source_id: i64,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<i64>> {
+        let user_ids = match self {
+            UserList::Whitelist => WhiteList::find_page_by_source_id(pool, source_id, after, limit)
+                .await?
+                .into_iter()
+                .map(|w| w.target_id)
+                .collect(),
+            UserList::Blocklist => BlockList::find_page_by_source_id(pool, source_id, after, limit)
+                .await?
+                .into_iter()
+                .map(|b| b.target_id)
+                .collect(),
+        };
+        Ok(user_ids)
+    }
+
+    pub(crate) async fn find_existing(
+        self,
+        pool: &PgPool,
+        source_id: i64,
+        target_ids: &[i64],
+    ) -> Result<Vec<i64>> {
+        let existing = match self {
+            UserList::Whitelist => WhiteList::find_existing(pool, source_id, target_ids).await?,
+            UserList::Blocklist => BlockList::find_existing(pool, source_id, target_ids).await?,
+        };
+        Ok(existing)
+    }
+
+    /// Inserts all the targets in a single transaction. `via` tells the audit log how they were
+    /// added.
+    pub(crate) async fn save_all(
+        self,
+        pool: &PgPool,
+        source_id: i64,
+        target_ids: &[i64],
+        via: &str,
+    ) -> Result<u64> {
+        let mut tx = pool.begin().await?;
+        let mut updated = 0;
+        for chunk in target_ids.chunks(MAX_TARGETS) {
+            updated += match self {
+                UserList::Whitelist => WhiteList::save_all(&mut tx, source_id, chunk).await?,
+                UserList::Blocklist => BlockList::save_all(&mut tx, source_id, chunk).await?,
+            };
+        }
+        let action = match self {
+            UserList::Whitelist => AuditAction::WhitelistAdd,
+            UserList::Blocklist => AuditAction::BlocklistAdd,
+        };
+        AuditLog::append(
+            &mut tx,
+            &audit_record(source_id, action, target_ids, updated, via),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    async fn delete(self, pool: &PgPool, source_id: i64, target_ids: &[i64]) -> Result<u64> {
+        let mut tx = pool.begin().await?;
+        let (updated, action) = match self {
+            UserList::Whitelist => (
+                WhiteList::delete(&mut tx, source_id, target_ids).await?,
+                AuditAction::WhitelistRemove,
+            ),
+            UserList::Blocklist => (
+                BlockList::delete(&mut tx, source_id, target_ids).await?,
+                AuditAction::BlocklistRemove,
+            ),
+        };
+        AuditLog::append(
+            &mut tx,
+            &audit_record(source_id, action, target_ids, updated, "api"),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(updated)
+    }
+}
+
+fn audit_record(
+    source_id: i64,
+    action: AuditAction,
+    target_ids: &[i64],
+    updated: u64,
+    via: &str,
+) -> AuditRecord {
+    let parameters = serde_json::json!({ "user_ids": target_ids, "updated": updated, "via": via });
+    AuditRecord::new(source_id, AuditActor::User, action).parameters(parameters.to_string())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ListQuery {
+    after: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ListResponse {
+    user_ids: Vec<i64>,
+    next: Option<i64>,
+}
+
+async fn list(
+    list: UserList,
+    session: Session,
+    query: web::Query<ListQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let user_ids = list
+        .find_page(pool.as_ref(), session.user_id, query.after, limit)
+        .await?;
+    let next = if user_ids.len() as i64 == limit {
+        user_ids.last().copied()
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(ListResponse { user_ids, next }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Targets {
+    #[serde(default)]
+    user_ids: Vec<i64>,
+    #[serde(default)]
+    screen_names: Vec<String>,
+}
+
+impl Targets {
+    async fn resolve(
+        self,
+        session: &Session,
+        pool: &PgPool,
+        consumer: &KeyPair,
+        twitter_v2: Option<&TwitterV2Client>,
+    ) -> Result<(Vec<i64>, Vec<String>)> {
+        if self.user_ids.len() + self.screen_names.len() > MAX_TARGETS {
+            return Err(ApiError::validation(format!(
+                "at most {} users can be given at once",
+                MAX_TARGETS
+            )));
+        }
+
+        let mut user_ids = self.user_ids;
+        let mut unresolved = vec![];
+        if !self.screen_names.is_empty() {
+            let token = session.token(pool, consumer, twitter_v2).await?;
+            let (resolved, names) = resolve_screen_names(&token, &self.screen_names).await?;
+            user_ids.extend(resolved);
+            unresolved = names;
+        }
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        Ok((user_ids, unresolved))
+    }
+}
+
+#[derive(Serialize)]
+struct UpdateResponse {
+    user_ids: Vec<i64>,
+    updated: u64,
+    unresolved: Vec<String>,
+}
+
+async fn add(
+    list: UserList,
+    session: Session,
+    targets: web::Json<Targets>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    let (user_ids, unresolved) = targets
+        .into_inner()
+        .resolve(
+            &session,
+            pool.as_ref(),
+            consumer.as_ref(),
+            twitter_v2.as_ref().map(|client| client.as_ref()),
+        )
+        .await?;
+    let updated = list
+        .save_all(pool.as_ref(), session.user_id, &user_ids, "api")
+        .await?;
+    Ok(HttpResponse::Ok().json(UpdateResponse {
+        user_ids,
+        updated,
+        unresolved,
+    }))
+}
+
+async fn remove(
+    list: UserList,
+    session: Session,
+    targets: web::Json<Targets>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    let (user_ids, unresolved) = targets
+        .into_inner()
+        .resolve(
+            &session,
+            pool.as_ref(),
+            consumer.as_ref(),
+            twitter_v2.as_ref().map(|client| client.as_ref()),
+        )
+        .await?;
+    let updated = list
+        .delete(pool.as_ref(), session.user_id, &user_ids)
+        .await?;
+    Ok(HttpResponse::Ok().json(UpdateResponse {
+        user_ids,
+        updated,
+        unresolved,
+    }))
+}
+
+#[get("/api/whitelist")]
+pub(crate) async fn list_whitelist(
+    session: Session,
+    query: web::Query<ListQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    list(UserList::Whitelist, session, query, pool).await
+}
+
+#[post("/api/whitelist")]
+pub(crate) async fn add_whitelist(
+    session: Session,
+    targets: web::Json<Targets>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    add(
+        UserList::Whitelist,
+        session,
+        targets,
+        pool,
+        consumer,
+        twitter_v2,
+    )
+    .await
+}
+
+#[delete("/api/whitelist")]
+pub(crate) async fn remove_whitelist(
+    session: Session,
+    targets: web::Json<Targets>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    remove(
+        UserList::Whitelist,
+        session,
+        targets,
+        pool,
+        consumer,
+        twitter_v2,
+    )
+    .await
+}
+
+#[get("/api/blocklist")]
+pub(crate) async fn list_blocklist(
+    session: Session,
+    query: web::Query<ListQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    list(UserList::Blocklist, session, query, pool).await
+}
+
+#[post("/api/blocklist")]
+pub(crate) async fn add_blocklist(
+    session: Session,
+    targets: web::Json<Targets>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    add(
+        UserList::Blocklist,
+        session,
+        targets,
+        pool,
+        consumer,
+        twitter_v2,
+    )
+    .await
+}
+
+#[delete("/api/blocklist")]
+pub(crate) async fn remove_blocklist(
+    session: Session,
+    targets: web::Json<Targets>,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+    twitter_v2: Option<web::Data<TwitterV2Client>>,
+) -> Result<HttpResponse> {
+    remove(
+        UserList::Blocklist,
+        session,
+        targets,
+        pool,
+        consumer,
+        twitter_v2,
+    )
+    .await
+}
@@ -0,0 +1,119 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use egg_mode::KeyPair;
+use fantastic_giggle_sql::{FollowBackRequest, Identity, PgPool, RelationKind, Relationship};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{ApiError, Result};
+
+const SIGNATURE_HEADER: &str = "x-twitter-webhooks-signature";
+
+fn hmac(consumer: &KeyPair) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(consumer.secret.as_bytes())
+        .expect("HMAC accepts keys of any length")
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CrcQuery {
+    crc_token: String,
+}
+
+#[derive(Serialize)]
+struct CrcResponse {
+    response_token: String,
+}
+
+/// Answers the challenge-response check of the Account Activity API.
+#[get("/api/webhooks/twitter")]
+pub(crate) async fn crc(
+    query: web::Query<CrcQuery>,
+    consumer: web::Data<KeyPair>,
+) -> Result<HttpResponse> {
+    let mut mac = hmac(&consumer);
+    mac.update(query.crc_token.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    Ok(HttpResponse::Ok().json(CrcResponse {
+        response_token: format!("sha256={}", base64::encode(digest)),
+    }))
+}
+
+#[derive(Deserialize)]
+struct ActivityEvent {
+    for_user_id: String,
+    #[serde(default)]
+    follow_events: Vec<FollowEvent>,
+}
+
+#[derive(Deserialize)]
+struct FollowEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    source: EventUser,
+    target: EventUser,
+}
+
+#[derive(Deserialize)]
+struct EventUser {
+    id: String,
+}
+
+fn parse_id(id: &str) -> Result<i64> {
+    id.parse()
+        .map_err(|_| ApiError::validation(format!("invalid user id: {}", id)))
+}
+
+/// Applies the follow events to the relationships as they happen. The periodic sync still
+/// reconciles anything missed here.
+#[post("/api/webhooks/twitter")]
+pub(crate) async fn events(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    consumer: web::Data<KeyPair>,
+) -> Result<HttpResponse> {
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("sha256="))
+        .and_then(|value| base64::decode(value).ok())
+        .ok_or(ApiError::Unauthorized)?;
+    let mut mac = hmac(&consumer);
+    mac.update(&body);
+    mac.verify_slice(&signature)
+        .map_err(|_| ApiError::Unauthorized)?;
+
+    let event: ActivityEvent =
+        serde_json::from_slice(&body).map_err(|e| ApiError::validation(e.to_string()))?;
+    let user_id = parse_id(&event.for_user_id)?;
+    match Identity::find_by_id(pool.as_ref(), user_id).await? {
+        Some(identity) if identity.provider == "twitter" => {}
+        _ => return Ok(HttpResponse::Ok().finish()),
+    }
+
+    for follow in event.follow_events {
+        let source_id = parse_id(&follow.source.id)?;
+        let target_id = parse_id(&follow.target.id)?;
+        let (kind, other_id) = if source_id == user_id {
+            (RelationKind::Friend, target_id)
+        } else if target_id == user_id {
+            (RelationKind::Follower, source_id)
+        } else {
+            continue;
+        };
+        match follow.kind.as_str() {
+            "follow" => {
+                let added = Relationship::add(pool.as_ref(), user_id, kind, other_id).await?;
+                if added && kind == RelationKind::Follower {
+                    FollowBackRequest::save(pool.as_ref(), user_id, other_id).await?;
+                }
+            }
+            "unfollow" => {
+                Relationship::remove(pool.as_ref(), user_id, kind, other_id).await?;
+            }
+            _ => {}
+        }
+    }
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,61 @@
+use actix_web::{get, web, HttpResponse};
+use fantastic_giggle_sql::{OffsetDateTime, PgPool, Profile, UserSettings, WelcomeMessage};
+use fantastic_giggle_worker::{render_welcome_message, WELCOME_MESSAGE_LOOKBACK};
+use serde::{Deserialize, Serialize};
+
+use crate::{session::Session, settings::MAX_WELCOME_MESSAGE_LENGTH, ApiError, Result};
+
+const PREVIEW_LIMIT: i64 = 20;
+
+#[derive(Deserialize)]
+pub(crate) struct PreviewQuery {
+    /// Previews an unsaved template instead of the saved one.
+    template: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PreviewMessage {
+    target_id: i64,
+    screen_name: String,
+    text: String,
+}
+
+/// Renders the messages the next new followers would get, without sending anything. Followers
+/// whose profiles aren't cached yet are left out.
+#[get("/api/welcome_message/preview")]
+pub(crate) async fn preview(
+    session: Session,
+    query: web::Query<PreviewQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse> {
+    let template = match query.into_inner().template {
+        Some(template) => template,
+        None => {
+            UserSettings::find_or_default(pool.as_ref(), session.user_id)
+                .await?
+                .welcome_message_template
+        }
+    };
+    if template.chars().count() > MAX_WELCOME_MESSAGE_LENGTH {
+        return Err(ApiError::validation(format!(
+            "template must be at most {} characters",
+            MAX_WELCOME_MESSAGE_LENGTH
+        )));
+    }
+
+    let since = OffsetDateTime::now_utc() - WELCOME_MESSAGE_LOOKBACK;
+    let target_ids =
+        WelcomeMessage::find_pending(pool.as_ref(), session.user_id, since, false, PREVIEW_LIMIT)
+            .await?;
+    let mut profiles = Profile::find_by_ids(pool.as_ref(), &target_ids).await?;
+    profiles.sort_by_key(|profile| target_ids.iter().position(|&id| id == profile.id));
+    let messages = profiles
+        .iter()
+        .map(|profile| PreviewMessage {
+            target_id: profile.id,
+            screen_name: profile.screen_name.clone(),
+            text: render_welcome_message(&template, profile),
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(messages))
+}
@@ -0,0 +1,225 @@
+use actix_web::{
+    cookie::Cookie,
+    http::StatusCode,
+    test::{self, TestRequest},
+    web, App,
+};
+use egg_mode::KeyPair;
+use fantastic_giggle_api::config_services;
+use fantastic_giggle_sql::{
+    Account, AuditAction, AuditLog, Identity, OffsetDateTime, PausedUser, PgPool, RelationKind,
+    Session, SyncRequest, ADMIN_ROLE,
+};
+use fantastic_giggle_test::connect_to_test_sql;
+use fantastic_giggle_worker::ActivityTracker;
+use serde_json::Value;
+
+const PROVIDER: &str = "admin_test";
+
+macro_rules! app {
+    ($pool:expr) => {
+        test::init_service(
+            App::new()
+                .configure(config_services)
+                .app_data(web::Data::new(KeyPair::new(
+                    "consumer_key",
+                    "consumer_secret",
+                )))
+                .app_data(web::Data::new(ActivityTracker::default()))
+                .app_data(web::Data::new($pool)),
+        )
+        .await
+    };
+}
+
+/// Saves an identity in a new account and returns the account ID.
+async fn save_identity(pool: &PgPool, user_id: i64) -> i64 {
+    let account_id = Account::create(pool).await.unwrap();
+    Identity::save(
+        pool,
+        Identity {
+            id: user_id,
+            account_id,
+            provider: PROVIDER.to_string(),
+            access_key: "access_key".to_string(),
+            access_secret: "access_secret".to_string(),
+            token_expires_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    PausedUser::resume(pool, user_id).await.unwrap();
+    account_id
+}
+
+/// Returns the cookie of a new session of the identity.
+async fn login(pool: &PgPool, user_id: i64, role: &str) -> Cookie<'static> {
+    let account_id = save_identity(pool, user_id).await;
+    Account::set_role(pool, account_id, role).await.unwrap();
+    let session_id = format!("admin_test_{}_{}", user_id, OffsetDateTime::now_utc());
+    let expires_at = OffsetDateTime::now_utc() + std::time::Duration::from_secs(60 * 60);
+    Session::save(pool, &session_id, account_id, user_id, expires_at)
+        .await
+        .unwrap();
+    Cookie::new("session", session_id)
+}
+
+async fn audited(pool: &PgPool, user_id: i64, action: AuditAction) -> usize {
+    AuditLog::find_page_by_user_id(pool, user_id, None, Some(action), 100)
+        .await
+        .unwrap()
+        .len()
+}
+
+async fn is_paused(pool: &PgPool, user_id: i64) -> bool {
+    PausedUser::find_all(pool)
+        .await
+        .unwrap()
+        .iter()
+        .any(|paused| paused.user_id == user_id)
+}
+
+#[actix_web::test]
+async fn requires_an_admin_session() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let cookie = login(&pool, 4_000_001, "user").await;
+    let app = app!(pool);
+
+    let request = TestRequest::get().uri("/api/admin/users").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = TestRequest::post()
+        .uri("/api/admin/users/4000001/pause")
+        .cookie(cookie)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn pauses_and_resumes_users() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let cookie = login(&pool, 4_000_011, ADMIN_ROLE).await;
+    let user_id = 4_000_012;
+    save_identity(&pool, user_id).await;
+    let paused = audited(&pool, user_id, AuditAction::Pause).await;
+    let resumed = audited(&pool, user_id, AuditAction::Resume).await;
+    let app = app!(pool.clone());
+
+    for _ in 0..2 {
+        let request = TestRequest::post()
+            .uri("/api/admin/users/4000012/pause")
+            .cookie(cookie.clone())
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+    assert!(is_paused(&pool, user_id).await);
+    assert!(!Identity::is_active(&pool, user_id).await.unwrap());
+    // Pausing a paused user is not audited again.
+    assert_eq!(
+        audited(&pool, user_id, AuditAction::Pause).await,
+        paused + 1
+    );
+
+    let request = TestRequest::get()
+        .uri("/api/admin/users")
+        .cookie(cookie.clone())
+        .to_request();
+    let users: Value = test::call_and_read_body_json(&app, request).await;
+    let user = users
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|user| user["user_id"] == user_id)
+        .unwrap();
+    assert_eq!(user["paused"], true);
+    assert_eq!(user["tokens_revoked"], false);
+    assert_eq!(user["admin"], false);
+
+    let request = TestRequest::post()
+        .uri("/api/admin/users/4000012/resume")
+        .cookie(cookie)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!is_paused(&pool, user_id).await);
+    assert_eq!(
+        audited(&pool, user_id, AuditAction::Resume).await,
+        resumed + 1
+    );
+}
+
+#[actix_web::test]
+async fn requests_a_resync_of_every_kind() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let cookie = login(&pool, 4_000_021, ADMIN_ROLE).await;
+    let user_id = 4_000_022;
+    save_identity(&pool, user_id).await;
+    let resynced = audited(&pool, user_id, AuditAction::Resync).await;
+    let app = app!(pool.clone());
+
+    let request = TestRequest::post()
+        .uri("/api/admin/users/4000022/resync")
+        .cookie(cookie)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    for kind in RelationKind::ALL {
+        let requested = SyncRequest::find_by_provider(&pool, PROVIDER, kind)
+            .await
+            .unwrap();
+        assert!(requested.contains(&user_id));
+        SyncRequest::delete(&pool, user_id, kind).await.unwrap();
+    }
+    assert_eq!(
+        audited(&pool, user_id, AuditAction::Resync).await,
+        resynced + 1
+    );
+}
+
+#[actix_web::test]
+async fn revokes_tokens_and_sessions() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let cookie = login(&pool, 4_000_031, ADMIN_ROLE).await;
+    let user_id = 4_000_032;
+    let user_cookie = login(&pool, user_id, "user").await;
+    let revoked = audited(&pool, user_id, AuditAction::RevokeTokens).await;
+    let app = app!(pool.clone());
+
+    let request = TestRequest::post()
+        .uri("/api/admin/users/4000032/revoke")
+        .cookie(cookie)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let identity = Identity::find_by_id(&pool, user_id).await.unwrap().unwrap();
+    assert!(identity.access_key.is_empty());
+    assert!(identity.access_secret.is_empty());
+    let session = Session::find_by_id(&pool, user_cookie.value())
+        .await
+        .unwrap();
+    assert!(session.is_none());
+    assert_eq!(
+        audited(&pool, user_id, AuditAction::RevokeTokens).await,
+        revoked + 1
+    );
+}
+
+#[actix_web::test]
+async fn rejects_unknown_users() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let cookie = login(&pool, 4_000_041, ADMIN_ROLE).await;
+    let app = app!(pool);
+
+    for action in ["pause", "resume", "resync", "revoke"] {
+        let request = TestRequest::post()
+            .uri(&format!("/api/admin/users/4000049/{}", action))
+            .cookie(cookie.clone())
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", action);
+    }
+}
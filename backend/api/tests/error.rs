@@ -0,0 +1,28 @@
+use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+use fantastic_giggle_api::ApiError;
+use serde_json::Value;
+
+async fn body(error: &ApiError) -> Value {
+    let body = to_bytes(error.error_response().into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[actix_web::test]
+async fn maps_unknown_worker_errors_to_internal_errors() {
+    let error = ApiError::from(anyhow::anyhow!("identity 1 no longer exists"));
+    assert!(matches!(error, ApiError::Internal(_)));
+    assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = body(&error).await;
+    assert_eq!(body["code"], "internal_error");
+    assert_eq!(body["message"], "Internal server error.");
+}
+
+#[actix_web::test]
+async fn keeps_provider_and_database_errors() {
+    let error = ApiError::from(anyhow::Error::new(egg_mode::error::Error::RateLimit(0)));
+    assert_eq!(error.status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+    let error = ApiError::from(anyhow::Error::new(fantastic_giggle_sql::Error::RowNotFound));
+    assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+    assert_eq!(body(&error).await["code"], "not_found");
+}
@@ -0,0 +1,110 @@
+use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web, App,
+};
+use egg_mode::KeyPair;
+use fantastic_giggle_api::config_services;
+use fantastic_giggle_sql::PgPool;
+use fantastic_giggle_test::connect_to_test_sql;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+const CONSUMER_SECRET: &str = "consumer_secret";
+
+fn consumer() -> KeyPair {
+    KeyPair::new("consumer_key", CONSUMER_SECRET)
+}
+
+fn sign(body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(CONSUMER_SECRET.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", base64::encode(mac.finalize().into_bytes()))
+}
+
+fn unused_pool() -> PgPool {
+    PgPool::connect_lazy("postgres://localhost/unused").unwrap()
+}
+
+macro_rules! app {
+    ($pool:expr) => {
+        test::init_service(
+            App::new()
+                .configure(config_services)
+                .app_data(web::Data::new(consumer()))
+                .app_data(web::Data::new($pool)),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn answers_the_crc_with_the_hmac_of_the_token() {
+    let app = app!(unused_pool());
+    let request = TestRequest::get()
+        .uri("/api/webhooks/twitter?crc_token=challenge")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(body, json!({ "response_token": sign(b"challenge") }));
+}
+
+#[actix_web::test]
+async fn rejects_the_crc_without_a_token() {
+    let app = app!(unused_pool());
+    let request = TestRequest::get().uri("/api/webhooks/twitter").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn rejects_events_without_a_signature() {
+    let app = app!(unused_pool());
+    let request = TestRequest::post()
+        .uri("/api/webhooks/twitter")
+        .set_payload(r#"{"for_user_id": "1"}"#)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn rejects_events_with_a_wrong_signature() {
+    let app = app!(unused_pool());
+    let body = r#"{"for_user_id": "1"}"#;
+    for signature in [
+        sign(br#"{"for_user_id": "2"}"#),
+        sign(body.as_bytes()).replace("sha256=", "sha1="),
+        "sha256=not base64".to_string(),
+    ] {
+        let request = TestRequest::post()
+            .uri("/api/webhooks/twitter")
+            .insert_header(("x-twitter-webhooks-signature", signature))
+            .set_payload(body)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+async fn accepts_signed_events() {
+    let app = app!(connect_to_test_sql().await.unwrap());
+    // Not an identity, so the event is acknowledged and ignored.
+    let body = json!({
+        "for_user_id": "9007199254740991",
+        "follow_events": [{
+            "type": "follow",
+            "source": { "id": "1" },
+            "target": { "id": "9007199254740991" }
+        }]
+    })
+    .to_string();
+    let request = TestRequest::post()
+        .uri("/api/webhooks/twitter")
+        .insert_header(("x-twitter-webhooks-signature", sign(body.as_bytes())))
+        .set_payload(body)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
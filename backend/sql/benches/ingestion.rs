@@ -0,0 +1,77 @@
+//! Compares the per-page upsert with the COPY into the staging table.
+//!
+//! Needs a database: `DATABASE_URL=... cargo bench -p fantastic-giggle-sql`. The benchmark runs in
+//! a scratch database created next to it, so the tables of `DATABASE_URL` are left alone.
+//! The number of IDs can be changed with `BENCH_IDS`.
+
+use std::time::{Duration, Instant};
+
+use fantastic_giggle_sql::{PgPool, RelationKind, Relationship, Schema};
+use fantastic_giggle_test::create_scratch_sql;
+
+const PAGE_SIZE: usize = 5000;
+const KIND: RelationKind = RelationKind::Follower;
+
+async fn upsert(pool: &PgPool, source_id: i64, ids: &[i64]) -> sqlx::Result<Duration> {
+    let started = Instant::now();
+    let started_at = Relationship::sync_started_at(pool).await?;
+    for page in ids.chunks(PAGE_SIZE) {
+        Relationship::save(pool, source_id, KIND, page).await?;
+    }
+    Relationship::finish_sync(pool, source_id, KIND, started_at).await?;
+    Ok(started.elapsed())
+}
+
+async fn staged(pool: &PgPool, source_id: i64, ids: &[i64]) -> sqlx::Result<Duration> {
+    let started = Instant::now();
+    let started_at = Relationship::sync_started_at(pool).await?;
+    Relationship::clear_staged(pool, source_id, KIND).await?;
+    for page in ids.chunks(PAGE_SIZE) {
+        Relationship::stage(pool, source_id, KIND, page).await?;
+    }
+    Relationship::merge_staged(pool, source_id, KIND, started_at).await?;
+    Ok(started.elapsed())
+}
+
+async fn table_size(pool: &PgPool) -> sqlx::Result<i64> {
+    sqlx::query_scalar(r#"SELECT pg_total_relation_size('relationship')"#)
+        .fetch_one(pool)
+        .await
+}
+
+#[tokio::main]
+async fn main() -> sqlx::Result<()> {
+    let count = std::env::var("BENCH_IDS")
+        .ok()
+        .and_then(|count| count.parse::<i64>().ok())
+        .unwrap_or(200_000);
+    let pool = create_scratch_sql("fantastic_giggle_bench").await?;
+    Schema::migrate(&pool).await?;
+
+    // The second synchronization drops 1% of the IDs and adds as many new ones.
+    let initial = (0..count).collect::<Vec<_>>();
+    let churned = (count / 100..count + count / 100).collect::<Vec<_>>();
+
+    println!("{} ids, {} per page", count, PAGE_SIZE);
+    for (name, source_id) in [("upsert", 1), ("staged", 2)] {
+        let size = table_size(&pool).await?;
+        let mut elapsed = vec![];
+        for ids in [&initial, &churned, &churned] {
+            let duration = match name {
+                "upsert" => upsert(&pool, source_id, ids).await?,
+                _ => staged(&pool, source_id, ids).await?,
+            };
+            elapsed.push(duration);
+        }
+        let growth = table_size(&pool).await? - size;
+        println!(
+            "{:>6}: initial {:>8.2?}, churned {:>8.2?}, unchanged {:>8.2?}, table grew {} kB",
+            name,
+            elapsed[0],
+            elapsed[1],
+            elapsed[2],
+            growth / 1024
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+/// A user of the app, which can have identities of several providers.
+pub struct Account {
+    pub id: i64,
+    /// `user` or `admin`.
+    pub role: String,
+    pub created_at: OffsetDateTime,
+}
+
+impl Account {
+    /// Returns the ID of the new account.
+    pub async fn create<'a, E>(conn: E) -> Result<i64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar(r#"INSERT INTO "account" DEFAULT VALUES RETURNING id"#)
+            .fetch_one(conn)
+            .await
+    }
+    /// Returns whether the account exists.
+    pub async fn set_role<'a, E>(conn: E, id: i64, role: &str) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(r#"UPDATE "account" SET role=$2 WHERE id=$1"#)
+            .bind(id)
+            .bind(role)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    pub async fn find_by_id<'a, E>(conn: E, id: i64) -> Result<Option<Account>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(Account, r#"SELECT * FROM "account" WHERE id=$1"#, id)
+            .fetch_optional(conn)
+            .await
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == ADMIN_ROLE
+    }
+}
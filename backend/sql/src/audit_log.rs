@@ -0,0 +1,287 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+use crate::ModerationKind;
+
+/// Who performed an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditActor {
+    Worker,
+    User,
+    Admin,
+}
+
+impl AuditActor {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            AuditActor::Worker => "worker",
+            AuditActor::User => "user",
+            AuditActor::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    Follow,
+    Unfollow,
+    DirectMessage,
+    Mute,
+    Unmute,
+    Block,
+    Unblock,
+    SoftBlock,
+    SettingsChange,
+    WhitelistAdd,
+    WhitelistRemove,
+    BlocklistAdd,
+    BlocklistRemove,
+    RulesChange,
+    RulesReset,
+    IdentityLink,
+    IdentityUnlink,
+    Pause,
+    Resume,
+    Resync,
+    RevokeTokens,
+    Purge,
+    SetRole,
+    RotateKeys,
+}
+
+const AUDIT_ACTIONS: [AuditAction; 25] = [
+    AuditAction::Login,
+    AuditAction::Follow,
+    AuditAction::Unfollow,
+    AuditAction::DirectMessage,
+    AuditAction::Mute,
+    AuditAction::Unmute,
+    AuditAction::Block,
+    AuditAction::Unblock,
+    AuditAction::SoftBlock,
+    AuditAction::SettingsChange,
+    AuditAction::WhitelistAdd,
+    AuditAction::WhitelistRemove,
+    AuditAction::BlocklistAdd,
+    AuditAction::BlocklistRemove,
+    AuditAction::RulesChange,
+    AuditAction::RulesReset,
+    AuditAction::IdentityLink,
+    AuditAction::IdentityUnlink,
+    AuditAction::Pause,
+    AuditAction::Resume,
+    AuditAction::Resync,
+    AuditAction::RevokeTokens,
+    AuditAction::Purge,
+    AuditAction::SetRole,
+    AuditAction::RotateKeys,
+];
+
+impl AuditAction {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Follow => "follow",
+            AuditAction::Unfollow => "unfollow",
+            AuditAction::DirectMessage => "direct_message",
+            AuditAction::Mute => "mute",
+            AuditAction::Unmute => "unmute",
+            AuditAction::Block => "block",
+            AuditAction::Unblock => "unblock",
+            AuditAction::SoftBlock => "soft_block",
+            AuditAction::SettingsChange => "settings_change",
+            AuditAction::WhitelistAdd => "whitelist_add",
+            AuditAction::WhitelistRemove => "whitelist_remove",
+            AuditAction::BlocklistAdd => "blocklist_add",
+            AuditAction::BlocklistRemove => "blocklist_remove",
+            AuditAction::RulesChange => "rules_change",
+            AuditAction::RulesReset => "rules_reset",
+            AuditAction::IdentityLink => "identity_link",
+            AuditAction::IdentityUnlink => "identity_unlink",
+            AuditAction::Pause => "pause",
+            AuditAction::Resume => "resume",
+            AuditAction::Resync => "resync",
+            AuditAction::RevokeTokens => "revoke_tokens",
+            AuditAction::Purge => "purge",
+            AuditAction::SetRole => "set_role",
+            AuditAction::RotateKeys => "rotate_keys",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        AUDIT_ACTIONS
+            .iter()
+            .copied()
+            .find(|action| action.as_str() == s)
+    }
+}
+
+impl From<ModerationKind> for AuditAction {
+    fn from(kind: ModerationKind) -> Self {
+        match kind {
+            ModerationKind::Mute => AuditAction::Mute,
+            ModerationKind::Block => AuditAction::Block,
+            ModerationKind::SoftBlock => AuditAction::SoftBlock,
+        }
+    }
+}
+
+/// An entry to append to the audit log.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub user_id: i64,
+    pub actor: AuditActor,
+    pub action: AuditAction,
+    pub target_id: Option<i64>,
+    /// A JSON object.
+    pub parameters: String,
+    pub dry_run: bool,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(user_id: i64, actor: AuditActor, action: AuditAction) -> Self {
+        Self {
+            user_id,
+            actor,
+            action,
+            target_id: None,
+            parameters: "{}".to_string(),
+            dry_run: false,
+            error: None,
+        }
+    }
+
+    pub fn target(mut self, target_id: i64) -> Self {
+        self.target_id = Some(target_id);
+        self
+    }
+
+    pub fn parameters(mut self, parameters: String) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn error(mut self, error: Option<&str>) -> Self {
+        self.error = error.map(|e| e.to_string());
+        self
+    }
+}
+
+pub struct AuditLog {
+    pub id: i64,
+    pub user_id: i64,
+    /// One of `AuditActor::as_str`.
+    pub actor: String,
+    /// One of `AuditAction::as_str`.
+    pub action: String,
+    pub target_id: Option<i64>,
+    /// The JSON of the parameters.
+    pub parameters: String,
+    pub dry_run: bool,
+    pub error: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+impl AuditLog {
+    /// Entries are never updated. Old ones are only moved to the archive.
+    pub async fn append<'a, E>(conn: E, record: &AuditRecord) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "audit_log"
+        (
+            user_id,
+            actor,
+            action,
+            target_id,
+            parameters,
+            dry_run,
+            error
+        )
+        VALUES ($1, $2, $3, $4, $5::JSONB, $6, $7)
+        "#,
+        )
+        .bind(record.user_id)
+        .bind(record.actor.as_str())
+        .bind(record.action.as_str())
+        .bind(record.target_id)
+        .bind(&record.parameters)
+        .bind(record.dry_run)
+        .bind(&record.error)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the entries before the `before` ID, newest first.
+    pub async fn find_page_by_user_id<'a, E>(
+        conn: E,
+        user_id: i64,
+        before: Option<i64>,
+        action: Option<AuditAction>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            AuditLog,
+            r#"
+        SELECT
+            id,
+            user_id,
+            actor,
+            action,
+            target_id,
+            parameters::TEXT AS "parameters!",
+            dry_run,
+            error,
+            created_at
+        FROM "audit_log"
+        WHERE user_id=$1
+        AND ($2::BIGINT IS NULL OR id<$2)
+        AND ($3::TEXT IS NULL OR action=$3)
+        ORDER BY id DESC
+        LIMIT $4
+        "#,
+            user_id,
+            before,
+            action.map(|action| action.as_str()),
+            limit
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    /// Moves up to `limit` of the entries created before `before` to the archive, oldest first,
+    /// and returns how many were moved. The trigger on "audit_log" archives what is deleted.
+    pub async fn archive<'a, E>(conn: E, before: OffsetDateTime, limit: i64) -> Result<u64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(
+            r#"
+        DELETE FROM "audit_log"
+        WHERE id IN (
+            SELECT id FROM "audit_log"
+            WHERE created_at<$1
+            ORDER BY id
+            LIMIT $2
+        )
+        "#,
+        )
+        .bind(before)
+        .bind(limit)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
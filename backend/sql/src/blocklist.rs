@@ -0,0 +1,127 @@
+use sqlx::{Executor, Postgres, Result};
+pub struct BlockList {
+    pub source_id: i64,
+    pub target_id: i64,
+}
+
+impl BlockList {
+    pub async fn save<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        blocklist: BlockList,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+        INSERT INTO "blocklist"
+        (
+            source_id,
+            target_id
+        )
+        VALUES ($1, $2)
+        ON CONFLICT (source_id, target_id)
+        DO NOTHING
+        "#,
+        )
+        .bind(blocklist.source_id)
+        .bind(blocklist.target_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+    pub async fn save_all<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        target_ids: &[i64],
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+        INSERT INTO "blocklist"
+        (
+            source_id,
+            target_id
+        )
+        SELECT $1, UNNEST($2)
+        ON CONFLICT (source_id, target_id)
+        DO NOTHING
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_ids)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+    pub async fn delete<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        target_ids: &[i64],
+    ) -> Result<u64> {
+        let result =
+            sqlx::query(r#"DELETE FROM "blocklist" WHERE source_id=$1 AND target_id=ANY($2)"#)
+                .bind(source_id)
+                .bind(target_ids)
+                .execute(conn)
+                .await?;
+        Ok(result.rows_affected())
+    }
+    pub async fn exists<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+    ) -> Result<bool> {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM "blocklist" WHERE source_id=$1 AND target_id=$2) AS "exists!""#,
+            source_id,
+            target_id
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(exists)
+    }
+    /// Returns the given target IDs which are already in the list.
+    pub async fn find_existing<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        target_ids: &[i64],
+    ) -> Result<Vec<i64>> {
+        sqlx::query_scalar!(
+            r#"SELECT target_id FROM "blocklist" WHERE source_id=$1 AND target_id=ANY($2)"#,
+            source_id,
+            target_ids
+        )
+        .fetch_all(conn)
+        .await
+    }
+    pub async fn find_by_source_id<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+    ) -> Result<Vec<BlockList>> {
+        sqlx::query_as!(
+            BlockList,
+            r#"SELECT * FROM "blocklist" WHERE source_id=$1"#,
+            source_id
+        )
+        .fetch_all(conn)
+        .await
+    }
+    pub async fn find_page_by_source_id<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        after_target_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<BlockList>> {
+        sqlx::query_as!(
+            BlockList,
+            r#"
+        SELECT * FROM "blocklist"
+        WHERE source_id=$1 AND ($2::BIGINT IS NULL OR target_id>$2)
+        ORDER BY target_id
+        LIMIT $3
+        "#,
+            source_id,
+            after_target_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use sqlx::{Executor, PgPool, Postgres, Result};
+
+/// Maps the account IDs of the providers other than Twitter to internal IDs.
+pub struct ExternalAccount {
+    pub id: i64,
+    pub provider: String,
+    pub instance: String,
+    pub external_id: String,
+}
+
+impl ExternalAccount {
+    /// Returns the internal IDs of the accounts in the given order, assigning new IDs to the
+    /// accounts seen for the first time.
+    pub async fn resolve(
+        pool: &PgPool,
+        provider: &str,
+        instance: &str,
+        external_ids: &[String],
+    ) -> Result<Vec<i64>> {
+        sqlx::query(
+            r#"
+        INSERT INTO "external_account" (provider, instance, external_id)
+        SELECT $1, $2, external_id FROM UNNEST($3::TEXT[]) AS t(external_id)
+        ON CONFLICT (provider, instance, external_id) DO NOTHING
+        "#,
+        )
+        .bind(provider)
+        .bind(instance)
+        .bind(external_ids)
+        .execute(pool)
+        .await?;
+
+        let accounts = sqlx::query_as!(
+            ExternalAccount,
+            r#"
+        SELECT * FROM "external_account"
+        WHERE provider=$1 AND instance=$2 AND external_id=ANY($3)
+        "#,
+            provider,
+            instance,
+            external_ids
+        )
+        .fetch_all(pool)
+        .await?;
+        let ids = accounts
+            .into_iter()
+            .map(|account| (account.external_id, account.id))
+            .collect::<BTreeMap<_, _>>();
+        Ok(external_ids
+            .iter()
+            .filter_map(|external_id| ids.get(external_id).copied())
+            .collect())
+    }
+    pub async fn find_by_ids<'a, E>(conn: E, ids: &[i64]) -> Result<Vec<ExternalAccount>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            ExternalAccount,
+            r#"SELECT * FROM "external_account" WHERE id=ANY($1)"#,
+            ids
+        )
+        .fetch_all(conn)
+        .await
+    }
+    pub async fn find_by_id<'a, E>(conn: E, id: i64) -> Result<Option<ExternalAccount>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            ExternalAccount,
+            r#"SELECT * FROM "external_account" WHERE id=$1"#,
+            id
+        )
+        .fetch_optional(conn)
+        .await
+    }
+}
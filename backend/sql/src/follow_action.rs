@@ -0,0 +1,128 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+pub struct FollowAction {
+    pub source_id: i64,
+    pub target_id: i64,
+    pub dry_run: bool,
+    pub error: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+impl FollowAction {
+    pub async fn save<'a, E>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+        dry_run: bool,
+        error: Option<&str>,
+    ) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "follow_action"
+        (
+            source_id,
+            target_id,
+            dry_run,
+            error
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .bind(dry_run)
+        .bind(error)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the latest attempts to follow the target, newest first.
+    pub async fn find_by_target_id<'a, E>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+        limit: i64,
+    ) -> Result<Vec<FollowAction>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            FollowAction,
+            r#"
+        SELECT * FROM "follow_action"
+        WHERE source_id=$1 AND target_id=$2
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+            source_id,
+            target_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    /// Counts the follows which did not fail since the given time, including dry runs.
+    pub async fn count_since<'a, E>(conn: E, source_id: i64, since: OffsetDateTime) -> Result<i64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let count = sqlx::query_scalar!(
+            r#"
+        SELECT COUNT(*) AS "count!" FROM "follow_action"
+        WHERE source_id=$1 AND created_at>=$2 AND error IS NULL
+        "#,
+            source_id,
+            since
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(count)
+    }
+
+    /// Counts the accounts followed in the given period and how many of them followed back
+    /// afterwards.
+    pub async fn count_conversions<'a, E>(
+        conn: E,
+        source_id: i64,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<FollowConversion>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            FollowConversion,
+            r#"
+        SELECT
+            COUNT(DISTINCT a.target_id) AS "followed!",
+            COUNT(DISTINCT h.target_id) AS "converted!"
+        FROM "follow_action" a
+        LEFT JOIN "relationship_history" h
+        ON h.source_id=a.source_id
+            AND h.target_id=a.target_id
+            AND h.kind='follower'
+            AND h.event='added'
+            AND h.created_at>a.created_at
+        WHERE a.source_id=$1
+            AND a.created_at>=$2
+            AND a.created_at<$3
+            AND a.error IS NULL
+            AND NOT a.dry_run
+        "#,
+            source_id,
+            from,
+            to
+        )
+        .fetch_one(conn)
+        .await
+    }
+}
+
+pub struct FollowConversion {
+    pub followed: i64,
+    pub converted: i64,
+}
@@ -0,0 +1,107 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+pub struct FollowBackRequest {
+    pub source_id: i64,
+    pub target_id: i64,
+    pub created_at: OffsetDateTime,
+}
+
+impl FollowBackRequest {
+    pub async fn save<'a, E>(conn: E, source_id: i64, target_id: i64) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "follow_back_request"
+        (
+            source_id,
+            target_id
+        )
+        VALUES ($1, $2)
+        ON CONFLICT (source_id, target_id)
+        DO NOTHING
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the requests of the user, oldest first.
+    pub async fn find_by_source_id<'a, E>(conn: E, source_id: i64) -> Result<Vec<FollowBackRequest>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            FollowBackRequest,
+            r#"
+        SELECT * FROM "follow_back_request"
+        WHERE source_id=$1
+        ORDER BY created_at
+        "#,
+            source_id
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    /// Deletes the requests of the user made until `until`, keeping the ones which came in
+    /// since they were read.
+    pub async fn delete_until<'a, E>(conn: E, source_id: i64, until: OffsetDateTime) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(r#"DELETE FROM "follow_back_request" WHERE source_id=$1 AND created_at<=$2"#)
+            .bind(source_id)
+            .bind(until)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns whether the target is waiting for the next follow-back round.
+    pub async fn is_pending<'a, E>(conn: E, source_id: i64, target_id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT EXISTS(
+            SELECT 1 FROM "follow_back_request" WHERE source_id=$1 AND target_id=$2
+        ) AS "exists!"
+        "#,
+            source_id,
+            target_id
+        )
+        .fetch_one(conn)
+        .await
+    }
+
+    /// Returns the identities of the provider which the workers run for and which got requests
+    /// after `since`.
+    pub async fn find_source_ids<'a, E>(
+        conn: E,
+        provider: &str,
+        since: OffsetDateTime,
+    ) -> Result<Vec<i64>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT DISTINCT r.source_id FROM "follow_back_request" r
+        JOIN "identity" i ON i.id=r.source_id
+        WHERE i.provider=$1
+        AND r.created_at>$2
+        AND i.access_key<>''
+        AND NOT EXISTS (SELECT 1 FROM "paused_user" p WHERE p.user_id=i.id)
+        "#,
+            provider,
+            since
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
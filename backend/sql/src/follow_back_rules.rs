@@ -0,0 +1,63 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+pub struct FollowBackRules {
+    pub user_id: i64,
+    /// The JSON of the rule set, which the worker parses.
+    pub rules: String,
+    pub updated_at: OffsetDateTime,
+}
+
+impl FollowBackRules {
+    pub async fn save<'a, E>(conn: E, user_id: i64, rules: &str) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "follow_back_rules"
+        (
+            user_id,
+            rules
+        )
+        VALUES ($1, $2::JSONB)
+        ON CONFLICT (user_id)
+        DO UPDATE
+            SET rules=EXCLUDED.rules,
+                updated_at=CURRENT_TIMESTAMP
+        "#,
+        )
+        .bind(user_id)
+        .bind(rules)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns whether the user had their own rules.
+    pub async fn delete<'a, E>(conn: E, user_id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(r#"DELETE FROM "follow_back_rules" WHERE user_id=$1"#)
+            .bind(user_id)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_user_id<'a, E>(conn: E, user_id: i64) -> Result<Option<FollowBackRules>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            FollowBackRules,
+            r#"
+        SELECT user_id, rules::TEXT AS "rules!", updated_at
+        FROM "follow_back_rules"
+        WHERE user_id=$1
+        "#,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await
+    }
+}
@@ -0,0 +1,53 @@
+use async_stream::try_stream;
+use futures_util::{Stream, TryStreamExt};
+use sqlx::{types::time::OffsetDateTime, PgPool, Result};
+
+/// A change of a relationship detected at the end of a synchronization.
+#[derive(sqlx::FromRow)]
+pub struct RelationshipHistory {
+    pub source_id: i64,
+    pub target_id: i64,
+    /// One of `RelationKind::as_str`.
+    pub kind: String,
+    /// `added` or `removed`.
+    pub event: String,
+    pub created_at: OffsetDateTime,
+}
+
+/// A history entry joined with the cached profile of the target, if any.
+#[derive(sqlx::FromRow)]
+pub struct ProfiledHistory {
+    pub target_id: i64,
+    pub kind: String,
+    pub event: String,
+    pub created_at: OffsetDateTime,
+    pub screen_name: Option<String>,
+    pub name: Option<String>,
+    pub followers_count: Option<i32>,
+    pub friends_count: Option<i32>,
+}
+
+impl RelationshipHistory {
+    pub fn stream_by_source_id(
+        pool: PgPool,
+        source_id: i64,
+    ) -> impl Stream<Item = Result<ProfiledHistory>> {
+        try_stream! {
+            let mut rows = sqlx::query_as::<_, ProfiledHistory>(
+                r#"
+    SELECT h.target_id, h.kind, h.event, h.created_at,
+        p.screen_name, p.name, p.followers_count, p.friends_count
+    FROM relationship_history h
+    LEFT JOIN profile p ON p.id=h.target_id
+    WHERE h.source_id=$1
+    ORDER BY h.created_at
+    "#,
+            )
+            .bind(source_id)
+            .fetch(&pool);
+            while let Some(row) = rows.try_next().await? {
+                yield row;
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use sqlx::{types::time::OffsetDateTime, Executor, PgPool, Postgres, Result, Transaction};
+
+use crate::{
+    token_cipher::{open, seal},
+    TokenCipher,
+};
+
+/// The tables keyed by the ID of an identity, and their key columns. The audit log is kept.
+const USER_TABLES: [(&str, &str); 18] = [
+    ("relationship", "source_id"),
+    ("relationship_staging", "source_id"),
+    ("relationship_history", "source_id"),
+    ("relationship_snapshot", "source_id"),
+    ("relationship_sync", "source_id"),
+    ("sync_request", "source_id"),
+    ("whitelist", "source_id"),
+    ("blocklist", "source_id"),
+    ("follow_action", "source_id"),
+    ("follow_back_request", "source_id"),
+    ("follow_back_rules", "user_id"),
+    ("welcome_message", "source_id"),
+    ("spam_check", "source_id"),
+    ("moderation_action", "source_id"),
+    ("user_settings", "user_id"),
+    ("paused_user", "user_id"),
+    ("session", "identity_id"),
+    ("external_account", "id"),
+];
+
+/// The columns of the tokens, which their encryption is bound to.
+const ACCESS_KEY: &str = "access_key";
+const ACCESS_SECRET: &str = "access_secret";
+
+pub struct Identity {
+    /// The Twitter user ID, or the ID of the external account for the other providers.
+    pub id: i64,
+    pub account_id: i64,
+    /// `twitter`, `mastodon` or `bluesky`.
+    pub provider: String,
+    /// The OAuth 1.0a access token, the OAuth 2.0 access token, or the Bluesky access JWT.
+    pub access_key: String,
+    /// The OAuth 1.0a access token secret, the OAuth 2.0 refresh token, or the Bluesky refresh
+    /// JWT.
+    pub access_secret: String,
+    /// Only OAuth 2.0 access tokens and Bluesky access JWTs expire.
+    pub token_expires_at: Option<OffsetDateTime>,
+}
+
+impl Identity {
+    /// Decrypts the tokens read from the table.
+    fn open(mut self) -> Result<Self> {
+        self.access_key = open(self.id, ACCESS_KEY, &self.access_key)?;
+        self.access_secret = open(self.id, ACCESS_SECRET, &self.access_secret)?;
+        Ok(self)
+    }
+    /// Saves the identity, moving it to the given account if it is linked to another one.
+    pub async fn save<'a, E>(conn: E, identity: Identity) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "identity"
+        (
+            id,
+            account_id,
+            provider,
+            access_key,
+            access_secret,
+            token_expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (id)
+        DO UPDATE
+            SET account_id=EXCLUDED.account_id,
+                provider=EXCLUDED.provider,
+                access_key=EXCLUDED.access_key,
+                access_secret=EXCLUDED.access_secret,
+                token_expires_at=EXCLUDED.token_expires_at
+        "#,
+        )
+        .bind(identity.id)
+        .bind(identity.account_id)
+        .bind(identity.provider)
+        .bind(seal(identity.id, ACCESS_KEY, &identity.access_key))
+        .bind(seal(identity.id, ACCESS_SECRET, &identity.access_secret))
+        .bind(identity.token_expires_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+    pub async fn save_token<'a, E>(
+        conn: E,
+        id: i64,
+        access_key: &str,
+        access_secret: &str,
+        token_expires_at: Option<OffsetDateTime>,
+    ) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        UPDATE "identity"
+        SET access_key=$2, access_secret=$3, token_expires_at=$4
+        WHERE id=$1
+        "#,
+        )
+        .bind(id)
+        .bind(seal(id, ACCESS_KEY, access_key))
+        .bind(seal(id, ACCESS_SECRET, access_secret))
+        .bind(token_expires_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+    /// Forgets the tokens, so that the workers stop until the user logs in again. Returns
+    /// whether the identity exists.
+    pub async fn revoke_tokens<'a, E>(conn: E, id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(
+            r#"
+        UPDATE "identity"
+        SET access_key='', access_secret='', token_expires_at=NULL
+        WHERE id=$1
+        "#,
+        )
+        .bind(id)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    /// Returns whether the identity was linked to the account.
+    pub async fn delete<'a, E>(conn: E, account_id: i64, id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(r#"DELETE FROM "identity" WHERE account_id=$1 AND id=$2"#)
+            .bind(account_id)
+            .bind(id)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    /// Deletes the identity and all the data of it in a single transaction. Returns whether the
+    /// identity existed.
+    pub async fn purge(pool: &PgPool, id: i64) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+        for (table, column) in USER_TABLES {
+            sqlx::query(&format!(r#"DELETE FROM "{}" WHERE {}=$1"#, table, column))
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+        }
+        let result = sqlx::query(r#"DELETE FROM "identity" WHERE id=$1"#)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+    pub async fn find_all<'a, E>(conn: E) -> Result<Vec<Identity>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(Identity, r#"SELECT * FROM "identity""#)
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .map(Identity::open)
+            .collect()
+    }
+    /// Returns the identities the workers run for: not paused, and with tokens.
+    pub async fn find_by_provider<'a, E>(conn: E, provider: &str) -> Result<Vec<Identity>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            Identity,
+            r#"
+        SELECT * FROM "identity" i
+        WHERE provider=$1
+        AND access_key<>''
+        AND NOT EXISTS (SELECT 1 FROM "paused_user" p WHERE p.user_id=i.id)
+        "#,
+            provider
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(Identity::open)
+        .collect()
+    }
+    /// Returns whether the workers run for the identity, checked again before each API call of a
+    /// round which has already started.
+    pub async fn is_active<'a, E>(conn: E, id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT EXISTS(
+            SELECT 1 FROM "identity" i
+            WHERE id=$1
+            AND access_key<>''
+            AND NOT EXISTS (SELECT 1 FROM "paused_user" p WHERE p.user_id=i.id)
+        ) AS "exists!"
+        "#,
+            id
+        )
+        .fetch_one(conn)
+        .await
+    }
+    pub async fn find_by_account_id<'a, E>(conn: E, account_id: i64) -> Result<Vec<Identity>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            Identity,
+            r#"SELECT * FROM "identity" WHERE account_id=$1 ORDER BY id"#,
+            account_id
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(Identity::open)
+        .collect()
+    }
+    pub async fn find_by_id<'a, E>(conn: E, id: i64) -> Result<Option<Identity>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(Identity, r#"SELECT * FROM "identity" WHERE id=$1"#, id)
+            .fetch_optional(conn)
+            .await?
+            .map(Identity::open)
+            .transpose()
+    }
+    /// Locks the row until the transaction ends, so that only one worker refreshes the token.
+    pub async fn find_by_id_for_update<'a, E>(conn: E, id: i64) -> Result<Option<Identity>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            Identity,
+            r#"SELECT * FROM "identity" WHERE id=$1 FOR UPDATE"#,
+            id
+        )
+        .fetch_optional(conn)
+        .await?
+        .map(Identity::open)
+        .transpose()
+    }
+    /// Encrypts the tokens which are not encrypted with the current key of the cipher yet, and
+    /// returns the IDs of their identities.
+    pub async fn rotate_tokens(
+        tx: &mut Transaction<'static, Postgres>,
+        cipher: &TokenCipher,
+    ) -> Result<Vec<i64>> {
+        let identities = sqlx::query_as!(
+            Identity,
+            r#"SELECT * FROM "identity" ORDER BY id FOR UPDATE"#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut rotated = vec![];
+        for identity in identities {
+            if cipher.is_current(&identity.access_key) && cipher.is_current(&identity.access_secret)
+            {
+                continue;
+            }
+            let id = identity.id;
+            let access_key = cipher.decrypt(id, ACCESS_KEY, &identity.access_key)?;
+            let access_secret = cipher.decrypt(id, ACCESS_SECRET, &identity.access_secret)?;
+            sqlx::query(r#"UPDATE "identity" SET access_key=$2, access_secret=$3 WHERE id=$1"#)
+                .bind(id)
+                .bind(cipher.encrypt(id, ACCESS_KEY, &access_key))
+                .bind(cipher.encrypt(id, ACCESS_SECRET, &access_secret))
+                .execute(&mut *tx)
+                .await?;
+            rotated.push(identity.id);
+        }
+        Ok(rotated)
+    }
+}
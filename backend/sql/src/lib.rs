@@ -1,11 +1,79 @@
-mod user;
-pub use user::User;
+mod account;
+pub use account::{Account, ADMIN_ROLE};
+
+mod audit_log;
+pub use audit_log::{AuditAction, AuditActor, AuditLog, AuditRecord};
+
+mod blocklist;
+pub use blocklist::BlockList;
+
+mod external_account;
+pub use external_account::ExternalAccount;
+
+mod identity;
+pub use identity::Identity;
+
+mod follow_action;
+pub use follow_action::{FollowAction, FollowConversion};
+
+mod follow_back_request;
+pub use follow_back_request::FollowBackRequest;
+
+mod follow_back_rules;
+pub use follow_back_rules::FollowBackRules;
+
+mod history;
+pub use history::{ProfiledHistory, RelationshipHistory};
+
+mod mastodon_app;
+pub use mastodon_app::MastodonApp;
+
+mod moderation_action;
+pub use moderation_action::{ModerationAction, ModerationKind};
+
+mod paused_user;
+pub use paused_user::PausedUser;
+
+mod profile;
+pub use profile::Profile;
 
 mod relationship;
-pub use relationship::Relationship;
+pub use relationship::{
+    PageCursor, ProfiledRelationship, RelationKind, Relationship, RelationshipSet, SortOrder,
+};
+
+mod relationship_sync;
+pub use relationship_sync::RelationshipSync;
+
+mod schema;
+pub use schema::{Migration, Schema};
+
+mod session;
+pub use session::Session;
+
+mod snapshot;
+pub use snapshot::RelationshipSnapshot;
+
+mod spam_check;
+pub use spam_check::SpamCheck;
+
+mod sync_request;
+pub use sync_request::SyncRequest;
+
+mod token_cipher;
+pub use token_cipher::TokenCipher;
+
+mod user_settings;
+pub use user_settings::UserSettings;
+
+mod welcome_message;
+pub use welcome_message::WelcomeMessage;
 
 mod whitelist;
 pub use whitelist::WhiteList;
 
 // re-export
-pub use sqlx::PgPool;
+pub use sqlx::{
+    types::time::{Date, OffsetDateTime},
+    Error, PgPool, Postgres, Transaction,
+};
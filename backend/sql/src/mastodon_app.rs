@@ -0,0 +1,55 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+/// The OAuth application registered on a Mastodon server.
+pub struct MastodonApp {
+    pub instance: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub created_at: OffsetDateTime,
+}
+
+impl MastodonApp {
+    pub async fn save<'a, E>(
+        conn: E,
+        instance: &str,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "mastodon_app"
+        (
+            instance,
+            client_id,
+            client_secret
+        )
+        VALUES ($1, $2, $3)
+        ON CONFLICT (instance)
+        DO UPDATE
+            SET client_id=EXCLUDED.client_id,
+                client_secret=EXCLUDED.client_secret
+        "#,
+        )
+        .bind(instance)
+        .bind(client_id)
+        .bind(client_secret)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+    pub async fn find_by_instance<'a, E>(conn: E, instance: &str) -> Result<Option<MastodonApp>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            MastodonApp,
+            r#"SELECT * FROM "mastodon_app" WHERE instance=$1"#,
+            instance
+        )
+        .fetch_optional(conn)
+        .await
+    }
+}
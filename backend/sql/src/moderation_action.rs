@@ -0,0 +1,188 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationKind {
+    Mute,
+    Block,
+    /// Blocks and unblocks at once, which only removes the follower.
+    SoftBlock,
+}
+
+impl ModerationKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ModerationKind::Mute => "mute",
+            ModerationKind::Block => "block",
+            ModerationKind::SoftBlock => "soft_block",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "mute" => Some(ModerationKind::Mute),
+            "block" => Some(ModerationKind::Block),
+            "soft_block" => Some(ModerationKind::SoftBlock),
+            _ => None,
+        }
+    }
+}
+
+/// A follower muted or blocked for looking like spam.
+pub struct ModerationAction {
+    pub id: i64,
+    pub source_id: i64,
+    pub target_id: i64,
+    /// One of `ModerationKind::as_str`.
+    pub action: String,
+    pub reason: String,
+    pub dry_run: bool,
+    pub error: Option<String>,
+    /// A soft block whose unblock hasn't gone out yet.
+    pub pending_unblock: bool,
+    pub created_at: OffsetDateTime,
+    pub undone_at: Option<OffsetDateTime>,
+}
+
+impl ModerationAction {
+    /// Returns the ID of the action. A soft block without an error is saved once the block went
+    /// out, and is pending until the unblock goes out too.
+    pub async fn save<'a, E>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+        action: ModerationKind,
+        reason: &str,
+        dry_run: bool,
+        error: Option<&str>,
+    ) -> Result<i64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let pending_unblock = action == ModerationKind::SoftBlock && !dry_run && error.is_none();
+        sqlx::query_scalar(
+            r#"
+        INSERT INTO "moderation_action"
+        (
+            source_id,
+            target_id,
+            action,
+            reason,
+            dry_run,
+            error,
+            pending_unblock
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .bind(action.as_str())
+        .bind(reason)
+        .bind(dry_run)
+        .bind(error)
+        .bind(pending_unblock)
+        .fetch_one(conn)
+        .await
+    }
+
+    pub async fn finish_unblock<'a, E>(conn: E, source_id: i64, id: i64) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        UPDATE "moderation_action"
+        SET pending_unblock=FALSE
+        WHERE source_id=$1 AND id=$2
+        "#,
+        )
+        .bind(source_id)
+        .bind(id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the soft blocks whose unblock failed, oldest first.
+    pub async fn find_pending_unblocks<'a, E>(
+        conn: E,
+        source_id: i64,
+    ) -> Result<Vec<ModerationAction>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            ModerationAction,
+            r#"
+        SELECT * FROM "moderation_action"
+        WHERE source_id=$1 AND pending_unblock
+        ORDER BY id
+        "#,
+            source_id
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    /// Returns whether the action was not undone yet.
+    pub async fn mark_undone<'a, E>(conn: E, source_id: i64, id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(
+            r#"
+        UPDATE "moderation_action"
+        SET undone_at=CURRENT_TIMESTAMP
+        WHERE source_id=$1 AND id=$2 AND undone_at IS NULL
+        "#,
+        )
+        .bind(source_id)
+        .bind(id)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_id<'a, E>(
+        conn: E,
+        source_id: i64,
+        id: i64,
+    ) -> Result<Option<ModerationAction>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            ModerationAction,
+            r#"SELECT * FROM "moderation_action" WHERE source_id=$1 AND id=$2"#,
+            source_id,
+            id
+        )
+        .fetch_optional(conn)
+        .await
+    }
+
+    /// Returns the latest actions, newest first.
+    pub async fn find_by_source_id<'a, E>(
+        conn: E,
+        source_id: i64,
+        limit: i64,
+    ) -> Result<Vec<ModerationAction>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            ModerationAction,
+            r#"
+        SELECT * FROM "moderation_action"
+        WHERE source_id=$1
+        ORDER BY created_at DESC, id DESC
+        LIMIT $2
+        "#,
+            source_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
@@ -0,0 +1,49 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+/// An identity whose workers were paused by an admin.
+pub struct PausedUser {
+    pub user_id: i64,
+    pub created_at: OffsetDateTime,
+}
+
+impl PausedUser {
+    /// Returns whether the user was not paused yet.
+    pub async fn pause<'a, E>(conn: E, user_id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(
+            r#"
+        INSERT INTO "paused_user" (user_id)
+        VALUES ($1)
+        ON CONFLICT (user_id)
+        DO NOTHING
+        "#,
+        )
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns whether the user was paused.
+    pub async fn resume<'a, E>(conn: E, user_id: i64) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(r#"DELETE FROM "paused_user" WHERE user_id=$1"#)
+            .bind(user_id)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_all<'a, E>(conn: E) -> Result<Vec<PausedUser>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(PausedUser, r#"SELECT * FROM "paused_user""#)
+            .fetch_all(conn)
+            .await
+    }
+}
@@ -0,0 +1,97 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub id: i64,
+    pub screen_name: String,
+    pub name: String,
+    pub description: String,
+    pub followers_count: i32,
+    pub friends_count: i32,
+    pub default_profile_image: bool,
+    pub protected: bool,
+    pub account_created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+impl Profile {
+    pub async fn save<'a, E>(conn: E, profile: &Profile) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "profile"
+        (
+            id,
+            screen_name,
+            name,
+            description,
+            followers_count,
+            friends_count,
+            default_profile_image,
+            protected,
+            account_created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (id)
+        DO UPDATE
+            SET screen_name=EXCLUDED.screen_name,
+                name=EXCLUDED.name,
+                description=EXCLUDED.description,
+                followers_count=EXCLUDED.followers_count,
+                friends_count=EXCLUDED.friends_count,
+                default_profile_image=EXCLUDED.default_profile_image,
+                protected=EXCLUDED.protected,
+                account_created_at=EXCLUDED.account_created_at,
+                updated_at=CURRENT_TIMESTAMP
+        "#,
+        )
+        .bind(profile.id)
+        .bind(&profile.screen_name)
+        .bind(&profile.name)
+        .bind(&profile.description)
+        .bind(profile.followers_count)
+        .bind(profile.friends_count)
+        .bind(profile.default_profile_image)
+        .bind(profile.protected)
+        .bind(profile.account_created_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_ids<'a, E>(conn: E, ids: &[i64]) -> Result<Vec<Profile>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(Profile, r#"SELECT * FROM "profile" WHERE id=ANY($1)"#, ids)
+            .fetch_all(conn)
+            .await
+    }
+
+    /// Case-insensitive, among the accounts the user has a relationship with, which are all of the
+    /// provider of the user. More than one can match when the screen name has changed hands since
+    /// the profiles were looked up.
+    pub async fn find_by_screen_name<'a, E>(
+        conn: E,
+        source_id: i64,
+        screen_name: &str,
+    ) -> Result<Vec<Profile>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            Profile,
+            r#"
+        SELECT p.* FROM "profile" p
+        WHERE LOWER(p.screen_name)=LOWER($2)
+        AND EXISTS (SELECT 1 FROM "relationship" r WHERE r.source_id=$1 AND r.target_id=p.id)
+        ORDER BY p.updated_at DESC
+        "#,
+            source_id,
+            screen_name
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
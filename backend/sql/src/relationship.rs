@@ -1,87 +1,502 @@
-use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+use async_stream::try_stream;
+use futures_util::{Stream, TryStreamExt};
+use sqlx::{types::time::OffsetDateTime, Executor, PgPool, Postgres, Result};
 
+#[derive(sqlx::FromRow)]
 pub struct Relationship {
     pub source_id: i64,
+    pub kind: String,
     pub target_id: i64,
+    pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
 }
 
+/// A cursor-paged set of IDs synchronized for each user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelationKind {
+    Follower,
+    Friend,
+    Blocking,
+    Muting,
+    /// Users who requested to follow the protected user.
+    IncomingRequest,
+    /// Protected users whom the user requested to follow.
+    OutgoingRequest,
+    /// Lists the user has been added to. The targets are list IDs.
+    ListMembership,
+}
+
+impl RelationKind {
+    pub const ALL: [RelationKind; 7] = [
+        RelationKind::Follower,
+        RelationKind::Friend,
+        RelationKind::Blocking,
+        RelationKind::Muting,
+        RelationKind::IncomingRequest,
+        RelationKind::OutgoingRequest,
+        RelationKind::ListMembership,
+    ];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            RelationKind::Follower => "follower",
+            RelationKind::Friend => "friend",
+            RelationKind::Blocking => "blocking",
+            RelationKind::Muting => "muting",
+            RelationKind::IncomingRequest => "incoming_request",
+            RelationKind::OutgoingRequest => "outgoing_request",
+            RelationKind::ListMembership => "list_membership",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelationshipSet {
+    Followers,
+    Friends,
+    /// Followers who are also friends.
+    Mutuals,
+    /// Followers who are not friends.
+    Fans,
+    /// Friends who are not followers.
+    NonFollowers,
+}
+
+impl RelationshipSet {
+    fn query(self) -> (&'static str, &'static str) {
+        match self {
+            RelationshipSet::Followers => ("follower", ""),
+            RelationshipSet::Friends => ("friend", ""),
+            RelationshipSet::Mutuals => ("follower", "AND EXISTS (SELECT 1 FROM relationship o WHERE o.source_id=r.source_id AND o.kind='friend' AND o.target_id=r.target_id)"),
+            RelationshipSet::Fans => ("follower", "AND NOT EXISTS (SELECT 1 FROM relationship o WHERE o.source_id=r.source_id AND o.kind='friend' AND o.target_id=r.target_id)"),
+            RelationshipSet::NonFollowers => ("friend", "AND NOT EXISTS (SELECT 1 FROM relationship o WHERE o.source_id=r.source_id AND o.kind='follower' AND o.target_id=r.target_id)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// A relationship joined with the cached profile of the target, if any.
+#[derive(sqlx::FromRow)]
+pub struct ProfiledRelationship {
+    pub target_id: i64,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    pub screen_name: Option<String>,
+    pub name: Option<String>,
+    pub followers_count: Option<i32>,
+    pub friends_count: Option<i32>,
+}
+
+/// Position of the last row of a page, ordered by the first-seen time and the target ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageCursor {
+    pub created_at: OffsetDateTime,
+    pub target_id: i64,
+}
+
 impl Relationship {
-    pub async fn save_followers<'a, E: Executor<'a, Database = Postgres>>(
+    pub async fn save<'a, E: Executor<'a, Database = Postgres>>(
         conn: E,
         source_id: i64,
+        kind: RelationKind,
         target_ids: &[i64],
     ) -> Result<()> {
         sqlx::query(
             r#"
-    INSERT INTO follower
+    INSERT INTO relationship
     (
         source_id,
+        kind,
         target_id
     )
-    SELECT $1, UNNEST($2)
-    ON CONFLICT (source_id, target_id)
+    SELECT $1, $2, UNNEST($3)
+    ON CONFLICT (source_id, kind, target_id)
     DO UPDATE
-    SET updated_at=CURRENT_TIMESTAMP    
+    SET updated_at=CURRENT_TIMESTAMP
     "#,
         )
         .bind(source_id)
+        .bind(kind.as_str())
         .bind(target_ids)
         .execute(conn)
         .await?;
         Ok(())
     }
 
-    pub async fn find_followers_by_source_id<'a, E: Executor<'a, Database = Postgres>>(
+    /// Adds a relationship reported as it happened, and returns whether it was new.
+    pub async fn add<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        kind: RelationKind,
+        target_id: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+    WITH added AS (
+        INSERT INTO relationship
+        (
+            source_id,
+            kind,
+            target_id
+        )
+        VALUES ($1, $2, $3)
+        ON CONFLICT (source_id, kind, target_id)
+        DO NOTHING
+        RETURNING target_id
+    )
+    INSERT INTO relationship_history
+    (
+        source_id,
+        target_id,
+        kind,
+        event
+    )
+    SELECT $1, target_id, $2, 'added'
+    FROM added
+    "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .bind(target_id)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Removes a relationship reported as it happened, and returns whether it existed.
+    pub async fn remove<'a, E: Executor<'a, Database = Postgres>>(
         conn: E,
         source_id: i64,
+        kind: RelationKind,
+        target_id: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+    WITH removed AS (
+        DELETE FROM relationship
+        WHERE source_id=$1 AND kind=$2 AND target_id=$3
+        RETURNING target_id
+    )
+    INSERT INTO relationship_history
+    (
+        source_id,
+        target_id,
+        kind,
+        event
+    )
+    SELECT $1, target_id, $2, 'removed'
+    FROM removed
+    "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .bind(target_id)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_source_id<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        kind: RelationKind,
     ) -> Result<Vec<Relationship>> {
         let relationships = sqlx::query_as!(
             Relationship,
-            "SELECT * FROM follower WHERE source_id=$1",
-            source_id
+            "SELECT * FROM relationship WHERE source_id=$1 AND kind=$2",
+            source_id,
+            kind.as_str()
         )
         .fetch_all(conn)
         .await?;
         Ok(relationships)
     }
 
-    pub async fn save_friends<'a, E: Executor<'a, Database = Postgres>>(
+    /// Returns the relationships of every kind with the given targets.
+    pub async fn find_by_target_ids<'a, E: Executor<'a, Database = Postgres>>(
         conn: E,
         source_id: i64,
         target_ids: &[i64],
+    ) -> Result<Vec<Relationship>> {
+        sqlx::query_as!(
+            Relationship,
+            "SELECT * FROM relationship WHERE source_id=$1 AND target_id=ANY($2)",
+            source_id,
+            target_ids
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn find_page_by_source_id<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        set: RelationshipSet,
+        order: SortOrder,
+        after: Option<PageCursor>,
+        limit: i64,
+    ) -> Result<Vec<Relationship>> {
+        let (kind, condition) = set.query();
+        let (comparison, direction) = match order {
+            SortOrder::Ascending => (">", "ASC"),
+            SortOrder::Descending => ("<", "DESC"),
+        };
+        let query = format!(
+            r#"
+    SELECT r.source_id, r.kind, r.target_id, r.created_at, r.updated_at
+    FROM relationship r
+    WHERE r.source_id=$1 AND r.kind='{kind}'
+    {condition}
+    AND ($2::TIMESTAMPTZ IS NULL OR (r.created_at, r.target_id) {comparison} ($2, $3))
+    ORDER BY r.created_at {direction}, r.target_id {direction}
+    LIMIT $4
+    "#
+        );
+        sqlx::query_as(&query)
+            .bind(source_id)
+            .bind(after.map(|cursor| cursor.created_at))
+            .bind(after.map(|cursor| cursor.target_id))
+            .bind(limit)
+            .fetch_all(conn)
+            .await
+    }
+
+    pub fn stream_by_source_id(
+        pool: PgPool,
+        source_id: i64,
+        set: RelationshipSet,
+    ) -> impl Stream<Item = Result<ProfiledRelationship>> {
+        try_stream! {
+            let (kind, condition) = set.query();
+            let query = format!(
+                r#"
+    SELECT r.target_id, r.created_at, r.updated_at,
+        p.screen_name, p.name, p.followers_count, p.friends_count
+    FROM relationship r
+    LEFT JOIN profile p ON p.id=r.target_id
+    WHERE r.source_id=$1 AND r.kind='{kind}'
+    {condition}
+    ORDER BY r.created_at, r.target_id
+    "#
+            );
+            let mut rows = sqlx::query_as::<_, ProfiledRelationship>(&query)
+                .bind(source_id)
+                .fetch(&pool);
+            while let Some(row) = rows.try_next().await? {
+                yield row;
+            }
+        }
+    }
+
+    /// Drops the IDs left in the staging table by an interrupted synchronization.
+    pub async fn clear_staged<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        kind: RelationKind,
+    ) -> Result<()> {
+        sqlx::query(r#"DELETE FROM relationship_staging WHERE source_id=$1 AND kind=$2"#)
+            .bind(source_id)
+            .bind(kind.as_str())
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Appends a page of IDs to the staging table with COPY, to be merged by `merge_staged`.
+    pub async fn stage(
+        pool: &PgPool,
+        source_id: i64,
+        kind: RelationKind,
+        target_ids: &[i64],
     ) -> Result<()> {
+        let mut data = String::with_capacity(target_ids.len() * 40);
+        for target_id in target_ids {
+            data.push_str(&format!(
+                "{}\t{}\t{}\n",
+                source_id,
+                kind.as_str(),
+                target_id
+            ));
+        }
+        let mut copy = pool
+            .copy_in_raw(r#"COPY relationship_staging (source_id, kind, target_id) FROM STDIN"#)
+            .await?;
+        copy.send(data.into_bytes()).await?;
+        copy.finish().await?;
+        Ok(())
+    }
+
+    /// Replaces the relationships with the staged IDs and records the differences in the
+    /// history. Rows which are still there are refreshed at most once a day, so that large
+    /// accounts don't rewrite the whole table on every synchronization. Rows added since
+    /// `started_at`, by the webhooks, are kept although the pages fetched before did not have them.
+    pub async fn merge_staged(
+        pool: &PgPool,
+        source_id: i64,
+        kind: RelationKind,
+        started_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        // Without fresh statistics the planner doesn't know how many rows were just staged.
+        sqlx::query(r#"ANALYZE relationship_staging"#)
+            .execute(&mut tx)
+            .await?;
+
+        // The first synchronization of a user adds everything, which is not worth recording.
+        sqlx::query(
+            r#"
+    INSERT INTO relationship_history
+    (
+        source_id,
+        target_id,
+        kind,
+        event
+    )
+    SELECT DISTINCT $1, s.target_id, $2, 'added'
+    FROM relationship_staging s
+    WHERE s.source_id=$1 AND s.kind=$2
+    AND NOT EXISTS (SELECT 1 FROM relationship r WHERE r.source_id=$1 AND r.kind=$2 AND r.target_id=s.target_id)
+    AND EXISTS (SELECT 1 FROM relationship WHERE source_id=$1 AND kind=$2)
+    "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+    WITH removed AS (
+        DELETE FROM relationship r
+        WHERE r.source_id=$1 AND r.kind=$2 AND r.updated_at<$3
+        AND NOT EXISTS (SELECT 1 FROM relationship_staging s WHERE s.source_id=$1 AND s.kind=$2 AND s.target_id=r.target_id)
+        RETURNING target_id
+    )
+    INSERT INTO relationship_history
+    (
+        source_id,
+        target_id,
+        kind,
+        event
+    )
+    SELECT $1, target_id, $2, 'removed'
+    FROM removed
+    "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .bind(started_at)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+    UPDATE relationship
+    SET updated_at=CURRENT_TIMESTAMP
+    WHERE source_id=$1 AND kind=$2 AND updated_at<CURRENT_TIMESTAMP - INTERVAL '1 day'
+    "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .execute(&mut tx)
+        .await?;
+
         sqlx::query(
             r#"
-    INSERT INTO friend
+    INSERT INTO relationship
     (
         source_id,
+        kind,
         target_id
     )
-    SELECT $1, UNNEST($2)
-    ON CONFLICT (source_id, target_id)
-    DO UPDATE
-    SET updated_at=CURRENT_TIMESTAMP    
+    SELECT DISTINCT $1, $2, target_id
+    FROM relationship_staging
+    WHERE source_id=$1 AND kind=$2
+    ON CONFLICT (source_id, kind, target_id)
+    DO NOTHING
     "#,
         )
         .bind(source_id)
-        .bind(target_ids)
-        .execute(conn)
+        .bind(kind.as_str())
+        .execute(&mut tx)
         .await?;
-        Ok(())
+
+        Relationship::clear_staged(&mut tx, source_id, kind).await?;
+        tx.commit().await
     }
 
-    pub async fn find_friends_by_source_id<'a, E: Executor<'a, Database = Postgres>>(
+    /// Returns the database clock, against which the `updated_at` of synchronized rows is compared
+    /// when a synchronization finishes.
+    pub async fn sync_started_at<'a, E: Executor<'a, Database = Postgres>>(
         conn: E,
+    ) -> Result<OffsetDateTime> {
+        sqlx::query_scalar!(r#"SELECT CURRENT_TIMESTAMP AS "now!""#)
+            .fetch_one(conn)
+            .await
+    }
+
+    /// Records the rows added since `started_at` and removes the rows which were not seen since
+    /// then.
+    pub async fn finish_sync(
+        pool: &PgPool,
         source_id: i64,
-    ) -> Result<Vec<Relationship>> {
-        let relationships = sqlx::query_as!(
-            Relationship,
-            "SELECT * FROM friend WHERE source_id=$1",
-            source_id
+        kind: RelationKind,
+        started_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        // The first synchronization of a user adds everything, which is not worth recording.
+        sqlx::query(
+            r#"
+    INSERT INTO relationship_history
+    (
+        source_id,
+        target_id,
+        kind,
+        event
+    )
+    SELECT source_id, target_id, kind, 'added'
+    FROM relationship
+    WHERE source_id=$1 AND kind=$2 AND created_at>=$3
+    AND EXISTS (SELECT 1 FROM relationship WHERE source_id=$1 AND kind=$2 AND created_at<$3)
+    "#,
         )
-        .fetch_all(conn)
+        .bind(source_id)
+        .bind(kind.as_str())
+        .bind(started_at)
+        .execute(&mut tx)
         .await?;
-        Ok(relationships)
+
+        sqlx::query(
+            r#"
+    WITH removed AS (
+        DELETE FROM relationship
+        WHERE source_id=$1 AND kind=$2 AND updated_at<$3
+        RETURNING target_id
+    )
+    INSERT INTO relationship_history
+    (
+        source_id,
+        target_id,
+        kind,
+        event
+    )
+    SELECT $1, target_id, $2, 'removed'
+    FROM removed
+    "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .bind(started_at)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await
     }
 }
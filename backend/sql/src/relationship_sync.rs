@@ -0,0 +1,69 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+use crate::RelationKind;
+
+pub struct RelationshipSync {
+    pub source_id: i64,
+    /// One of `RelationKind::as_str`.
+    pub kind: String,
+    pub finished_at: OffsetDateTime,
+}
+
+impl RelationshipSync {
+    pub async fn record<'a, E>(conn: E, source_id: i64, kind: RelationKind) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "relationship_sync"
+        (
+            source_id,
+            kind
+        )
+        VALUES ($1, $2)
+        ON CONFLICT (source_id, kind)
+        DO UPDATE
+            SET finished_at=CURRENT_TIMESTAMP
+        "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_source_id<'a, E>(conn: E, source_id: i64) -> Result<Vec<RelationshipSync>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            RelationshipSync,
+            r#"SELECT * FROM "relationship_sync" WHERE source_id=$1 ORDER BY kind"#,
+            source_id
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn find_by_source_ids<'a, E>(
+        conn: E,
+        source_ids: &[i64],
+    ) -> Result<Vec<RelationshipSync>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            RelationshipSync,
+            r#"
+        SELECT * FROM "relationship_sync"
+        WHERE source_id=ANY($1)
+        ORDER BY source_id, kind
+        "#,
+            source_ids
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
@@ -0,0 +1,119 @@
+use sqlx::{Executor, PgPool, Postgres, Result, Transaction};
+
+/// A change of the tables in `db/migrations`. Applied ones are never edited: every change of the
+/// tables is a new migration.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../../db/migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "relationship",
+        sql: include_str!("../../../db/migrations/0002_relationship.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "identity",
+        sql: include_str!("../../../db/migrations/0003_identity.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "settings_and_activity",
+        sql: include_str!("../../../db/migrations/0004_settings_and_activity.sql"),
+    },
+];
+
+/// The key of the advisory lock which keeps two processes from migrating at the same time.
+const MIGRATION_LOCK: i64 = 0x6667_6d69_6772_6174;
+
+/// The versions applied to the database are recorded in "schema_migration".
+pub struct Schema;
+
+impl Schema {
+    /// Creates "schema_migration" and takes the migration lock until the transaction ends.
+    async fn lock(pool: &PgPool) -> Result<Transaction<'static, Postgres>> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut tx)
+            .await?;
+        tx.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS "schema_migration" (
+            version BIGINT NOT NULL PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        )
+        .await?;
+        Ok(tx)
+    }
+
+    /// Records the first migration as applied when the database already has its tables, created
+    /// from `db/schema.sql` before there were migrations, so that the later ones move the rows of
+    /// "follower", "friend" and "user" over. Returns whether it was recorded.
+    pub async fn baseline(pool: &PgPool) -> Result<bool> {
+        let mut tx = Self::lock(pool).await?;
+        let result = sqlx::query(
+            r#"
+        INSERT INTO "schema_migration" (version, name)
+        SELECT $1, $2
+        WHERE to_regclass('"user"') IS NOT NULL AND to_regclass('"follower"') IS NOT NULL
+        AND NOT EXISTS (SELECT 1 FROM "schema_migration")
+        "#,
+        )
+        .bind(MIGRATIONS[0].version)
+        .bind(MIGRATIONS[0].name)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Applies the migrations which were not applied yet in order, each in its own transaction,
+    /// and returns them.
+    pub async fn migrate(pool: &PgPool) -> Result<Vec<&'static Migration>> {
+        let mut applied = vec![];
+        for migration in MIGRATIONS {
+            let mut tx = Self::lock(pool).await?;
+            let done: bool = sqlx::query_scalar(
+                r#"SELECT EXISTS(SELECT 1 FROM "schema_migration" WHERE version=$1)"#,
+            )
+            .bind(migration.version)
+            .fetch_one(&mut tx)
+            .await?;
+            if done {
+                continue;
+            }
+            tx.execute(migration.sql).await?;
+            sqlx::query(r#"INSERT INTO "schema_migration" (version, name) VALUES ($1, $2)"#)
+                .bind(migration.version)
+                .bind(migration.name)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+            applied.push(migration);
+        }
+        Ok(applied)
+    }
+
+    /// Returns the versions applied to the database, oldest first.
+    pub async fn applied(pool: &PgPool) -> Result<Vec<i64>> {
+        let mut tx = Self::lock(pool).await?;
+        let versions =
+            sqlx::query_scalar(r#"SELECT version FROM "schema_migration" ORDER BY version"#)
+                .fetch_all(&mut tx)
+                .await?;
+        tx.commit().await?;
+        Ok(versions)
+    }
+}
@@ -0,0 +1,100 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+pub struct Session {
+    pub id: String,
+    pub account_id: i64,
+    pub identity_id: i64,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+}
+
+impl Session {
+    pub async fn save<'a, E>(
+        conn: E,
+        id: &str,
+        account_id: i64,
+        identity_id: i64,
+        expires_at: OffsetDateTime,
+    ) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "session"
+        (
+            id,
+            account_id,
+            identity_id,
+            expires_at
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        )
+        .bind(id)
+        .bind(account_id)
+        .bind(identity_id)
+        .bind(expires_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+    pub async fn select_identity<'a, E>(conn: E, id: &str, identity_id: i64) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(r#"UPDATE "session" SET identity_id=$2 WHERE id=$1"#)
+            .bind(id)
+            .bind(identity_id)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+    /// Expired sessions and sessions whose identity is no longer linked to the account are not
+    /// returned.
+    pub async fn find_by_id<'a, E>(conn: E, id: &str) -> Result<Option<Session>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            Session,
+            r#"
+        SELECT s.* FROM "session" s
+        JOIN "identity" i ON i.id=s.identity_id AND i.account_id=s.account_id
+        WHERE s.id=$1 AND s.expires_at>CURRENT_TIMESTAMP
+        "#,
+            id
+        )
+        .fetch_optional(conn)
+        .await
+    }
+    pub async fn delete<'a, E>(conn: E, id: &str) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(r#"DELETE FROM "session" WHERE id=$1"#)
+            .bind(id)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+    /// Logs the account out everywhere.
+    pub async fn delete_by_account_id<'a, E>(conn: E, account_id: i64) -> Result<u64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(r#"DELETE FROM "session" WHERE account_id=$1"#)
+            .bind(account_id)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected())
+    }
+    pub async fn delete_expired<'a, E>(conn: E) -> Result<u64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(r#"DELETE FROM "session" WHERE expires_at<=CURRENT_TIMESTAMP"#)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
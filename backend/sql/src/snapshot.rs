@@ -0,0 +1,91 @@
+use sqlx::{
+    types::time::{Date, OffsetDateTime},
+    Executor, Postgres, Result,
+};
+pub struct RelationshipSnapshot {
+    pub source_id: i64,
+    pub date: Date,
+    pub follower_count: i32,
+    pub friend_count: i32,
+    pub mutual_count: i32,
+    pub followers_gained: i32,
+    pub followers_lost: i32,
+    pub updated_at: OffsetDateTime,
+}
+
+impl RelationshipSnapshot {
+    /// Records today's (in UTC) counts computed from the synchronized relationships.
+    pub async fn record<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+    WITH today AS (
+        SELECT DATE_TRUNC('day', CURRENT_TIMESTAMP AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS started_at
+    )
+    INSERT INTO relationship_snapshot
+    (
+        source_id,
+        date,
+        follower_count,
+        friend_count,
+        mutual_count,
+        followers_gained,
+        followers_lost
+    )
+    SELECT
+        $1,
+        (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')::DATE,
+        (SELECT COUNT(*) FROM relationship WHERE source_id=$1 AND kind='follower'),
+        (SELECT COUNT(*) FROM relationship WHERE source_id=$1 AND kind='friend'),
+        (
+            SELECT COUNT(*) FROM relationship f
+            WHERE f.source_id=$1 AND f.kind='follower'
+            AND EXISTS (SELECT 1 FROM relationship o WHERE o.source_id=f.source_id AND o.kind='friend' AND o.target_id=f.target_id)
+        ),
+        (
+            SELECT COUNT(*) FROM relationship_history, today
+            WHERE source_id=$1 AND kind='follower' AND event='added' AND created_at>=today.started_at
+        ),
+        (
+            SELECT COUNT(*) FROM relationship_history, today
+            WHERE source_id=$1 AND kind='follower' AND event='removed' AND created_at>=today.started_at
+        )
+    ON CONFLICT (source_id, date)
+    DO UPDATE
+    SET follower_count=EXCLUDED.follower_count,
+        friend_count=EXCLUDED.friend_count,
+        mutual_count=EXCLUDED.mutual_count,
+        followers_gained=EXCLUDED.followers_gained,
+        followers_lost=EXCLUDED.followers_lost,
+        updated_at=CURRENT_TIMESTAMP
+    "#,
+        )
+        .bind(source_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_source_id_between<'a, E: Executor<'a, Database = Postgres>>(
+        conn: E,
+        source_id: i64,
+        from: Date,
+        to: Date,
+    ) -> Result<Vec<RelationshipSnapshot>> {
+        sqlx::query_as!(
+            RelationshipSnapshot,
+            r#"
+    SELECT * FROM relationship_snapshot
+    WHERE source_id=$1 AND date>=$2 AND date<=$3
+    ORDER BY date
+    "#,
+            source_id,
+            from,
+            to
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
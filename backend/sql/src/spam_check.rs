@@ -0,0 +1,69 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+pub struct SpamCheck {
+    pub source_id: i64,
+    pub target_id: i64,
+    pub created_at: OffsetDateTime,
+}
+
+impl SpamCheck {
+    pub async fn save<'a, E>(conn: E, source_id: i64, target_id: i64) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "spam_check"
+        (
+            source_id,
+            target_id
+        )
+        VALUES ($1, $2)
+        ON CONFLICT (source_id, target_id)
+        DO NOTHING
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the followers gained since `since` who still follow the user, are not
+    /// whitelisted and haven't been checked, oldest first.
+    pub async fn find_pending<'a, E>(
+        conn: E,
+        source_id: i64,
+        since: OffsetDateTime,
+        limit: i64,
+    ) -> Result<Vec<i64>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT h.target_id AS "target_id!"
+        FROM "relationship_history" h
+        WHERE h.source_id=$1 AND h.kind='follower' AND h.event='added' AND h.created_at>=$2
+        AND EXISTS (
+            SELECT 1 FROM "relationship" r
+            WHERE r.source_id=$1 AND r.kind='follower' AND r.target_id=h.target_id
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM "whitelist" w WHERE w.source_id=$1 AND w.target_id=h.target_id
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM "spam_check" c WHERE c.source_id=$1 AND c.target_id=h.target_id
+        )
+        GROUP BY h.target_id
+        ORDER BY MIN(h.created_at)
+        LIMIT $3
+        "#,
+            source_id,
+            since,
+            limit
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
@@ -0,0 +1,75 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+
+use crate::RelationKind;
+
+/// A relation kind of a user to synchronize again from the first page.
+pub struct SyncRequest {
+    pub source_id: i64,
+    pub kind: String,
+    pub created_at: OffsetDateTime,
+}
+
+impl SyncRequest {
+    pub async fn save<'a, E>(conn: E, source_id: i64, kind: RelationKind) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "sync_request"
+        (
+            source_id,
+            kind
+        )
+        VALUES ($1, $2)
+        ON CONFLICT (source_id, kind)
+        DO NOTHING
+        "#,
+        )
+        .bind(source_id)
+        .bind(kind.as_str())
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the users of the requests of the relation kind for the identities of the provider
+    /// which the workers run for. The requests of paused users and users without tokens are kept
+    /// until they are resumed or log in again.
+    pub async fn find_by_provider<'a, E>(
+        conn: E,
+        provider: &str,
+        kind: RelationKind,
+    ) -> Result<Vec<i64>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT r.source_id FROM "sync_request" r
+        JOIN "identity" i ON i.id=r.source_id
+        WHERE i.provider=$1
+        AND r.kind=$2
+        AND i.access_key<>''
+        AND NOT EXISTS (SELECT 1 FROM "paused_user" p WHERE p.user_id=i.id)
+        "#,
+            provider,
+            kind.as_str()
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    /// Called once the synchronization has restarted.
+    pub async fn delete<'a, E>(conn: E, source_id: i64, kind: RelationKind) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(r#"DELETE FROM "sync_request" WHERE source_id=$1 AND kind=$2"#)
+            .bind(source_id)
+            .bind(kind.as_str())
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::OnceLock;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use sqlx::{Error, Result};
+
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+static INSTALLED: OnceLock<TokenCipher> = OnceLock::new();
+
+/// Encrypts the tokens of the identities at rest with AES-256-GCM. They are stored as
+/// `enc:<key ID>:<base64 of the nonce and the ciphertext>`, so that the keys can be rotated. The ID
+/// of the identity and the column are authenticated too, so that a token copied to another row or
+/// column does not decrypt.
+pub struct TokenCipher {
+    /// The first key encrypts. The others only decrypt the tokens which were not rotated yet.
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+impl TokenCipher {
+    /// Parses `<key ID>:<base64 of 32 bytes>` separated by commas, the current key first.
+    pub fn parse(keys: &str) -> Option<Self> {
+        let keys = keys
+            .split(',')
+            .map(|entry| {
+                let (id, key) = entry.trim().split_once(':')?;
+                let key = base64::decode(key).ok()?;
+                if id.is_empty() || key.len() != 32 {
+                    return None;
+                }
+                Some((id.to_string(), Aes256Gcm::new_from_slice(&key).ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let unique = keys
+            .iter()
+            .enumerate()
+            .all(|(i, (id, _))| keys[..i].iter().all(|(other, _)| other != id));
+        unique.then_some(Self { keys })
+    }
+
+    /// Returns a new `<key ID>:<base64>` entry for the keys to parse.
+    pub fn generate_key(id: &str) -> String {
+        let key = Aes256Gcm::generate_key(OsRng);
+        format!("{}:{}", id, base64::encode(key))
+    }
+
+    /// Encrypts the tokens saved by this process from now on, and decrypts the ones read. Returns
+    /// false when a cipher was already installed.
+    pub fn install(self) -> bool {
+        INSTALLED.set(self).is_ok()
+    }
+
+    /// The ID of the key which encrypts.
+    pub fn key_id(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    /// The empty tokens of revoked identities are kept empty.
+    pub fn encrypt(&self, identity_id: i64, column: &str, token: &str) -> String {
+        if token.is_empty() {
+            return String::new();
+        }
+        let (id, cipher) = &self.keys[0];
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let aad = associated_data(identity_id, column);
+        let payload = Payload {
+            msg: token.as_bytes(),
+            aad: aad.as_bytes(),
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .expect("tokens are short enough to encrypt");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}:{}", PREFIX, id, base64::encode(sealed))
+    }
+
+    /// Tokens saved before they were encrypted are returned as they are.
+    pub fn decrypt(&self, identity_id: i64, column: &str, stored: &str) -> Result<String> {
+        let (id, sealed) = match stored.strip_prefix(PREFIX) {
+            Some(rest) => rest
+                .split_once(':')
+                .ok_or_else(|| decode_error("malformed token"))?,
+            None => return Ok(stored.to_string()),
+        };
+        let cipher = self
+            .keys
+            .iter()
+            .find(|(key_id, _)| key_id == id)
+            .map(|(_, cipher)| cipher)
+            .ok_or_else(|| decode_error(format!("unknown token key: {}", id)))?;
+        let sealed = base64::decode(sealed).map_err(|_| decode_error("malformed token"))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(decode_error("malformed token"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let aad = associated_data(identity_id, column);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: aad.as_bytes(),
+        };
+        let token = cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                decode_error(format!(
+                    "{} of {} does not match the key {}",
+                    column, identity_id, id
+                ))
+            })?;
+        String::from_utf8(token).map_err(|_| decode_error("malformed token"))
+    }
+
+    /// Returns whether the stored token is already encrypted with the current key.
+    pub fn is_current(&self, stored: &str) -> bool {
+        stored.is_empty()
+            || stored
+                .strip_prefix(PREFIX)
+                .and_then(|rest| rest.split_once(':'))
+                .is_some_and(|(id, _)| id == self.key_id())
+    }
+}
+
+fn associated_data(identity_id: i64, column: &str) -> String {
+    format!("identity:{}:{}", identity_id, column)
+}
+
+fn decode_error<S: Into<String>>(message: S) -> Error {
+    Error::Decode(message.into().into())
+}
+
+/// Encrypts with the installed cipher. Without one, tokens are stored as they are.
+pub(crate) fn seal(identity_id: i64, column: &str, token: &str) -> String {
+    match INSTALLED.get() {
+        Some(cipher) => cipher.encrypt(identity_id, column, token),
+        None => token.to_string(),
+    }
+}
+
+pub(crate) fn open(identity_id: i64, column: &str, stored: &str) -> Result<String> {
+    match INSTALLED.get() {
+        Some(cipher) => cipher.decrypt(identity_id, column, stored),
+        None if stored.starts_with(PREFIX) => Err(decode_error("no token keys are installed")),
+        None => Ok(stored.to_string()),
+    }
+}
@@ -0,0 +1,156 @@
+use sqlx::{Executor, Postgres, Result};
+#[derive(Debug, Clone)]
+pub struct UserSettings {
+    pub user_id: i64,
+    pub follow_back_enabled: bool,
+    pub dry_run: bool,
+    pub daily_follow_cap: i32,
+    pub skip_default_profile_image: bool,
+    pub min_followers_count: i32,
+    pub max_friends_followers_ratio: Option<f64>,
+    pub min_account_age_days: i32,
+    pub blocked_bio_keywords: Vec<String>,
+    pub quiet_hours_start: Option<i16>,
+    pub quiet_hours_end: Option<i16>,
+    pub timezone: String,
+    pub welcome_message_enabled: bool,
+    /// `{name}` and `{screen_name}` are replaced with the ones of the follower.
+    pub welcome_message_template: String,
+    /// `none`, or one of `ModerationKind::as_str` to apply to the new followers who look like
+    /// spam.
+    pub spam_action: String,
+    pub spam_default_profile_image: bool,
+    pub spam_min_followers_count: i32,
+    pub spam_max_friends_followers_ratio: Option<f64>,
+    pub spam_min_account_age_days: i32,
+    pub spam_bio_keywords: Vec<String>,
+}
+
+impl UserSettings {
+    pub fn default_for(user_id: i64) -> Self {
+        Self {
+            user_id,
+            follow_back_enabled: true,
+            dry_run: false,
+            daily_follow_cap: 400,
+            skip_default_profile_image: false,
+            min_followers_count: 0,
+            max_friends_followers_ratio: None,
+            min_account_age_days: 0,
+            blocked_bio_keywords: vec![],
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+            timezone: "UTC".to_string(),
+            welcome_message_enabled: false,
+            welcome_message_template: String::new(),
+            spam_action: "none".to_string(),
+            spam_default_profile_image: false,
+            spam_min_followers_count: 0,
+            spam_max_friends_followers_ratio: None,
+            spam_min_account_age_days: 0,
+            spam_bio_keywords: vec![],
+        }
+    }
+
+    pub async fn save<'a, E>(conn: E, settings: &UserSettings) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "user_settings"
+        (
+            user_id,
+            follow_back_enabled,
+            dry_run,
+            daily_follow_cap,
+            skip_default_profile_image,
+            min_followers_count,
+            max_friends_followers_ratio,
+            min_account_age_days,
+            blocked_bio_keywords,
+            quiet_hours_start,
+            quiet_hours_end,
+            timezone,
+            welcome_message_enabled,
+            welcome_message_template,
+            spam_action,
+            spam_default_profile_image,
+            spam_min_followers_count,
+            spam_max_friends_followers_ratio,
+            spam_min_account_age_days,
+            spam_bio_keywords
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19,
+            $20
+        )
+        ON CONFLICT (user_id)
+        DO UPDATE
+            SET follow_back_enabled=EXCLUDED.follow_back_enabled,
+                dry_run=EXCLUDED.dry_run,
+                daily_follow_cap=EXCLUDED.daily_follow_cap,
+                skip_default_profile_image=EXCLUDED.skip_default_profile_image,
+                min_followers_count=EXCLUDED.min_followers_count,
+                max_friends_followers_ratio=EXCLUDED.max_friends_followers_ratio,
+                min_account_age_days=EXCLUDED.min_account_age_days,
+                blocked_bio_keywords=EXCLUDED.blocked_bio_keywords,
+                quiet_hours_start=EXCLUDED.quiet_hours_start,
+                quiet_hours_end=EXCLUDED.quiet_hours_end,
+                timezone=EXCLUDED.timezone,
+                welcome_message_enabled=EXCLUDED.welcome_message_enabled,
+                welcome_message_template=EXCLUDED.welcome_message_template,
+                spam_action=EXCLUDED.spam_action,
+                spam_default_profile_image=EXCLUDED.spam_default_profile_image,
+                spam_min_followers_count=EXCLUDED.spam_min_followers_count,
+                spam_max_friends_followers_ratio=EXCLUDED.spam_max_friends_followers_ratio,
+                spam_min_account_age_days=EXCLUDED.spam_min_account_age_days,
+                spam_bio_keywords=EXCLUDED.spam_bio_keywords
+        "#,
+        )
+        .bind(settings.user_id)
+        .bind(settings.follow_back_enabled)
+        .bind(settings.dry_run)
+        .bind(settings.daily_follow_cap)
+        .bind(settings.skip_default_profile_image)
+        .bind(settings.min_followers_count)
+        .bind(settings.max_friends_followers_ratio)
+        .bind(settings.min_account_age_days)
+        .bind(&settings.blocked_bio_keywords)
+        .bind(settings.quiet_hours_start)
+        .bind(settings.quiet_hours_end)
+        .bind(&settings.timezone)
+        .bind(settings.welcome_message_enabled)
+        .bind(&settings.welcome_message_template)
+        .bind(&settings.spam_action)
+        .bind(settings.spam_default_profile_image)
+        .bind(settings.spam_min_followers_count)
+        .bind(settings.spam_max_friends_followers_ratio)
+        .bind(settings.spam_min_account_age_days)
+        .bind(&settings.spam_bio_keywords)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_user_id<'a, E>(conn: E, user_id: i64) -> Result<Option<UserSettings>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_as!(
+            UserSettings,
+            r#"SELECT * FROM "user_settings" WHERE user_id=$1"#,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await
+    }
+
+    pub async fn find_or_default<'a, E>(conn: E, user_id: i64) -> Result<UserSettings>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let settings = Self::find_by_user_id(conn, user_id).await?;
+        Ok(settings.unwrap_or_else(|| Self::default_for(user_id)))
+    }
+}
@@ -0,0 +1,180 @@
+use sqlx::{types::time::OffsetDateTime, Executor, Postgres, Result};
+pub struct WelcomeMessage {
+    pub source_id: i64,
+    pub target_id: i64,
+    pub text: String,
+    pub dry_run: bool,
+    pub pending: bool,
+    pub error: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+impl WelcomeMessage {
+    /// Records the message of a dry run.
+    pub async fn save_dry_run<'a, E>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+        text: &str,
+    ) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        INSERT INTO "welcome_message"
+        (
+            source_id,
+            target_id,
+            text,
+            dry_run
+        )
+        VALUES ($1, $2, $3, TRUE)
+        ON CONFLICT (source_id, target_id)
+        DO NOTHING
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .bind(text)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Records the message as about to be sent, replacing a dry run. Returns false when a real
+    /// message has already been recorded, and nothing should be sent.
+    pub async fn save_pending<'a, E>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+        text: &str,
+    ) -> Result<bool>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        let result = sqlx::query(
+            r#"
+        INSERT INTO "welcome_message"
+        (
+            source_id,
+            target_id,
+            text,
+            dry_run,
+            pending
+        )
+        VALUES ($1, $2, $3, FALSE, TRUE)
+        ON CONFLICT (source_id, target_id)
+        DO UPDATE
+            SET text=EXCLUDED.text,
+                dry_run=FALSE,
+                pending=TRUE,
+                error=NULL,
+                created_at=CURRENT_TIMESTAMP
+            WHERE "welcome_message".dry_run
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .bind(text)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Marks the pending message as sent, or as failed for good with `error`.
+    pub async fn finish<'a, E>(
+        conn: E,
+        source_id: i64,
+        target_id: i64,
+        error: Option<&str>,
+    ) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"
+        UPDATE "welcome_message"
+        SET pending=FALSE, error=$3, created_at=CURRENT_TIMESTAMP
+        WHERE source_id=$1 AND target_id=$2 AND pending
+        "#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .bind(error)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Forgets the pending message which could not be sent for now, so that it is retried.
+    pub async fn delete_pending<'a, E>(conn: E, source_id: i64, target_id: i64) -> Result<()>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query(
+            r#"DELETE FROM "welcome_message" WHERE source_id=$1 AND target_id=$2 AND pending"#,
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the followers gained since `since` who still follow the user and haven't been
+    /// greeted, oldest first. With `dry_run`, the ones greeted in a dry run are excluded too.
+    pub async fn find_pending<'a, E>(
+        conn: E,
+        source_id: i64,
+        since: OffsetDateTime,
+        dry_run: bool,
+        limit: i64,
+    ) -> Result<Vec<i64>>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT h.target_id AS "target_id!"
+        FROM "relationship_history" h
+        WHERE h.source_id=$1 AND h.kind='follower' AND h.event='added' AND h.created_at>=$2
+        AND EXISTS (
+            SELECT 1 FROM "relationship" r
+            WHERE r.source_id=$1 AND r.kind='follower' AND r.target_id=h.target_id
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM "welcome_message" w
+            WHERE w.source_id=$1 AND w.target_id=h.target_id AND (NOT w.dry_run OR $3)
+        )
+        GROUP BY h.target_id
+        ORDER BY MIN(h.created_at)
+        LIMIT $4
+        "#,
+            source_id,
+            since,
+            dry_run,
+            limit
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    /// Counts the messages actually sent since `since`, including the failed and pending ones.
+    pub async fn count_since<'a, E>(conn: E, source_id: i64, since: OffsetDateTime) -> Result<i64>
+    where
+        E: Executor<'a, Database = Postgres>,
+    {
+        sqlx::query_scalar!(
+            r#"
+        SELECT COUNT(*) AS "count!"
+        FROM "welcome_message"
+        WHERE source_id=$1 AND NOT dry_run AND created_at>=$2
+        "#,
+            source_id,
+            since
+        )
+        .fetch_one(conn)
+        .await
+    }
+}
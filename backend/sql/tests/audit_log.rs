@@ -0,0 +1,61 @@
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, OffsetDateTime, PgPool,
+};
+use fantastic_giggle_test::connect_to_test_sql;
+
+const USER_ID: i64 = 3_000_001;
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!(
+        r#"SELECT COUNT(*) FROM "{}" WHERE user_id=$1"#,
+        table
+    ))
+    .bind(USER_ID)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn audit_log_is_append_only() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let archived = count(&pool, "audit_log_archive").await;
+
+    let record = AuditRecord::new(USER_ID, AuditActor::User, AuditAction::RulesReset);
+    AuditLog::append(&pool, &record).await.unwrap();
+    assert!(count(&pool, "audit_log").await > 0);
+
+    for statement in [
+        r#"UPDATE "audit_log" SET error='changed' WHERE user_id=$1"#,
+        r#"UPDATE "audit_log_archive" SET error='changed' WHERE user_id=$1"#,
+        r#"DELETE FROM "audit_log_archive" WHERE user_id=$1"#,
+    ] {
+        let result = sqlx::query(statement).bind(USER_ID).execute(&pool).await;
+        assert!(result.is_err(), "{}", statement);
+    }
+    for table in ["audit_log", "audit_log_archive"] {
+        let result = sqlx::query(&format!(r#"TRUNCATE "{}""#, table))
+            .execute(&pool)
+            .await;
+        assert!(result.is_err(), "{}", table);
+    }
+
+    // Deleting an entry archives it.
+    let moved = count(&pool, "audit_log").await;
+    sqlx::query(r#"DELETE FROM "audit_log" WHERE user_id=$1"#)
+        .bind(USER_ID)
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count(&pool, "audit_log").await, 0);
+    assert_eq!(count(&pool, "audit_log_archive").await, archived + moved);
+
+    AuditLog::append(&pool, &record).await.unwrap();
+    let future = OffsetDateTime::now_utc() + std::time::Duration::from_secs(60);
+    while AuditLog::archive(&pool, future, 1000).await.unwrap() > 0 {}
+    assert_eq!(count(&pool, "audit_log").await, 0);
+    assert_eq!(
+        count(&pool, "audit_log_archive").await,
+        archived + moved + 1
+    );
+}
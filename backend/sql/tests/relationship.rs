@@ -0,0 +1,48 @@
+use fantastic_giggle_sql::{PgPool, RelationKind, Relationship};
+use fantastic_giggle_test::connect_to_test_sql;
+
+const USER_ID: i64 = 3_300_001;
+const KIND: RelationKind = RelationKind::Follower;
+
+async fn target_ids(pool: &PgPool) -> Vec<i64> {
+    let mut ids: Vec<i64> = Relationship::find_by_source_id(pool, USER_ID, KIND)
+        .await
+        .unwrap()
+        .iter()
+        .map(|relationship| relationship.target_id)
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[tokio::test]
+async fn merging_keeps_rows_added_during_the_sync() {
+    let pool = connect_to_test_sql().await.unwrap();
+    Relationship::save(&pool, USER_ID, KIND, &[1, 2])
+        .await
+        .unwrap();
+
+    let started_at = Relationship::sync_started_at(&pool).await.unwrap();
+    Relationship::clear_staged(&pool, USER_ID, KIND)
+        .await
+        .unwrap();
+    Relationship::stage(&pool, USER_ID, KIND, &[1])
+        .await
+        .unwrap();
+    // A webhook reports a follower after the pages were fetched.
+    assert!(Relationship::add(&pool, USER_ID, KIND, 3).await.unwrap());
+    Relationship::merge_staged(&pool, USER_ID, KIND, started_at)
+        .await
+        .unwrap();
+    assert_eq!(target_ids(&pool).await, vec![1, 3]);
+
+    // The next synchronization removes it when it is gone.
+    let started_at = Relationship::sync_started_at(&pool).await.unwrap();
+    Relationship::stage(&pool, USER_ID, KIND, &[1])
+        .await
+        .unwrap();
+    Relationship::merge_staged(&pool, USER_ID, KIND, started_at)
+        .await
+        .unwrap();
+    assert_eq!(target_ids(&pool).await, vec![1]);
+}
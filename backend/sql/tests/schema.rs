@@ -0,0 +1,102 @@
+use fantastic_giggle_sql::{Identity, RelationKind, Relationship, Schema};
+use fantastic_giggle_test::create_scratch_sql;
+use sqlx::Executor;
+
+/// The schema deployments were created from before there were migrations.
+const ORIGINAL_SCHEMA: &str = include_str!("../../../db/migrations/0001_initial.sql");
+
+#[tokio::test]
+async fn upgrades_a_database_created_from_the_original_schema() {
+    let pool = create_scratch_sql("fantastic_giggle_baseline")
+        .await
+        .unwrap();
+    pool.execute(ORIGINAL_SCHEMA).await.unwrap();
+    pool.execute(
+        r#"
+    INSERT INTO "user" (id, access_key, access_secret) VALUES (1, 'key1', 'secret1'), (2, 'key2', 'secret2');
+    INSERT INTO "follower" (source_id, target_id, updated_at) VALUES (1, 10, '2020-01-01Z'), (1, 11, '2020-01-02Z');
+    INSERT INTO "friend" (source_id, target_id) VALUES (1, 11), (2, 10);
+    INSERT INTO "whitelist" (source_id, target_id) VALUES (1, 12);
+    "#,
+    )
+    .await
+    .unwrap();
+
+    assert!(Schema::baseline(&pool).await.unwrap());
+    assert!(!Schema::baseline(&pool).await.unwrap());
+    let applied = Schema::migrate(&pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|migration| migration.version)
+        .collect::<Vec<_>>();
+    assert_eq!(applied, vec![2, 3, 4]);
+    assert_eq!(Schema::applied(&pool).await.unwrap(), vec![1, 2, 3, 4]);
+
+    let mut followers = Relationship::find_by_source_id(&pool, 1, RelationKind::Follower)
+        .await
+        .unwrap();
+    followers.sort_by_key(|relationship| relationship.target_id);
+    assert_eq!(
+        followers
+            .iter()
+            .map(|relationship| (relationship.target_id, relationship.created_at.year()))
+            .collect::<Vec<_>>(),
+        vec![(10, 2020), (11, 2020)]
+    );
+    let friends = Relationship::find_by_source_id(&pool, 2, RelationKind::Friend)
+        .await
+        .unwrap();
+    assert_eq!(friends[0].target_id, 10);
+
+    let first = Identity::find_by_id(&pool, 1).await.unwrap().unwrap();
+    let second = Identity::find_by_id(&pool, 2).await.unwrap().unwrap();
+    assert_eq!(first.provider, "twitter");
+    assert_eq!(
+        (first.access_key.as_str(), first.access_secret.as_str()),
+        ("key1", "secret1")
+    );
+    assert_ne!(first.account_id, second.account_id);
+    let accounts: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM "account""#)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(accounts, 2);
+
+    let whitelisted: i64 = sqlx::query_scalar(r#"SELECT target_id FROM "whitelist""#)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(whitelisted, 12);
+    let old_tables: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM information_schema.tables WHERE table_name IN ('follower', 'friend', 'user')",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(old_tables, 0);
+}
+
+#[tokio::test]
+async fn migrates_an_empty_database_once() {
+    let scratch = create_scratch_sql("fantastic_giggle_migrations")
+        .await
+        .unwrap();
+    assert!(!Schema::baseline(&scratch).await.unwrap());
+    let applied = Schema::migrate(&scratch)
+        .await
+        .unwrap()
+        .iter()
+        .map(|migration| migration.version)
+        .collect::<Vec<_>>();
+    assert_eq!(applied, vec![1, 2, 3, 4]);
+    assert_eq!(Schema::applied(&scratch).await.unwrap(), applied);
+    assert!(Schema::migrate(&scratch).await.unwrap().is_empty());
+    let tables: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM information_schema.tables WHERE table_name='identity'",
+    )
+    .fetch_one(&scratch)
+    .await
+    .unwrap();
+    assert_eq!(tables, 1);
+}
@@ -0,0 +1,67 @@
+use fantastic_giggle_sql::{Identity, PausedUser, PgPool, RelationKind, SyncRequest};
+use fantastic_giggle_test::connect_to_test_sql;
+
+const USER_ID: i64 = 3_100_001;
+const PROVIDER: &str = "sync_request_test";
+
+async fn requested(pool: &PgPool) -> Vec<i64> {
+    SyncRequest::find_by_provider(pool, PROVIDER, RelationKind::Follower)
+        .await
+        .unwrap()
+}
+
+async fn save_identity(pool: &PgPool) {
+    Identity::save(
+        pool,
+        Identity {
+            id: USER_ID,
+            account_id: USER_ID,
+            provider: PROVIDER.to_string(),
+            access_key: "access_key".to_string(),
+            access_secret: "access_secret".to_string(),
+            token_expires_at: None,
+        },
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn requests_are_kept_while_paused_or_revoked() {
+    let pool = connect_to_test_sql().await.unwrap();
+    PausedUser::resume(&pool, USER_ID).await.unwrap();
+    save_identity(&pool).await;
+    SyncRequest::save(&pool, USER_ID, RelationKind::Follower)
+        .await
+        .unwrap();
+    assert_eq!(requested(&pool).await, vec![USER_ID]);
+
+    PausedUser::pause(&pool, USER_ID).await.unwrap();
+    assert!(requested(&pool).await.is_empty());
+    assert!(!Identity::is_active(&pool, USER_ID).await.unwrap());
+    PausedUser::resume(&pool, USER_ID).await.unwrap();
+    assert_eq!(requested(&pool).await, vec![USER_ID]);
+
+    Identity::revoke_tokens(&pool, USER_ID).await.unwrap();
+    assert!(requested(&pool).await.is_empty());
+    assert!(!Identity::is_active(&pool, USER_ID).await.unwrap());
+    save_identity(&pool).await;
+    assert_eq!(requested(&pool).await, vec![USER_ID]);
+    assert!(Identity::is_active(&pool, USER_ID).await.unwrap());
+
+    // Only the processed kind is deleted.
+    SyncRequest::save(&pool, USER_ID, RelationKind::Friend)
+        .await
+        .unwrap();
+    SyncRequest::delete(&pool, USER_ID, RelationKind::Follower)
+        .await
+        .unwrap();
+    assert!(requested(&pool).await.is_empty());
+    let friends = SyncRequest::find_by_provider(&pool, PROVIDER, RelationKind::Friend)
+        .await
+        .unwrap();
+    assert_eq!(friends, vec![USER_ID]);
+    SyncRequest::delete(&pool, USER_ID, RelationKind::Friend)
+        .await
+        .unwrap();
+}
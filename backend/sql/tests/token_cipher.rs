@@ -0,0 +1,125 @@
+use fantastic_giggle_sql::{Identity, PgPool, Schema, TokenCipher};
+use fantastic_giggle_test::create_scratch_sql;
+
+const USER_ID: i64 = 3_200_001;
+
+fn key(id: &str, byte: u8) -> String {
+    format!("{}:{}", id, base64::encode([byte; 32]))
+}
+
+async fn stored_tokens(pool: &PgPool) -> (String, String) {
+    sqlx::query_as(r#"SELECT access_key, access_secret FROM "identity" WHERE id=$1"#)
+        .bind(USER_ID)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn parses_keys() {
+    let cipher = TokenCipher::parse(&format!("{},{}", key("2", 2), key("1", 1))).unwrap();
+    assert_eq!(cipher.key_id(), "2");
+    assert!(TokenCipher::parse(&TokenCipher::generate_key("3")).is_some());
+    for keys in [
+        String::new(),
+        "1".to_string(),
+        ":AAAA".to_string(),
+        "1:not base64".to_string(),
+        format!("1:{}", base64::encode([1; 16])),
+        format!("{},{}", key("1", 1), key("1", 2)),
+    ] {
+        assert!(TokenCipher::parse(&keys).is_none(), "{}", keys);
+    }
+}
+
+#[test]
+fn decrypts_with_any_of_the_keys() {
+    let old = TokenCipher::parse(&key("1", 1)).unwrap();
+    let new = TokenCipher::parse(&format!("{},{}", key("2", 2), key("1", 1))).unwrap();
+    let sealed = old.encrypt(1, "access_key", "token");
+    assert!(sealed.starts_with("enc:1:"));
+    assert!(!sealed.contains("token"));
+    assert_ne!(sealed, old.encrypt(1, "access_key", "token"));
+    assert_eq!(new.decrypt(1, "access_key", &sealed).unwrap(), "token");
+    assert!(!new.is_current(&sealed));
+    assert!(new.is_current(&new.encrypt(1, "access_key", "token")));
+
+    // Tokens saved before the encryption, and revoked ones, are read as they are.
+    assert_eq!(new.decrypt(1, "access_key", "plain").unwrap(), "plain");
+    assert_eq!(new.encrypt(1, "access_key", ""), "");
+    assert!(new.is_current(""));
+
+    let unknown = TokenCipher::parse(&key("3", 3)).unwrap();
+    assert!(unknown.decrypt(1, "access_key", &sealed).is_err());
+    let wrong = TokenCipher::parse(&key("1", 9)).unwrap();
+    assert!(wrong.decrypt(1, "access_key", &sealed).is_err());
+}
+
+#[test]
+fn binds_tokens_to_their_identity_and_column() {
+    let cipher = TokenCipher::parse(&key("1", 1)).unwrap();
+    let sealed = cipher.encrypt(1, "access_key", "token");
+    assert!(cipher.decrypt(2, "access_key", &sealed).is_err());
+    assert!(cipher.decrypt(1, "access_secret", &sealed).is_err());
+}
+
+#[tokio::test]
+async fn encrypts_tokens_at_rest_and_rotates_them() {
+    // Rotating changes the tokens of every identity.
+    let pool = create_scratch_sql("fantastic_giggle_token_cipher")
+        .await
+        .unwrap();
+    Schema::migrate(&pool).await.unwrap();
+    assert!(TokenCipher::parse(&key("1", 1)).unwrap().install());
+    Identity::save(
+        &pool,
+        Identity {
+            id: USER_ID,
+            account_id: USER_ID,
+            provider: "twitter".to_string(),
+            access_key: "access_key".to_string(),
+            access_secret: String::new(),
+            token_expires_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let (access_key, access_secret) = stored_tokens(&pool).await;
+    assert!(access_key.starts_with("enc:1:"));
+    assert_eq!(access_secret, "");
+    let identity = Identity::find_by_id(&pool, USER_ID).await.unwrap().unwrap();
+    assert_eq!(identity.access_key, "access_key");
+    assert!(Identity::is_active(&pool, USER_ID).await.unwrap());
+
+    let rotated = TokenCipher::parse(&format!("{},{}", key("2", 2), key("1", 1))).unwrap();
+    let mut tx = pool.begin().await.unwrap();
+    let ids = Identity::rotate_tokens(&mut tx, &rotated).await.unwrap();
+    tx.commit().await.unwrap();
+    assert!(ids.contains(&USER_ID));
+    let (access_key, access_secret) = stored_tokens(&pool).await;
+    assert!(access_key.starts_with("enc:2:"));
+    assert_eq!(
+        rotated.decrypt(USER_ID, "access_key", &access_key).unwrap(),
+        "access_key"
+    );
+    assert_eq!(access_secret, "");
+
+    // Tokens saved before the encryption are encrypted too.
+    sqlx::query(r#"UPDATE "identity" SET access_secret='plain' WHERE id=$1"#)
+        .bind(USER_ID)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let mut tx = pool.begin().await.unwrap();
+    let ids = Identity::rotate_tokens(&mut tx, &rotated).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(ids, vec![USER_ID]);
+    let (_, access_secret) = stored_tokens(&pool).await;
+    assert_eq!(
+        rotated
+            .decrypt(USER_ID, "access_secret", &access_secret)
+            .unwrap(),
+        "plain"
+    );
+}
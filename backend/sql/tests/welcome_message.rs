@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use fantastic_giggle_sql::{OffsetDateTime, PgPool, RelationKind, Relationship, WelcomeMessage};
+use fantastic_giggle_test::connect_to_test_sql;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+async fn clear(pool: &PgPool, source_id: i64) {
+    for table in ["relationship_history", "welcome_message"] {
+        sqlx::query(&format!(r#"DELETE FROM "{}" WHERE source_id=$1"#, table))
+            .bind(source_id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
+
+async fn follow(pool: &PgPool, source_id: i64, target_id: i64) {
+    Relationship::add(pool, source_id, RelationKind::Follower, target_id)
+        .await
+        .unwrap();
+}
+
+async fn pending(pool: &PgPool, source_id: i64, dry_run: bool) -> Vec<i64> {
+    let since = OffsetDateTime::now_utc() - HOUR;
+    WelcomeMessage::find_pending(pool, source_id, since, dry_run, 100)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn find_pending() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let source_id = 1_000_001;
+    clear(&pool, source_id).await;
+
+    for target_id in [1, 2, 3, 4, 5] {
+        follow(&pool, source_id, target_id).await;
+    }
+    // New followers of someone else are not pending.
+    follow(&pool, source_id + 1, 6).await;
+    assert_eq!(pending(&pool, source_id, false).await, vec![1, 2, 3, 4, 5]);
+
+    // Those who unfollowed since are not greeted.
+    Relationship::remove(&pool, source_id, RelationKind::Follower, 5)
+        .await
+        .unwrap();
+
+    WelcomeMessage::save_dry_run(&pool, source_id, 1, "hi")
+        .await
+        .unwrap();
+    assert!(WelcomeMessage::save_pending(&pool, source_id, 2, "hi")
+        .await
+        .unwrap());
+    assert!(WelcomeMessage::save_pending(&pool, source_id, 3, "hi")
+        .await
+        .unwrap());
+    WelcomeMessage::finish(&pool, source_id, 3, Some("not accepted"))
+        .await
+        .unwrap();
+
+    // Dry runs are greeted again by a real run, but not by another dry run. Pending and failed
+    // messages are never sent again.
+    assert_eq!(pending(&pool, source_id, false).await, vec![1, 4]);
+    assert_eq!(pending(&pool, source_id, true).await, vec![4]);
+
+    // A message which could not be sent for now is retried.
+    WelcomeMessage::delete_pending(&pool, source_id, 2)
+        .await
+        .unwrap();
+    assert_eq!(pending(&pool, source_id, false).await, vec![1, 2, 4]);
+
+    // A real message replaces a dry run, but nothing replaces a real one.
+    assert!(WelcomeMessage::save_pending(&pool, source_id, 1, "hi")
+        .await
+        .unwrap());
+    assert!(!WelcomeMessage::save_pending(&pool, source_id, 1, "hi")
+        .await
+        .unwrap());
+    WelcomeMessage::finish(&pool, source_id, 1, None)
+        .await
+        .unwrap();
+    assert_eq!(pending(&pool, source_id, false).await, vec![2, 4]);
+
+    // Followers gained before `since` are not greeted.
+    let since = OffsetDateTime::now_utc() + HOUR;
+    assert!(
+        WelcomeMessage::find_pending(&pool, source_id, since, false, 100)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    clear(&pool, source_id).await;
+    clear(&pool, source_id + 1).await;
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use egg_mode::KeyPair;
+use fantastic_giggle_sql::{
+    Account, AuditAction, AuditActor, AuditLog, AuditRecord, Identity, PausedUser, PgPool, Profile,
+    RelationKind, RelationshipSync, Schema, SyncRequest, TokenCipher, ADMIN_ROLE,
+};
+use fantastic_giggle_worker::{
+    BlueskyClient, Credentials, EventBus, FollowBackWorker, FollowClient, MastodonClient,
+    OAuth2Config, PacingPolicy, TwitterClient, TwitterV2Client, BLUESKY, MASTODON,
+};
+
+const USAGE: &str = "usage: fantastic-giggle-admin <command>
+
+commands:
+    users                           list the identities and their state
+    sync-state <user_id>            show when each relation kind was last synchronized
+    resync <user_id>                synchronize every relation kind again from the first page
+    purge <user_id> --yes           delete the identity and all its data
+    set-role <account_id> <user|admin>
+    migrate                         apply the migrations of db/migrations not applied yet
+    new-key <key_id>                print a new key to put first in TOKEN_KEYS
+    rotate-keys                     encrypt every token with the first key of TOKEN_KEYS
+    candidates <user_id>            print the followers the user would follow back now";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+fn fail<E: std::fmt::Debug>(e: E) -> ! {
+    eprintln!("{:?}", e);
+    std::process::exit(1);
+}
+
+fn parse_id(id: &str) -> i64 {
+    id.parse().unwrap_or_else(|_| usage())
+}
+
+async fn audit(pool: &PgPool, user_id: i64, action: AuditAction) {
+    let record = AuditRecord::new(user_id, AuditActor::Admin, action)
+        .parameters(r#"{"via": "cli"}"#.to_string());
+    AuditLog::append(pool, &record)
+        .await
+        .unwrap_or_else(|e| fail(e));
+}
+
+async fn find_identity(pool: &PgPool, user_id: i64) -> Identity {
+    match Identity::find_by_id(pool, user_id).await {
+        Ok(Some(identity)) => identity,
+        Ok(None) => {
+            eprintln!("no such user: {}", user_id);
+            std::process::exit(1);
+        }
+        Err(e) => fail(e),
+    }
+}
+
+async fn list_users(pool: &PgPool) {
+    let identities = Identity::find_all(pool).await.unwrap_or_else(|e| fail(e));
+    let ids = identities.iter().map(|i| i.id).collect::<Vec<_>>();
+    let paused = PausedUser::find_all(pool)
+        .await
+        .unwrap_or_else(|e| fail(e))
+        .into_iter()
+        .map(|p| p.user_id)
+        .collect::<BTreeSet<_>>();
+    let screen_names = Profile::find_by_ids(pool, &ids)
+        .await
+        .unwrap_or_else(|e| fail(e))
+        .into_iter()
+        .map(|p| (p.id, p.screen_name))
+        .collect::<BTreeMap<_, _>>();
+    println!("user_id\taccount_id\tprovider\tscreen_name\trole\tstate");
+    for identity in identities {
+        let role = match Account::find_by_id(pool, identity.account_id).await {
+            Ok(Some(account)) => account.role,
+            Ok(None) => "-".to_string(),
+            Err(e) => fail(e),
+        };
+        let state = if identity.access_key.is_empty() {
+            "revoked"
+        } else if paused.contains(&identity.id) {
+            "paused"
+        } else {
+            "active"
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            identity.id,
+            identity.account_id,
+            identity.provider,
+            screen_names
+                .get(&identity.id)
+                .map_or("-", |name| name.as_str()),
+            role,
+            state
+        );
+    }
+}
+
+async fn show_sync_state(pool: &PgPool, user_id: i64) {
+    find_identity(pool, user_id).await;
+    let syncs = RelationshipSync::find_by_source_id(pool, user_id)
+        .await
+        .unwrap_or_else(|e| fail(e));
+    println!("kind\tfinished_at");
+    for kind in RelationKind::ALL {
+        let finished_at = syncs
+            .iter()
+            .find(|sync| sync.kind == kind.as_str())
+            .map_or("never".to_string(), |sync| sync.finished_at.to_string());
+        println!("{}\t{}", kind.as_str(), finished_at);
+    }
+}
+
+async fn resync(pool: &PgPool, user_id: i64) {
+    find_identity(pool, user_id).await;
+    for kind in RelationKind::ALL {
+        SyncRequest::save(pool, user_id, kind)
+            .await
+            .unwrap_or_else(|e| fail(e));
+    }
+    audit(pool, user_id, AuditAction::Resync).await;
+    println!("requested a resync of {}", user_id);
+}
+
+async fn purge(pool: &PgPool, user_id: i64) {
+    if !Identity::purge(pool, user_id)
+        .await
+        .unwrap_or_else(|e| fail(e))
+    {
+        eprintln!("no such user: {}", user_id);
+        std::process::exit(1);
+    }
+    audit(pool, user_id, AuditAction::Purge).await;
+    println!("purged {}", user_id);
+}
+
+async fn set_role(pool: &PgPool, account_id: i64, role: &str) {
+    if role != "user" && role != ADMIN_ROLE {
+        usage();
+    }
+    // Recorded in the audit log of every identity of the account.
+    let parameters = format!(
+        r#"{{"via": "cli", "account_id": {}, "role": "{}"}}"#,
+        account_id, role
+    );
+    let mut tx = pool.begin().await.unwrap_or_else(|e| fail(e));
+    if !Account::set_role(&mut tx, account_id, role)
+        .await
+        .unwrap_or_else(|e| fail(e))
+    {
+        eprintln!("no such account: {}", account_id);
+        std::process::exit(1);
+    }
+    for identity in Identity::find_by_account_id(&mut tx, account_id)
+        .await
+        .unwrap_or_else(|e| fail(e))
+    {
+        let record = AuditRecord::new(identity.id, AuditActor::Admin, AuditAction::SetRole)
+            .parameters(parameters.clone());
+        AuditLog::append(&mut tx, &record)
+            .await
+            .unwrap_or_else(|e| fail(e));
+    }
+    tx.commit().await.unwrap_or_else(|e| fail(e));
+    println!("account {} is now {}", account_id, role);
+}
+
+async fn migrate(pool: &PgPool) {
+    if Schema::baseline(pool).await.unwrap_or_else(|e| fail(e)) {
+        println!("recorded the existing tables as migration 1");
+    }
+    let applied = Schema::migrate(pool).await.unwrap_or_else(|e| fail(e));
+    for migration in &applied {
+        println!("applied {} {}", migration.version, migration.name);
+    }
+    let versions = Schema::applied(pool).await.unwrap_or_else(|e| fail(e));
+    println!(
+        "the database is at version {}",
+        versions.last().copied().unwrap_or_default()
+    );
+}
+
+/// The keys the server encrypts the tokens of the identities with.
+fn token_cipher() -> TokenCipher {
+    let keys = std::env::var("TOKEN_KEYS").unwrap_or_else(|_| {
+        eprintln!("TOKEN_KEYS is not set");
+        std::process::exit(1);
+    });
+    TokenCipher::parse(&keys).unwrap_or_else(|| {
+        eprintln!("TOKEN_KEYS is invalid");
+        std::process::exit(1);
+    })
+}
+
+fn new_key(key_id: &str) {
+    if key_id.is_empty() || key_id.contains([':', ',']) {
+        usage();
+    }
+    println!("{}", TokenCipher::generate_key(key_id));
+}
+
+/// Run after a new key is put first in TOKEN_KEYS. The old keys can be removed once it is done.
+async fn rotate_keys(pool: &PgPool) {
+    let cipher = token_cipher();
+    let parameters = format!(r#"{{"via": "cli", "key_id": "{}"}}"#, cipher.key_id());
+    let mut tx = pool.begin().await.unwrap_or_else(|e| fail(e));
+    let rotated = Identity::rotate_tokens(&mut tx, &cipher)
+        .await
+        .unwrap_or_else(|e| fail(e));
+    for &user_id in &rotated {
+        let record = AuditRecord::new(user_id, AuditActor::Admin, AuditAction::RotateKeys)
+            .parameters(parameters.clone());
+        AuditLog::append(&mut tx, &record)
+            .await
+            .unwrap_or_else(|e| fail(e));
+    }
+    tx.commit().await.unwrap_or_else(|e| fail(e));
+    println!(
+        "encrypted the tokens of {} identities with the key {}",
+        rotated.len(),
+        cipher.key_id()
+    );
+}
+
+async fn print_candidates<T: FollowClient>(
+    pool: &PgPool,
+    client: T,
+    credentials: Credentials,
+    identity: Identity,
+) {
+    let user_id = identity.id;
+    let worker = FollowBackWorker::new(
+        pool.clone(),
+        client,
+        credentials,
+        EventBus::default(),
+        PacingPolicy::default(),
+    );
+    let ids = worker
+        .candidates(identity)
+        .await
+        .unwrap_or_else(|e| fail(e))
+        .into_iter()
+        .map(|id| id as i64)
+        .collect::<Vec<_>>();
+    let screen_names = Profile::find_by_ids(pool, &ids)
+        .await
+        .unwrap_or_else(|e| fail(e))
+        .into_iter()
+        .map(|p| (p.id, p.screen_name))
+        .collect::<BTreeMap<_, _>>();
+    println!("user_id\tscreen_name");
+    for id in &ids {
+        println!(
+            "{}\t{}",
+            id,
+            screen_names.get(id).map_or("-", |name| name.as_str())
+        );
+    }
+    eprintln!("{} candidates for {}", ids.len(), user_id);
+}
+
+/// Builds the client of the provider from the same environment variables as the server.
+async fn candidates(pool: &PgPool, user_id: i64) {
+    let identity = find_identity(pool, user_id).await;
+    match identity.provider.as_str() {
+        MASTODON => {
+            let redirect_uri = std::env::var("MASTODON_REDIRECT_URI")
+                .unwrap_or_else(|_| "http://localhost:8080/api/mastodon/callback".to_string());
+            let client = MastodonClient::new(pool.clone(), redirect_uri);
+            print_candidates(pool, client, Credentials::Mastodon, identity).await;
+        }
+        BLUESKY => {
+            let service = std::env::var("BLUESKY_SERVICE")
+                .unwrap_or_else(|_| "https://bsky.social".to_string());
+            let client = BlueskyClient::new(pool.clone(), service);
+            let credentials = Credentials::Bluesky(client.clone());
+            print_candidates(pool, client, credentials, identity).await;
+        }
+        _ if std::env::var("TWITTER_API").as_deref() == Ok("v2") => {
+            let client_id = std::env::var("OAUTH2_CLIENT_ID").expect("OAUTH2_CLIENT_ID is not set");
+            let client_secret = std::env::var("OAUTH2_CLIENT_SECRET").ok();
+            let redirect_uri =
+                std::env::var("OAUTH2_REDIRECT_URI").expect("OAUTH2_REDIRECT_URI is not set");
+            let client =
+                TwitterV2Client::new(OAuth2Config::new(client_id, client_secret, redirect_uri));
+            let credentials = Credentials::OAuth2(client.clone());
+            print_candidates(pool, client, credentials, identity).await;
+        }
+        _ => {
+            let api_key = std::env::var("API_KEY").expect("API_KEY is not set");
+            let api_secret = std::env::var("API_SECRET").expect("API_SECRET is not set");
+            let credentials = Credentials::OAuth1(KeyPair::new(api_key, api_secret));
+            print_candidates(pool, TwitterClient::default(), credentials, identity).await;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>();
+    if args.is_empty() {
+        usage();
+    }
+
+    if let ["new-key", key_id] = args.as_slice() {
+        new_key(key_id);
+        return;
+    }
+    if std::env::var("TOKEN_KEYS").is_ok() && !token_cipher().install() {
+        eprintln!("the token keys are already installed");
+        std::process::exit(1);
+    }
+
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    let pool = PgPool::connect(&database_url)
+        .await
+        .expect("SQL connection failure");
+
+    match args.as_slice() {
+        ["users"] => list_users(&pool).await,
+        ["sync-state", user_id] => show_sync_state(&pool, parse_id(user_id)).await,
+        ["resync", user_id] => resync(&pool, parse_id(user_id)).await,
+        ["purge", user_id, "--yes"] => purge(&pool, parse_id(user_id)).await,
+        ["purge", _] => {
+            eprintln!("purge deletes everything of the user. pass --yes to confirm.");
+            std::process::exit(2);
+        }
+        ["set-role", account_id, role] => set_role(&pool, parse_id(account_id), role).await,
+        ["migrate"] => migrate(&pool).await,
+        ["rotate-keys"] => rotate_keys(&pool).await,
+        ["candidates", user_id] => candidates(&pool, parse_id(user_id)).await,
+        _ => usage(),
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{stdout, BufWriter, Write};
+
+use fantastic_giggle_api::export::{export, ExportFormat, ExportSet};
+use fantastic_giggle_sql::PgPool;
+use futures_util::StreamExt;
+
+const USAGE: &str = "usage: fantastic-giggle-export <user_id> <followers|friends|mutuals|fans|non-followers|history> <csv|jsonl|graphml>";
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (user_id, set, format) = match args.as_slice() {
+        [user_id, set, format] => (user_id, set, format),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let user_id = user_id.parse::<i64>().unwrap_or_else(|_| {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    });
+    let (set, format) = match (set.parse::<ExportSet>(), format.parse::<ExportFormat>()) {
+        (Ok(set), Ok(format)) => (set, format),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    let pool = PgPool::connect(&database_url)
+        .await
+        .expect("SQL connection failure");
+
+    let stream = export(pool, user_id, set, format).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    futures_util::pin_mut!(stream);
+    let mut out = BufWriter::new(stdout().lock());
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(bytes) => out.write_all(&bytes).expect("failed to write"),
+            Err(e) => {
+                eprintln!("export failed: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    out.flush().expect("failed to write");
+}
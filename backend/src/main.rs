@@ -1,11 +1,134 @@
+use std::time::Duration;
+
 use actix_web::{web, App, HttpServer};
 use egg_mode::KeyPair;
 use fantastic_giggle_api::config_services;
-use fantastic_giggle_sql::PgPool;
+use fantastic_giggle_sql::{PgPool, RelationKind, TokenCipher};
 use fantastic_giggle_worker::{
-    FollowBackWorker, FollowersDataConnector, FriendsDataConnector, IdSynchronizer,
+    ActivityTracker, AuditRetentionWorker, BlueskyClient, Credentials, EventBus, FollowBackWorker,
+    FollowClient, IdSynchronizer, IdsClient, Ingestion, MastodonClient, MessageClient,
+    ModerationClient, OAuth2Config, PacingPolicy, RelationDataConnector, RestartPolicy,
+    SpamModerationWorker, Supervisor, TwitterClient, TwitterV2Client, WelcomeMessageWorker,
+    DEFAULT_AUDIT_LOG_RETENTION,
 };
 
+const SYNCHRONIZERS: &[(&str, RelationKind, Ingestion)] = &[
+    // Followers and friends can be millions of rows, so they are merged in bulk.
+    ("followers", RelationKind::Follower, Ingestion::Staged),
+    ("friends", RelationKind::Friend, Ingestion::Staged),
+    ("blocking", RelationKind::Blocking, Ingestion::Upsert),
+    ("muting", RelationKind::Muting, Ingestion::Upsert),
+    (
+        "incoming_requests",
+        RelationKind::IncomingRequest,
+        Ingestion::Upsert,
+    ),
+    (
+        "outgoing_requests",
+        RelationKind::OutgoingRequest,
+        Ingestion::Upsert,
+    ),
+    (
+        "list_memberships",
+        RelationKind::ListMembership,
+        Ingestion::Upsert,
+    ),
+];
+
+/// Runs the synchronizers of the relation kinds supported by the client and the follow-back
+/// worker under the supervisor, with the names prefixed by `prefix`.
+fn spawn_workers<T>(
+    supervisor: &Supervisor,
+    prefix: &str,
+    client: T,
+    credentials: Credentials,
+    pool: &PgPool,
+    events: &EventBus,
+) where
+    T: IdsClient + FollowClient + Clone + 'static,
+{
+    for &(name, kind, ingestion) in SYNCHRONIZERS {
+        if !client.supports(kind) {
+            continue;
+        }
+        let client = client.clone();
+        let credentials = credentials.clone();
+        let pool = pool.clone();
+        let events = events.clone();
+        supervisor.spawn(&format!("{}{}", prefix, name), move || {
+            let connector =
+                RelationDataConnector::new(client.clone(), pool.clone(), kind, ingestion);
+            let synchronizer =
+                IdSynchronizer::new(credentials.clone(), pool.clone(), connector, events.clone());
+            async move {
+                synchronizer.run().await;
+                Ok(())
+            }
+        });
+    }
+
+    let pool = pool.clone();
+    let events = events.clone();
+    supervisor.spawn(&format!("{}follow_back", prefix), move || {
+        let follow_back = FollowBackWorker::new(
+            pool.clone(),
+            client.clone(),
+            credentials.clone(),
+            events.clone(),
+            PacingPolicy::default(),
+        );
+        async move {
+            follow_back.run().await;
+            Ok(())
+        }
+    });
+}
+
+/// Runs the workers which only Twitter supports.
+fn spawn_twitter_workers<T>(
+    supervisor: &Supervisor,
+    client: T,
+    credentials: Credentials,
+    pool: &PgPool,
+    events: &EventBus,
+) where
+    T: FollowClient + MessageClient + ModerationClient + Clone + 'static,
+{
+    {
+        let client = client.clone();
+        let credentials = credentials.clone();
+        let pool = pool.clone();
+        let events = events.clone();
+        supervisor.spawn("welcome_message", move || {
+            let worker = WelcomeMessageWorker::new(
+                pool.clone(),
+                client.clone(),
+                credentials.clone(),
+                events.clone(),
+            );
+            async move {
+                worker.run().await;
+                Ok(())
+            }
+        });
+    }
+
+    let pool = pool.clone();
+    let events = events.clone();
+    supervisor.spawn("spam_moderation", move || {
+        let worker = SpamModerationWorker::new(
+            pool.clone(),
+            client.clone(),
+            credentials.clone(),
+            events.clone(),
+        );
+        async move {
+            worker.run().await;
+            Ok(())
+        }
+    });
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
@@ -15,46 +138,137 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("SQL connection failure");
 
+    // `<key ID>:<base64 of 32 bytes>` separated by commas. The first key encrypts the tokens of
+    // the identities, the others only decrypt until `fantastic-giggle-admin rotate-keys` is run.
+    let token_keys = std::env::var("TOKEN_KEYS").expect("TOKEN_KEYS is not set");
+    let installed = TokenCipher::parse(&token_keys)
+        .expect("TOKEN_KEYS is invalid")
+        .install();
+    assert!(installed, "the token keys are already installed");
+
     let api_key = std::env::var("API_KEY").expect("API_KEY is not set");
     let api_secret = std::env::var("API_SECRET").expect("API_SECRET is not set");
     let consumer = KeyPair::new(api_key, api_secret);
 
-    let pool1 = pool.clone();
-    let consumer1 = consumer.clone();
-    let followers = tokio::spawn(async move {
-        let synchronizer =
-            IdSynchronizer::new(consumer1, pool1.clone(), FollowersDataConnector::new(pool1));
-        synchronizer.run().await;
-    });
+    let supervisor = Supervisor::new(RestartPolicy::default());
+    let events = EventBus::default();
+    let tracker = ActivityTracker::spawn(&events);
 
-    let pool1 = pool.clone();
-    let consumer1 = consumer.clone();
-    let friends = tokio::spawn(async move {
-        let synchronizer =
-            IdSynchronizer::new(consumer1, pool1.clone(), FriendsDataConnector::new(pool1));
-        synchronizer.run().await;
-    });
+    // The v1.1 API is used unless the deployment opts into the v2 API with OAuth 2.0.
+    let twitter_v2 = match std::env::var("TWITTER_API").as_deref() {
+        Ok("v2") => {
+            let client_id = std::env::var("OAUTH2_CLIENT_ID").expect("OAUTH2_CLIENT_ID is not set");
+            let client_secret = std::env::var("OAUTH2_CLIENT_SECRET").ok();
+            let redirect_uri =
+                std::env::var("OAUTH2_REDIRECT_URI").expect("OAUTH2_REDIRECT_URI is not set");
+            let client =
+                TwitterV2Client::new(OAuth2Config::new(client_id, client_secret, redirect_uri));
+            let credentials = Credentials::OAuth2(client.clone());
+            spawn_workers(
+                &supervisor,
+                "",
+                client.clone(),
+                credentials.clone(),
+                &pool,
+                &events,
+            );
+            spawn_twitter_workers(&supervisor, client.clone(), credentials, &pool, &events);
+            Some(client)
+        }
+        _ => {
+            let credentials = Credentials::OAuth1(consumer.clone());
+            spawn_workers(
+                &supervisor,
+                "",
+                TwitterClient::default(),
+                credentials.clone(),
+                &pool,
+                &events,
+            );
+            spawn_twitter_workers(
+                &supervisor,
+                TwitterClient::default(),
+                credentials,
+                &pool,
+                &events,
+            );
+            None
+        }
+    };
 
-    let pool1 = pool.clone();
-    let consumer1 = consumer.clone();
-    let follow_back = tokio::spawn(async move {
-        let follow_back = FollowBackWorker::new(pool1, consumer1);
-        follow_back.run().await;
-    });
+    let redirect_uri = std::env::var("MASTODON_REDIRECT_URI")
+        .unwrap_or_else(|_| "http://localhost:8080/api/mastodon/callback".to_string());
+    let mastodon = MastodonClient::new(pool.clone(), redirect_uri);
+    spawn_workers(
+        &supervisor,
+        "mastodon_",
+        mastodon.clone(),
+        Credentials::Mastodon,
+        &pool,
+        &events,
+    );
+
+    let service =
+        std::env::var("BLUESKY_SERVICE").unwrap_or_else(|_| "https://bsky.social".to_string());
+    let bluesky = BlueskyClient::new(pool.clone(), service);
+    spawn_workers(
+        &supervisor,
+        "bluesky_",
+        bluesky.clone(),
+        Credentials::Bluesky(bluesky.clone()),
+        &pool,
+        &events,
+    );
 
-    HttpServer::new(move || {
+    let retention = std::env::var("AUDIT_LOG_RETENTION_DAYS")
+        .ok()
+        .map(|days| {
+            let days = days
+                .parse::<u64>()
+                .expect("AUDIT_LOG_RETENTION_DAYS is not a number");
+            Duration::from_secs(days * 24 * 60 * 60)
+        })
+        .unwrap_or(DEFAULT_AUDIT_LOG_RETENTION);
+    {
+        let pool = pool.clone();
+        supervisor.spawn("audit_retention", move || {
+            let worker = AuditRetentionWorker::new(pool.clone(), retention);
+            async move {
+                worker.run().await;
+                Ok(())
+            }
+        });
+    }
+
+    let supervisor_handle = supervisor.handle();
+    let server = HttpServer::new(move || {
         let consumer = consumer.clone();
         let pool = pool.clone();
-        App::new()
+        let events = events.clone();
+        let tracker = tracker.clone();
+        let supervisor = supervisor_handle.clone();
+        let mut app = App::new()
             .configure(config_services)
             .app_data(web::Data::new(consumer))
             .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(events))
+            .app_data(web::Data::new(tracker))
+            .app_data(web::Data::new(supervisor))
+            .app_data(web::Data::new(mastodon.clone()))
+            .app_data(web::Data::new(bluesky.clone()));
+        if let Some(client) = twitter_v2.clone() {
+            app = app.app_data(web::Data::new(client));
+        }
+        app
     })
     .bind(("0.0.0.0", 8080))?
-    .run()
-    .await?;
-    followers.await.unwrap();
-    friends.await.unwrap();
-    follow_back.await.unwrap();
-    Ok(())
+    .run();
+
+    tokio::select! {
+        result = server => result,
+        e = supervisor.wait() => {
+            log::error!("shutting down: {:?}", e);
+            Err(std::io::Error::other(e.to_string()))
+        }
+    }
 }
@@ -8,3 +8,23 @@ pub async fn connect_to_test_sql() -> Result<PgPool> {
         .await?;
     Ok(pool)
 }
+
+/// Connects to an empty database of the name, created next to the test database, for the tests
+/// which would change the rows of the others.
+pub async fn create_scratch_sql(name: &str) -> Result<PgPool> {
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    let pool = PgPool::connect(&database_url).await?;
+    sqlx::query(&format!(
+        r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE)"#,
+        name
+    ))
+    .execute(&pool)
+    .await?;
+    sqlx::query(&format!(r#"CREATE DATABASE "{}""#, name))
+        .execute(&pool)
+        .await?;
+    let (server, _) = database_url
+        .rsplit_once('/')
+        .expect("DATABASE_URL has no database name");
+    PgPool::connect(&format!("{}/{}", server, name)).await
+}
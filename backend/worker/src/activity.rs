@@ -0,0 +1,77 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{current_seconds, EventBus, WorkerEvent};
+
+/// What the workers are doing for a user, as far as the events tell.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UserActivity {
+    /// Followers left in the current follow-back round.
+    pub follow_back_queued: usize,
+    /// The workers which are rate-limited for the user, and until when in unix seconds.
+    pub rate_limited_until: BTreeMap<&'static str, i64>,
+    pub last_event_at: Option<i64>,
+}
+
+/// Keeps the latest activity of each user in memory, from the events of the workers.
+#[derive(Clone, Default)]
+pub struct ActivityTracker {
+    users: Arc<Mutex<BTreeMap<i64, UserActivity>>>,
+}
+
+impl ActivityTracker {
+    /// Starts tracking the events published from now on.
+    pub fn spawn(events: &EventBus) -> Self {
+        let tracker = Self::default();
+        let mut receiver = events.subscribe();
+        let users = tracker.users.clone();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => record(&mut users.lock().unwrap(), &event),
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("activity tracker lagged: {} events skipped", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+        tracker
+    }
+
+    /// Rate limits which have already expired are left out.
+    pub fn get(&self, user_id: i64) -> UserActivity {
+        let mut activity = self
+            .users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default();
+        let now = current_seconds();
+        activity.rate_limited_until.retain(|_, until| *until > now);
+        activity
+    }
+}
+
+fn record(users: &mut BTreeMap<i64, UserActivity>, event: &WorkerEvent) {
+    let activity = users.entry(event.user_id()).or_default();
+    activity.last_event_at = Some(current_seconds());
+    match event {
+        WorkerEvent::RateLimited { worker, until, .. } => {
+            activity.rate_limited_until.insert(worker, *until);
+        }
+        WorkerEvent::FollowBackQueued { queued, .. } => {
+            activity.follow_back_queued = *queued;
+        }
+        WorkerEvent::Followed { .. } | WorkerEvent::FollowFailed { .. } => {
+            activity.follow_back_queued = activity.follow_back_queued.saturating_sub(1);
+        }
+        _ => {}
+    }
+}
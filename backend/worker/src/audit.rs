@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use fantastic_giggle_sql::{AuditLog, OffsetDateTime, PgPool};
+use tokio::time::sleep;
+
+const ARCHIVE_BATCH_SIZE: i64 = 10000;
+const ROUND_PAUSE: Duration = Duration::from_secs(24 * 60 * 60);
+pub const DEFAULT_AUDIT_LOG_RETENTION: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// Moves the audit log entries older than the retention to the archive once a day.
+pub struct AuditRetentionWorker {
+    pool: PgPool,
+    retention: Duration,
+}
+
+impl AuditRetentionWorker {
+    pub fn new(pool: PgPool, retention: Duration) -> Self {
+        Self { pool, retention }
+    }
+
+    pub async fn run(&self) {
+        loop {
+            match self.archive().await {
+                Ok(archived) => log::info!("archived {} audit log entries", archived),
+                Err(e) => log::error!("database error: {:?}", e),
+            }
+            sleep(ROUND_PAUSE).await;
+        }
+    }
+
+    /// Archives in batches, so that a large backlog doesn't hold one long transaction.
+    async fn archive(&self) -> Result<u64, fantastic_giggle_sql::Error> {
+        let before = OffsetDateTime::now_utc() - self.retention;
+        let mut archived = 0;
+        loop {
+            let moved = AuditLog::archive(&self.pool, before, ARCHIVE_BATCH_SIZE).await?;
+            archived += moved;
+            if moved < ARCHIVE_BATCH_SIZE as u64 {
+                return Ok(archived);
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{ExternalAccount, OffsetDateTime, PgPool, Profile, RelationKind};
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    http::{bearer, database_error, parse_created_at, send, to_i32, RateLimitHeader, ResetFormat},
+    FollowClient, IdsClient, IdsPage, LiveRelation,
+};
+
+pub const BLUESKY: &str = "bluesky";
+const PROFILE_LOOKUP_LIMIT: usize = 25;
+
+const RATE_LIMIT: RateLimitHeader = RateLimitHeader {
+    name: "ratelimit-reset",
+    format: ResetFormat::UnixSeconds,
+    default_wait: 5 * 60,
+};
+const BEARER_NEEDED: &str = "Bluesky needs an access JWT";
+
+/// The AT Protocol XRPC API of a Bluesky service. Accounts are identified by their DIDs, which
+/// are stored as external accounts.
+#[derive(Debug, Clone)]
+pub struct BlueskyClient {
+    http: reqwest::Client,
+    pool: PgPool,
+    service: String,
+    pub page_size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlueskySession {
+    pub did: String,
+    pub access_jwt: String,
+    pub refresh_jwt: String,
+    /// Taken from the `exp` claim of the access token.
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionResponse {
+    did: String,
+    access_jwt: String,
+    refresh_jwt: String,
+}
+
+#[derive(Deserialize)]
+struct Actor {
+    did: String,
+}
+
+#[derive(Deserialize)]
+struct Actors {
+    #[serde(
+        alias = "followers",
+        alias = "follows",
+        alias = "blocks",
+        alias = "mutes"
+    )]
+    actors: Vec<Actor>,
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Profiles {
+    profiles: Vec<ProfileView>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileView {
+    did: String,
+    handle: String,
+    display_name: Option<String>,
+    description: Option<String>,
+    avatar: Option<String>,
+    #[serde(default)]
+    followers_count: i64,
+    #[serde(default)]
+    follows_count: i64,
+    created_at: Option<String>,
+    #[serde(default)]
+    viewer: Viewer,
+}
+
+/// The URIs of the follow records between the viewer and the actor.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Viewer {
+    following: Option<String>,
+    followed_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    exp: i64,
+}
+
+impl BlueskyClient {
+    pub fn new(pool: PgPool, service: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            pool,
+            service,
+            page_size: 100,
+        }
+    }
+
+    /// Logs in with a handle or an email address and an app password.
+    pub async fn create_session(
+        &self,
+        identifier: &str,
+        password: &str,
+    ) -> Result<BlueskySession, Error> {
+        let request = self
+            .http
+            .post(self.xrpc("com.atproto.server.createSession"))
+            .json(&json!({ "identifier": identifier, "password": password }));
+        Ok(to_session(send(request, &RATE_LIMIT).await?))
+    }
+
+    pub async fn refresh_session(&self, refresh_jwt: &str) -> Result<BlueskySession, Error> {
+        let request = self
+            .http
+            .post(self.xrpc("com.atproto.server.refreshSession"))
+            .bearer_auth(refresh_jwt);
+        Ok(to_session(send(request, &RATE_LIMIT).await?))
+    }
+
+    /// Fetches a page of the DIDs related to the actor, and the cursor of the next page.
+    pub async fn fetch_dids(
+        &self,
+        kind: RelationKind,
+        did: &str,
+        token: &Token,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<String>, Option<String>), Error> {
+        let (method, actor) = match kind {
+            RelationKind::Follower => ("app.bsky.graph.getFollowers", Some(did)),
+            RelationKind::Friend => ("app.bsky.graph.getFollows", Some(did)),
+            RelationKind::Blocking => ("app.bsky.graph.getBlocks", None),
+            RelationKind::Muting => ("app.bsky.graph.getMutes", None),
+            RelationKind::IncomingRequest
+            | RelationKind::OutgoingRequest
+            | RelationKind::ListMembership => {
+                return Err(Error::InvalidResponse(
+                    "not supported by Bluesky",
+                    Some(kind.as_str().to_string()),
+                ))
+            }
+        };
+        let mut query = vec![("limit", self.page_size.to_string())];
+        if let Some(actor) = actor {
+            query.push(("actor", actor.to_string()));
+        }
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor.to_string()));
+        }
+        let request = self.get(method, token)?.query(&query);
+        let response: Actors = send(request, &RATE_LIMIT).await?;
+        let dids = response.actors.into_iter().map(|actor| actor.did).collect();
+        // An empty page may still carry a cursor, which would never end.
+        Ok((dids, response.cursor.filter(|cursor| !cursor.is_empty())))
+    }
+
+    /// Returns the relationships of the viewer with the given actors by DID.
+    pub async fn fetch_relations(
+        &self,
+        token: &Token,
+        dids: &[String],
+    ) -> Result<BTreeMap<String, LiveRelation>, Error> {
+        let profiles = self.fetch_profiles(token, dids).await?;
+        Ok(profiles
+            .into_iter()
+            .map(|p| {
+                let relation = LiveRelation {
+                    followed_by: p.viewer.followed_by.is_some(),
+                    following: p.viewer.following.is_some(),
+                };
+                (p.did, relation)
+            })
+            .collect())
+    }
+
+    /// Creates an `app.bsky.graph.follow` record in the repository of the user.
+    pub async fn follow_did(&self, token: &Token, did: &str, subject: &str) -> Result<(), Error> {
+        let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let request = self
+            .http
+            .post(self.xrpc("com.atproto.repo.createRecord"))
+            .bearer_auth(bearer(token, BEARER_NEEDED)?)
+            .json(&json!({
+                "repo": did,
+                "collection": "app.bsky.graph.follow",
+                "record": {
+                    "$type": "app.bsky.graph.follow",
+                    "subject": subject,
+                    "createdAt": created_at,
+                },
+            }));
+        let _: serde_json::Value = send(request, &RATE_LIMIT).await?;
+        Ok(())
+    }
+
+    async fn fetch_profiles(
+        &self,
+        token: &Token,
+        dids: &[String],
+    ) -> Result<Vec<ProfileView>, Error> {
+        let mut profiles = vec![];
+        for chunk in dids.chunks(PROFILE_LOOKUP_LIMIT) {
+            let query = chunk
+                .iter()
+                .map(|did| ("actors", did.as_str()))
+                .collect::<Vec<_>>();
+            let request = self.get("app.bsky.actor.getProfiles", token)?.query(&query);
+            let response: Profiles = send(request, &RATE_LIMIT).await?;
+            profiles.extend(response.profiles);
+        }
+        Ok(profiles)
+    }
+
+    async fn accounts(&self, ids: &[i64]) -> Result<BTreeMap<String, i64>, Error> {
+        Ok(ExternalAccount::find_by_ids(&self.pool, ids)
+            .await
+            .map_err(database_error)?
+            .into_iter()
+            .map(|account| (account.external_id, account.id))
+            .collect())
+    }
+
+    async fn did(&self, id: i64) -> Result<String, Error> {
+        ExternalAccount::find_by_id(&self.pool, id)
+            .await
+            .map_err(database_error)?
+            .map(|account| account.external_id)
+            .ok_or(Error::MissingValue("account"))
+    }
+
+    fn xrpc(&self, method: &str) -> String {
+        format!("{}/xrpc/{}", self.service, method)
+    }
+
+    fn get(&self, method: &str, token: &Token) -> Result<RequestBuilder, Error> {
+        Ok(self
+            .http
+            .get(self.xrpc(method))
+            .bearer_auth(bearer(token, BEARER_NEEDED)?))
+    }
+}
+
+#[async_trait]
+impl IdsClient for BlueskyClient {
+    fn supports(&self, kind: RelationKind) -> bool {
+        matches!(
+            kind,
+            RelationKind::Follower
+                | RelationKind::Friend
+                | RelationKind::Blocking
+                | RelationKind::Muting
+        )
+    }
+
+    async fn fetch_ids(
+        &self,
+        kind: RelationKind,
+        user_id: i64,
+        token: &Token,
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error> {
+        let did = self.did(user_id).await?;
+        let (dids, next) = self.fetch_dids(kind, &did, token, cursor).await?;
+        let ids = ExternalAccount::resolve(&self.pool, BLUESKY, "", &dids)
+            .await
+            .map_err(database_error)?;
+        Ok((ids, next))
+    }
+}
+
+#[async_trait]
+impl FollowClient for BlueskyClient {
+    async fn lookup_relations(
+        &self,
+        _: i64,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Option<BTreeMap<u64, LiveRelation>>, Error> {
+        let ids = user_ids.iter().map(|&id| id as i64).collect::<Vec<_>>();
+        let accounts = self.accounts(&ids).await?;
+        let dids = accounts.keys().cloned().collect::<Vec<_>>();
+        let relations = self.fetch_relations(token, &dids).await?;
+        Ok(Some(
+            relations
+                .into_iter()
+                .filter_map(|(did, relation)| Some((*accounts.get(&did)? as u64, relation)))
+                .collect(),
+        ))
+    }
+
+    async fn lookup_profiles(
+        &self,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Vec<Profile>, Error> {
+        let ids = user_ids.iter().map(|&id| id as i64).collect::<Vec<_>>();
+        let accounts = self.accounts(&ids).await?;
+        let dids = accounts.keys().cloned().collect::<Vec<_>>();
+        let profiles = self.fetch_profiles(token, &dids).await?;
+        Ok(profiles
+            .into_iter()
+            .filter_map(|profile| Some(to_profile(*accounts.get(&profile.did)?, profile)))
+            .collect())
+    }
+
+    async fn follow(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        let did = self.did(user_id).await?;
+        let subject = self.did(target_id as i64).await?;
+        self.follow_did(token, &did, &subject).await
+    }
+}
+
+fn to_session(response: SessionResponse) -> BlueskySession {
+    let expires_at = jwt_expiration(&response.access_jwt)
+        .unwrap_or_else(|| OffsetDateTime::now_utc() + Duration::from_secs(60 * 60));
+    BlueskySession {
+        did: response.did,
+        access_jwt: response.access_jwt,
+        refresh_jwt: response.refresh_jwt,
+        expires_at,
+    }
+}
+
+fn jwt_expiration(jwt: &str) -> Option<OffsetDateTime> {
+    let payload = jwt.split('.').nth(1)?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    OffsetDateTime::from_unix_timestamp(claims.exp).ok()
+}
+
+fn to_profile(id: i64, profile: ProfileView) -> Profile {
+    let account_created_at = parse_created_at(profile.created_at.as_deref());
+    Profile {
+        id,
+        screen_name: profile.handle,
+        name: profile.display_name.unwrap_or_default(),
+        description: profile.description.unwrap_or_default(),
+        followers_count: to_i32(profile.followers_count),
+        friends_count: to_i32(profile.follows_count),
+        default_profile_image: profile.avatar.is_none(),
+        // Bluesky has no protected accounts.
+        protected: false,
+        account_created_at,
+        updated_at: OffsetDateTime::now_utc(),
+    }
+}
@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use async_trait::async_trait;
+use egg_mode::{error::Error, KeyPair, Token};
+use fantastic_giggle_sql::{
+    Identity, OffsetDateTime, PgPool, Postgres, Profile, RelationKind, Transaction,
+};
+
+use crate::{BlueskyClient, TwitterV2Client, BLUESKY, MASTODON};
+
+/// A cursor-paged page of IDs, and the cursor of the next page if there is one.
+pub type IdsPage = (Vec<i64>, Option<String>);
+
+/// Fetches a page of a cursor-paged ID set.
+#[async_trait]
+pub trait IdsClient: Send + Sync {
+    fn supports(&self, _kind: RelationKind) -> bool {
+        true
+    }
+    /// Checks that the token is still accepted before a synchronization starts.
+    async fn verify_token(&self, _token: &Token) -> Result<(), Error> {
+        Ok(())
+    }
+    /// `cursor` is `None` for the first page.
+    async fn fetch_ids(
+        &self,
+        kind: RelationKind,
+        user_id: i64,
+        token: &Token,
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error>;
+}
+
+/// The relationship of the user with an account, as the provider reports it now.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LiveRelation {
+    /// The account follows the user.
+    pub followed_by: bool,
+    /// The user follows the account or requested to.
+    pub following: bool,
+}
+
+/// The operations the follow-back worker needs.
+#[async_trait]
+pub trait FollowClient: Send + Sync {
+    /// Looks up the relationships of the user with the given accounts, which the synchronized
+    /// ones can be hours behind. The accounts missing from the result are not followed. `None`
+    /// when the provider can't look them up, and the synchronized relationships are trusted.
+    async fn lookup_relations(
+        &self,
+        _user_id: i64,
+        _token: &Token,
+        _user_ids: &[u64],
+    ) -> Result<Option<BTreeMap<u64, LiveRelation>>, Error> {
+        Ok(None)
+    }
+    async fn lookup_profiles(&self, token: &Token, user_ids: &[u64])
+        -> Result<Vec<Profile>, Error>;
+    async fn follow(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error>;
+}
+
+/// Sends direct messages.
+#[async_trait]
+pub trait MessageClient: Send + Sync {
+    async fn send_message(
+        &self,
+        user_id: i64,
+        token: &Token,
+        recipient_id: u64,
+        text: &str,
+    ) -> Result<(), Error>;
+}
+
+/// Mutes and blocks other users.
+#[async_trait]
+pub trait ModerationClient: Send + Sync {
+    async fn mute(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error>;
+    async fn unmute(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error>;
+    async fn block(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error>;
+    async fn unblock(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error>;
+}
+
+/// How the tokens stored in the `identity` table are turned into API tokens.
+#[derive(Clone)]
+pub enum Credentials {
+    /// OAuth 1.0a with the consumer key of the app, for the v1.1 API.
+    OAuth1(KeyPair),
+    /// OAuth 2.0 user context, for the v2 API.
+    OAuth2(TwitterV2Client),
+    /// Mastodon access tokens, which don't expire.
+    Mastodon,
+    /// Bluesky access and refresh JWTs.
+    Bluesky(BlueskyClient),
+}
+
+impl Credentials {
+    /// The provider of the users these credentials are for.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            Credentials::OAuth1(_) | Credentials::OAuth2(_) => "twitter",
+            Credentials::Mastodon => MASTODON,
+            Credentials::Bluesky(_) => BLUESKY,
+        }
+    }
+
+    /// Returns the token of the identity, refreshing and saving it first if it is about to
+    /// expire.
+    pub async fn token(&self, pool: &PgPool, identity: Identity) -> anyhow::Result<Token> {
+        match self {
+            Credentials::OAuth1(consumer) => Ok(Token::Access {
+                consumer: consumer.clone(),
+                access: KeyPair::new(identity.access_key, identity.access_secret),
+            }),
+            Credentials::OAuth2(client) => {
+                if !expires_soon(&identity) {
+                    return Ok(Token::Bearer(identity.access_key));
+                }
+
+                let mut tx = pool.begin().await?;
+                let identity = lock_identity(&mut tx, identity.id).await?;
+                // Another worker may have refreshed it while this one waited for the lock.
+                if !expires_soon(&identity) {
+                    return Ok(Token::Bearer(identity.access_key));
+                }
+                let token = client.refresh_token(&identity.access_secret).await?;
+                Identity::save_token(
+                    &mut tx,
+                    identity.id,
+                    &token.access_token,
+                    &token.refresh_token,
+                    Some(token.expires_at),
+                )
+                .await?;
+                tx.commit().await?;
+                Ok(Token::Bearer(token.access_token))
+            }
+            Credentials::Mastodon => Ok(Token::Bearer(identity.access_key)),
+            Credentials::Bluesky(client) => {
+                if !expires_soon(&identity) {
+                    return Ok(Token::Bearer(identity.access_key));
+                }
+
+                let mut tx = pool.begin().await?;
+                let identity = lock_identity(&mut tx, identity.id).await?;
+                if !expires_soon(&identity) {
+                    return Ok(Token::Bearer(identity.access_key));
+                }
+                let session = client.refresh_session(&identity.access_secret).await?;
+                Identity::save_token(
+                    &mut tx,
+                    identity.id,
+                    &session.access_jwt,
+                    &session.refresh_jwt,
+                    Some(session.expires_at),
+                )
+                .await?;
+                tx.commit().await?;
+                Ok(Token::Bearer(session.access_jwt))
+            }
+        }
+    }
+}
+
+/// Refresh tokens are single use, so they are refreshed a minute ahead under a row lock.
+fn expires_soon(identity: &Identity) -> bool {
+    let expires_soon = OffsetDateTime::now_utc() + Duration::from_secs(60);
+    identity
+        .token_expires_at
+        .is_none_or(|at| at <= expires_soon)
+}
+
+async fn lock_identity(
+    tx: &mut Transaction<'static, Postgres>,
+    id: i64,
+) -> anyhow::Result<Identity> {
+    Identity::find_by_id_for_update(&mut *tx, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("identity {} no longer exists", id))
+}
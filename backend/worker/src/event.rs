@@ -0,0 +1,94 @@
+use serde::Serialize;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorkerEvent {
+    SyncPageFetched {
+        user_id: i64,
+        kind: &'static str,
+        pages: u32,
+        fetched: usize,
+        next_cursor: Option<String>,
+    },
+    SyncCompleted {
+        user_id: i64,
+        kind: &'static str,
+        pages: u32,
+    },
+    RateLimited {
+        user_id: i64,
+        worker: &'static str,
+        until: i64,
+    },
+    /// A follow-back round queued the followers to follow.
+    FollowBackQueued {
+        user_id: i64,
+        queued: usize,
+    },
+    Followed {
+        user_id: i64,
+        target_id: i64,
+        dry_run: bool,
+    },
+    FollowFailed {
+        user_id: i64,
+        target_id: i64,
+    },
+    WelcomeMessageSent {
+        user_id: i64,
+        target_id: i64,
+        dry_run: bool,
+    },
+    WelcomeMessageFailed {
+        user_id: i64,
+        target_id: i64,
+    },
+    Moderated {
+        user_id: i64,
+        target_id: i64,
+        action: &'static str,
+        dry_run: bool,
+    },
+}
+
+impl WorkerEvent {
+    pub fn user_id(&self) -> i64 {
+        match self {
+            WorkerEvent::SyncPageFetched { user_id, .. }
+            | WorkerEvent::SyncCompleted { user_id, .. }
+            | WorkerEvent::RateLimited { user_id, .. }
+            | WorkerEvent::FollowBackQueued { user_id, .. }
+            | WorkerEvent::Followed { user_id, .. }
+            | WorkerEvent::FollowFailed { user_id, .. }
+            | WorkerEvent::WelcomeMessageSent { user_id, .. }
+            | WorkerEvent::WelcomeMessageFailed { user_id, .. }
+            | WorkerEvent::Moderated { user_id, .. } => *user_id,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: Sender<WorkerEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: WorkerEvent) {
+        // An error only means that nobody is listening at the moment.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> Receiver<WorkerEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -5,59 +5,102 @@ use std::{
 };
 
 use anyhow::Result;
-use egg_mode::{
-    user::{follow, relation_lookup, Connection},
-    KeyPair,
+use chrono::Utc;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, BlockList, FollowAction, FollowBackRequest,
+    FollowBackRules, Identity, OffsetDateTime, PgPool, Profile, RelationKind, Relationship,
+    UserSettings, WhiteList,
 };
-use fantastic_giggle_sql::{PgPool, Relationship, User};
 use rand::{prelude::SliceRandom, thread_rng};
 use tokio::time::sleep;
 
-use crate::Sortable;
+use crate::{
+    current_seconds, load_targets, Credentials, Decision, EventBus, Fact, FollowClient, Pacer,
+    PacingPolicy, QuietHours, RuleSet, Sortable, WorkerEvent,
+};
 
 const RELATION_LOOKUP_LIMIT: usize = 100;
-pub struct FollowBackWorker {
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+const REQUEST_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+struct FollowBackQueue {
+    user_id: i64,
+    token: Token,
+    dry_run: bool,
+    pacer: Pacer,
+    user_ids: Vec<u64>,
+    /// The time of the newest webhook request taken in the queue, deleted once it is done.
+    requested_until: Option<OffsetDateTime>,
+}
+
+pub struct FollowBackWorker<T> {
     pool: PgPool,
-    consumer: KeyPair,
+    client: T,
+    credentials: Credentials,
+    events: EventBus,
+    pacing: PacingPolicy,
 }
 
-impl FollowBackWorker {
-    pub fn new(pool: PgPool, consumer: KeyPair) -> Self {
-        Self { pool, consumer }
+impl<T: FollowClient> FollowBackWorker<T> {
+    pub fn new(
+        pool: PgPool,
+        client: T,
+        credentials: Credentials,
+        events: EventBus,
+        pacing: PacingPolicy,
+    ) -> Self {
+        Self {
+            pool,
+            client,
+            credentials,
+            events,
+            pacing,
+        }
     }
     pub async fn run(&self) {
+        let mut next_full_round = Instant::now();
+        // The users the webhook reported new followers of, when only they are woken up.
+        let mut woken: Option<BTreeSet<i64>> = None;
         loop {
-            log::info!("Start following back ...");
-            let users = match User::find_all(&self.pool).await {
-                Ok(users) => users,
-                Err(e) => {
-                    log::error!("database error: {:?}", e);
-                    sleep(Duration::from_secs(10)).await;
-                    continue;
+            let round_started_at = OffsetDateTime::now_utc();
+            let mut identities =
+                match Identity::find_by_provider(&self.pool, self.credentials.provider()).await {
+                    Ok(identities) => identities,
+                    Err(e) => {
+                        log::error!("database error: {:?}", e);
+                        sleep(Duration::from_secs(10)).await;
+                        continue;
+                    }
+                };
+            match woken.take() {
+                Some(user_ids) => {
+                    log::info!(
+                        "Start following back the new followers of {:?} ...",
+                        user_ids
+                    );
+                    identities.retain(|identity| user_ids.contains(&identity.id));
+                }
+                None => {
+                    log::info!("Start following back ...");
+                    next_full_round = Instant::now() + self.pacing.round_pause(&mut thread_rng());
                 }
-            };
+            }
 
             let mut heap = BinaryHeap::new();
-            for user in users {
-                let follow_back_user_ids = match fetch_follow_back_user_ids(
-                    &user,
-                    &self.pool,
-                    self.consumer.clone(),
-                )
-                .await
-                {
-                    Ok(user_ids) => user_ids,
+            for identity in identities {
+                let queue = match self.prepare_queue(identity).await {
+                    Ok(Some(queue)) => queue,
+                    Ok(None) => continue,
                     Err(e) => {
                         log::error!("{:?}", e);
                         continue;
                     }
                 };
-                let consumer = self.consumer.clone();
-                let access = KeyPair::new(user.access_key, user.access_secret);
-                let token = egg_mode::Token::Access { consumer, access };
+                let delay = queue.pacer.initial_delay(Utc::now(), &mut thread_rng());
                 heap.push(Sortable {
-                    key: Reverse(Instant::now()),
-                    data: ((token, follow_back_user_ids)),
+                    key: Reverse(Instant::now() + delay),
+                    data: queue,
                 });
             }
 
@@ -68,77 +111,308 @@ impl FollowBackWorker {
                     continue;
                 }
 
-                let (token, mut user_ids) = data;
-                let id = match user_ids.pop() {
+                let mut queue = data;
+                let user_id = queue.user_id;
+                if queue.pacer.is_quiet(Utc::now()) {
+                    let delay = queue.pacer.initial_delay(Utc::now(), &mut thread_rng());
+                    log::info!("quiet hours for {}. sleep {:?}.", user_id, delay);
+                    heap.push(Sortable {
+                        key: Reverse(Instant::now() + delay),
+                        data: queue,
+                    });
+                    continue;
+                }
+                // The requests are kept for the round after the user is resumed.
+                match Identity::is_active(&self.pool, user_id).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        log::info!("{} was paused or lost the tokens", user_id);
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("database error: {:?}", e);
+                        heap.push(Sortable {
+                            key: Reverse(Instant::now() + Duration::from_secs(10)),
+                            data: queue,
+                        });
+                        continue;
+                    }
+                }
+                let id = match queue.user_ids.pop() {
                     Some(id) => id,
-                    None => continue,
+                    None => {
+                        self.finish_requests(user_id, queue.requested_until).await;
+                        continue;
+                    }
                 };
 
-                match follow(id, false, &token).await {
+                if queue.dry_run {
+                    log::info!("dry run: would follow {} for {}", id, user_id);
+                    self.save_action(user_id, id, true, None).await;
+                    self.events.publish(WorkerEvent::Followed {
+                        user_id,
+                        target_id: id as i64,
+                        dry_run: true,
+                    });
+                    heap.push(Sortable {
+                        key: Reverse(Instant::now()),
+                        data: queue,
+                    });
+                    continue;
+                }
+
+                match self.client.follow(user_id, &queue.token, id).await {
                     Ok(_) => {
                         log::info!("followed {}", id);
+                        self.save_action(user_id, id, false, None).await;
+                        self.events.publish(WorkerEvent::Followed {
+                            user_id,
+                            target_id: id as i64,
+                            dry_run: false,
+                        });
+                        let delay = queue.pacer.next_delay(Utc::now(), &mut thread_rng());
+                        heap.push(Sortable {
+                            key: Reverse(Instant::now() + delay),
+                            data: queue,
+                        });
+                    }
+                    Err(Error::RateLimit(timestamp)) => {
+                        let sleep_duration = (timestamp as i64 - current_seconds()).max(0);
+                        log::info!("rate limit exceeded. sleep {} seconds.", sleep_duration);
+                        self.events.publish(WorkerEvent::RateLimited {
+                            user_id,
+                            worker: "follow_back",
+                            until: timestamp as i64,
+                        });
+                        queue.user_ids.push(id);
                         heap.push(Sortable {
-                            key: Reverse(Instant::now() + Duration::from_secs(60)),
-                            data: (token, user_ids),
+                            key: Reverse(
+                                Instant::now() + Duration::from_secs(sleep_duration as u64),
+                            ),
+                            data: queue,
                         });
                     }
                     Err(e) => {
                         log::error!("failed to follow: {:?}", e);
+                        self.save_action(user_id, id, false, Some(&e.to_string()))
+                            .await;
+                        self.events.publish(WorkerEvent::FollowFailed {
+                            user_id,
+                            target_id: id as i64,
+                        });
                     }
                 }
             }
 
-            log::info!("finished following back. sleeping 5 minutes");
-            sleep(Duration::from_secs(5 * 60)).await;
+            log::info!(
+                "finished following back. sleeping {:?}",
+                next_full_round.saturating_duration_since(Instant::now())
+            );
+            woken = self.pause(next_full_round, round_started_at).await;
         }
     }
-}
-async fn fetch_follow_back_user_ids(
-    user: &User,
-    pool: &PgPool,
-    consumer: KeyPair,
-) -> Result<Vec<u64>> {
-    let followers = Relationship::find_followers_by_source_id(pool, user.id).await?;
-    let friends = Relationship::find_friends_by_source_id(pool, user.id).await?;
-
-    let mut follower_ids = followers
-        .into_iter()
-        .map(|r| r.target_id)
-        .collect::<BTreeSet<_>>();
-    for friend in friends {
-        follower_ids.remove(&friend.target_id);
-    }
 
-    let mut following_ids = follower_ids
-        .into_iter()
-        .map(|id| id as u64)
-        .collect::<Vec<_>>();
-    following_ids.shuffle(&mut thread_rng());
-    following_ids.truncate(RELATION_LOOKUP_LIMIT);
-
-    let access = KeyPair::new(user.access_key.clone(), user.access_secret.clone());
-    let token = egg_mode::Token::Access { consumer, access };
-    let relationships = relation_lookup(following_ids, &token).await?;
-
-    let mut following_user_ids = vec![];
-    for relationship in relationships {
-        let mut is_followed_by = false;
-        let mut following = false;
-        for connection in &relationship.connections {
-            match connection {
-                Connection::FollowingReceived | Connection::FollowedBy => {
-                    is_followed_by = true;
-                }
-                Connection::Following | Connection::FollowingRequested => {
-                    following = true;
+    /// Sleeps until the next full round. Returns early with the users the webhook reported new
+    /// followers of since the last round started, so that only they are woken up.
+    async fn pause(&self, until: Instant, since: OffsetDateTime) -> Option<BTreeSet<i64>> {
+        while Instant::now() < until {
+            sleep(REQUEST_POLL_INTERVAL.min(until - Instant::now())).await;
+            match FollowBackRequest::find_source_ids(&self.pool, self.credentials.provider(), since)
+                .await
+            {
+                Ok(user_ids) if !user_ids.is_empty() => {
+                    return Some(user_ids.into_iter().collect())
                 }
-                _ => {}
+                Ok(_) => {}
+                Err(e) => log::error!("database error: {:?}", e),
+            }
+        }
+        None
+    }
+
+    /// Deletes the webhook requests which made it into a queue that has been worked through.
+    async fn finish_requests(&self, user_id: i64, requested_until: Option<OffsetDateTime>) {
+        if let Some(until) = requested_until {
+            if let Err(e) = FollowBackRequest::delete_until(&self.pool, user_id, until).await {
+                log::error!("database error: {:?}", e);
+            }
+        }
+    }
+
+    /// Returns the followers the user would follow back now, regardless of the daily cap, without
+    /// following anyone or taking the requests of the webhook.
+    pub async fn candidates(&self, identity: Identity) -> Result<Vec<u64>> {
+        let user_id = identity.id;
+        let settings = UserSettings::find_or_default(&self.pool, user_id).await?;
+        let token = self.credentials.token(&self.pool, identity).await?;
+        self.fetch_follow_back_user_ids(user_id, &token, &settings, &[])
+            .await
+    }
+
+    async fn prepare_queue(&self, identity: Identity) -> Result<Option<FollowBackQueue>> {
+        // The requests are kept until the queue is worked through, so that a failed round
+        // retries them. Dropped when following back is disabled or capped.
+        let requests = FollowBackRequest::find_by_source_id(&self.pool, identity.id).await?;
+        let requested_until = requests.last().map(|request| request.created_at);
+        let requested = requests
+            .into_iter()
+            .map(|request| request.target_id)
+            .collect::<Vec<_>>();
+        let settings = UserSettings::find_or_default(&self.pool, identity.id).await?;
+        if !settings.follow_back_enabled {
+            log::info!("follow back is disabled for {}", identity.id);
+            self.finish_requests(identity.id, requested_until).await;
+            return Ok(None);
+        }
+
+        let followed_today =
+            FollowAction::count_since(&self.pool, identity.id, OffsetDateTime::now_utc() - DAY)
+                .await?;
+        let remaining = (settings.daily_follow_cap as i64 - followed_today).max(0) as usize;
+        if remaining == 0 {
+            log::info!("daily follow cap reached for {}", identity.id);
+            self.finish_requests(identity.id, requested_until).await;
+            return Ok(None);
+        }
+
+        let user_id = identity.id;
+        let token = self.credentials.token(&self.pool, identity).await?;
+        let mut user_ids = self
+            .fetch_follow_back_user_ids(user_id, &token, &settings, &requested)
+            .await?;
+        user_ids.truncate(remaining);
+        self.events.publish(WorkerEvent::FollowBackQueued {
+            user_id,
+            queued: user_ids.len(),
+        });
+        let pacer = Pacer::new(self.pacing.clone(), QuietHours::from_settings(&settings));
+        Ok(Some(FollowBackQueue {
+            user_id,
+            token,
+            dry_run: settings.dry_run,
+            pacer,
+            user_ids,
+            requested_until,
+        }))
+    }
+
+    async fn save_action(&self, user_id: i64, target_id: u64, dry_run: bool, error: Option<&str>) {
+        let target_id = target_id as i64;
+        let record = AuditRecord::new(user_id, AuditActor::Worker, AuditAction::Follow)
+            .target(target_id)
+            .dry_run(dry_run)
+            .error(error);
+        let result = async {
+            let mut tx = self.pool.begin().await?;
+            FollowAction::save(&mut tx, user_id, target_id, dry_run, error).await?;
+            AuditLog::append(&mut tx, &record).await?;
+            tx.commit().await
+        };
+        if let Err(e) = result.await {
+            log::error!("database error: {:?}", e);
+        }
+    }
+    async fn fetch_follow_back_user_ids(
+        &self,
+        user_id: i64,
+        token: &Token,
+        settings: &UserSettings,
+        requested: &[i64],
+    ) -> Result<Vec<u64>> {
+        let pool = &self.pool;
+        let rules = match FollowBackRules::find_by_user_id(pool, user_id).await? {
+            Some(rules) => serde_json::from_str::<RuleSet>(&rules.rules)?,
+            None => RuleSet::from_settings(settings),
+        };
+        // The followers and the whitelisted accounts can be followed, except the ones followed or
+        // requested already, and the blocklisted, blocked or muted ones, which the rules can't
+        // override.
+        let mut candidate_ids =
+            Relationship::find_by_source_id(pool, user_id, RelationKind::Follower)
+                .await?
+                .into_iter()
+                .map(|relationship| relationship.target_id)
+                .collect::<BTreeSet<_>>();
+        for whitelisted in WhiteList::find_by_source_id(pool, user_id).await? {
+            candidate_ids.insert(whitelisted.target_id);
+        }
+        for kind in [
+            RelationKind::Friend,
+            RelationKind::OutgoingRequest,
+            RelationKind::Blocking,
+            RelationKind::Muting,
+        ] {
+            for relationship in Relationship::find_by_source_id(pool, user_id, kind).await? {
+                candidate_ids.remove(&relationship.target_id);
             }
         }
+        for blocked in BlockList::find_by_source_id(pool, user_id).await? {
+            candidate_ids.remove(&blocked.target_id);
+        }
+
+        // The followers reported by the webhook come first, then random ones.
+        let mut lookup_ids = requested
+            .iter()
+            .filter(|id| candidate_ids.remove(id))
+            .map(|&id| id as u64)
+            .collect::<Vec<_>>();
+        let mut others = candidate_ids
+            .into_iter()
+            .map(|id| id as u64)
+            .collect::<Vec<_>>();
+        others.shuffle(&mut thread_rng());
+        lookup_ids.extend(others);
+        lookup_ids.truncate(RELATION_LOOKUP_LIMIT);
+
+        // The synchronized relationships can be hours old, so the candidates who were followed
+        // since are dropped, and the ones who unfollowed are no longer taken as followers.
+        let relations = self
+            .client
+            .lookup_relations(user_id, token, &lookup_ids)
+            .await?;
+        if let Some(relations) = &relations {
+            lookup_ids.retain(|id| {
+                relations
+                    .get(id)
+                    .is_some_and(|relation| !relation.following)
+            });
+        }
 
-        if is_followed_by && !following {
-            following_user_ids.push(relationship.id);
+        let now = OffsetDateTime::now_utc();
+        let profiles = self.client.lookup_profiles(token, &lookup_ids).await?;
+        for profile in &profiles {
+            Profile::save(pool, profile).await?;
         }
+        let ids = profiles
+            .iter()
+            .map(|profile| profile.id)
+            .collect::<Vec<_>>();
+        let targets = load_targets(pool, user_id, &ids).await?;
+        let following_user_ids = profiles
+            .into_iter()
+            .filter(|profile| {
+                let mut target = targets.get(&profile.id).cloned().unwrap_or_default();
+                let relation = relations
+                    .as_ref()
+                    .and_then(|relations| relations.get(&(profile.id as u64)));
+                if let Some(relation) = relation {
+                    if relation.followed_by {
+                        target.facts.insert(Fact::Follower);
+                    } else {
+                        target.facts.remove(&Fact::Follower);
+                    }
+                }
+                match rules.evaluate(&target, now) {
+                    (Decision::Follow, _) => true,
+                    (Decision::Skip, rule) => {
+                        let rule = rule.map_or("no rule matched", |rule| rule.name.as_str());
+                        log::info!("skip {}: {}", profile.screen_name, rule);
+                        false
+                    }
+                }
+            })
+            .map(|profile| profile.id as u64)
+            .collect();
+        Ok(following_user_ids)
     }
-    Ok(following_user_ids)
 }
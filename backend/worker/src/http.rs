@@ -0,0 +1,85 @@
+use chrono::DateTime;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::OffsetDateTime;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::current_seconds;
+
+/// How a server reports when its rate limit resets.
+pub(crate) enum ResetFormat {
+    /// A Unix timestamp in seconds.
+    UnixSeconds,
+    /// An ISO 8601 timestamp.
+    Rfc3339,
+}
+
+/// The header of the rate limit reset, and how long to wait when it is missing.
+pub(crate) struct RateLimitHeader {
+    pub name: &'static str,
+    pub format: ResetFormat,
+    pub default_wait: i64,
+}
+
+/// Sends the request and parses the JSON body.
+pub(crate) async fn send<T: DeserializeOwned>(
+    request: RequestBuilder,
+    rate_limit: &RateLimitHeader,
+) -> Result<T, Error> {
+    let response = check(request.send().await.map_err(request_error)?, rate_limit)?;
+    let body = response.bytes().await.map_err(request_error)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Turns the rate limit and the other unsuccessful statuses into errors.
+pub(crate) fn check(response: Response, rate_limit: &RateLimitHeader) -> Result<Response, Error> {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let reset = response
+            .headers()
+            .get(rate_limit.name)
+            .and_then(|reset| reset.to_str().ok())
+            .and_then(|reset| match rate_limit.format {
+                ResetFormat::UnixSeconds => reset.parse::<i64>().ok(),
+                ResetFormat::Rfc3339 => DateTime::parse_from_rfc3339(reset)
+                    .ok()
+                    .map(|reset| reset.timestamp()),
+            })
+            .unwrap_or(current_seconds() + rate_limit.default_wait);
+        return Err(Error::RateLimit(to_i32(reset)));
+    }
+    if !status.is_success() {
+        return Err(Error::BadStatus(status));
+    }
+    Ok(response)
+}
+
+/// `message` tells which token the provider needs instead of an OAuth 1.0a one.
+pub(crate) fn bearer<'a>(token: &'a Token, message: &'static str) -> Result<&'a str, Error> {
+    match token {
+        Token::Bearer(token) => Ok(token),
+        Token::Access { .. } => Err(Error::InvalidResponse(message, None)),
+    }
+}
+
+pub(crate) fn request_error(e: reqwest::Error) -> Error {
+    Error::InvalidResponse("request failed", Some(e.to_string()))
+}
+
+pub(crate) fn database_error(e: fantastic_giggle_sql::Error) -> Error {
+    Error::InvalidResponse("database error", Some(e.to_string()))
+}
+
+/// Parses the creation time of a profile. Missing and invalid times are the Unix epoch.
+pub(crate) fn parse_created_at(created_at: Option<&str>) -> OffsetDateTime {
+    created_at
+        .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())
+        .and_then(|created_at| OffsetDateTime::from_unix_timestamp(created_at.timestamp()).ok())
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+/// Converts counts and timestamps to the 32 bits of the profiles and the rate limits, saturating
+/// instead of wrapping around.
+pub(crate) fn to_i32(value: i64) -> i32 {
+    i32::try_from(value).unwrap_or(if value < 0 { i32::MIN } else { i32::MAX })
+}
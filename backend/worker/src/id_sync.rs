@@ -1,29 +1,38 @@
-use std::{cmp::Reverse, collections::BinaryHeap, time::Duration};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
-use egg_mode::{
-    auth::verify_tokens,
-    cursor::{CursorIter, IDCursor},
-    error::Error,
-    user::{followers_ids, friends_ids},
-    KeyPair, Token,
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{
+    Error as SqlError, Identity, OffsetDateTime, PgPool, RelationKind, Relationship,
+    RelationshipSnapshot, RelationshipSync, SyncRequest,
 };
-use fantastic_giggle_sql::{PgPool, Relationship, User};
 use tokio::time::sleep;
 
-use crate::{current_seconds, Sortable};
+use crate::{current_seconds, Credentials, EventBus, IdsClient, IdsPage, Sortable, WorkerEvent};
+
+const SYNC_REQUEST_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// The longest wait before retrying a user after database errors, in seconds.
+const MAX_RETRY_DELAY: i64 = 300;
+
+type SyncQueue = BinaryHeap<Sortable<Reverse<i64>, SyncState>>;
 
 pub struct IdSynchronizer<C> {
-    consumer: KeyPair,
+    credentials: Credentials,
     pool: PgPool,
     connector: C,
+    events: EventBus,
 }
 impl<C> IdSynchronizer<C> {
-    pub fn new(consumer: KeyPair, pool: PgPool, connector: C) -> Self {
+    pub fn new(credentials: Credentials, pool: PgPool, connector: C, events: EventBus) -> Self {
         Self {
-            consumer,
+            credentials,
             pool,
             connector,
+            events,
         }
     }
 }
@@ -34,156 +43,378 @@ where
 {
     pub async fn run(&self) {
         loop {
-            let tokens = match User::find_all(&self.pool).await {
-                Ok(tokens) => tokens,
+            let identities =
+                match Identity::find_by_provider(&self.pool, self.credentials.provider()).await {
+                    Ok(identities) => identities,
+                    Err(e) => {
+                        log::error!("database error: {:?}", e);
+                        sleep(Duration::from_secs(10)).await;
+                        continue;
+                    }
+                };
+            if identities.is_empty() {
+                log::info!("No identities");
+                sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+
+            let started_at = match Relationship::sync_started_at(&self.pool).await {
+                Ok(started_at) => started_at,
                 Err(e) => {
                     log::error!("database error: {:?}", e);
                     sleep(Duration::from_secs(10)).await;
                     continue;
                 }
             };
-            if tokens.is_empty() {
-                log::info!("No tokens");
-                sleep(Duration::from_secs(10)).await;
-                continue;
+
+            let mut users = vec![];
+            for identity in identities {
+                let user_id = identity.id;
+                match self.credentials.token(&self.pool, identity).await {
+                    Ok(token) => users.push((user_id, token)),
+                    Err(e) => log::error!("failed to get the token of {}: {:?}", user_id, e),
+                }
             }
+            self.sync(users, started_at).await;
+        }
+    }
 
-            let mut heap = BinaryHeap::new();
-            for token in tokens {
-                let consumer = self.consumer.clone();
-                let user_id = token.id;
-                let access = KeyPair::new(token.access_key, token.access_secret);
-                let token = Token::Access { consumer, access };
-                heap.push(Sortable {
-                    key: (Reverse(0)),
-                    data: (user_id, token, -1),
-                });
+    /// Synchronizes all the pages of the given users once.
+    pub async fn sync(&self, users: Vec<(i64, Token)>, started_at: OffsetDateTime) {
+        let kind = self.connector.kind().as_str();
+        let mut heap = SyncQueue::new();
+        for (user_id, token) in users {
+            heap.push(Sortable {
+                key: Reverse(0),
+                data: SyncState {
+                    user_id,
+                    token,
+                    next_cursor: None,
+                    pages: 0,
+                    started_at,
+                    failures: 0,
+                },
+            });
+        }
+
+        let mut requests_checked_at = Instant::now();
+        loop {
+            if requests_checked_at.elapsed() >= SYNC_REQUEST_POLL_INTERVAL {
+                requests_checked_at = Instant::now();
+                self.restart_requested(&mut heap).await;
+            }
+            let Sortable { key, data } = match heap.pop() {
+                Some(entry) => entry,
+                None => break,
+            };
+            let timestamp = key.0;
+            if timestamp > current_seconds() {
+                heap.push(Sortable { key, data });
+                sleep(Duration::from_secs(1)).await;
+                continue;
             }
 
-            while let Some(Sortable { key, data }) = heap.pop() {
-                let timestamp = key.0;
-                if timestamp > current_seconds() {
-                    heap.push(Sortable { key, data });
-                    sleep(Duration::from_secs(1)).await;
+            let mut state = data;
+            let user_id = state.user_id;
+            match self.connector.is_active(user_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    log::info!("{} was paused or lost the tokens", user_id);
                     continue;
                 }
-
-                let (user_id, token, next_cursor) = data;
-                if let Err(e) = verify_tokens(&token).await {
-                    log::error!("{:?}", e);
+                Err(e) => {
+                    log::error!("database error: {:?}", e);
+                    retry_later(&mut heap, state);
                     continue;
                 }
-                match C::fetch_ids(user_id, &token, next_cursor).await {
-                    Ok((ids, next_cursor)) => {
-                        log::info!("successfully fetched {} ids", ids.len());
-                        self.connector.save_ids(user_id, &ids).await;
-                        if next_cursor != 0 {
-                            heap.push(Sortable {
-                                key: Reverse(timestamp),
-                                data: (user_id, token, next_cursor),
-                            });
-                        }
-                    }
+            }
+            if state.pages == 0 {
+                match self.connector.verify_token(&state.token).await {
+                    Ok(()) => {}
                     Err(Error::RateLimit(timestamp)) => {
-                        let sleep_duration = timestamp as i64 - current_seconds();
-                        log::info!("rate limit exceeded. sleep {} seconds.", sleep_duration);
                         heap.push(Sortable {
                             key: Reverse(timestamp as i64),
-                            data: (user_id, token, next_cursor),
+                            data: state,
                         });
+                        continue;
                     }
                     Err(e) => {
-                        log::error!("twitter error: {:?}", e);
+                        log::error!("the token of {} was rejected: {:?}", user_id, e);
+                        continue;
                     }
                 }
+                if let Err(e) = self.connector.start_sync(user_id).await {
+                    log::error!("database error: {:?}", e);
+                    retry_later(&mut heap, state);
+                    continue;
+                }
+            }
+            match self
+                .connector
+                .fetch_ids(user_id, &state.token, state.next_cursor.as_deref())
+                .await
+            {
+                Ok((ids, next_cursor)) => {
+                    log::info!("successfully fetched {} {}", ids.len(), kind);
+                    // The page is fetched again when the user is retried.
+                    if let Err(e) = self.connector.save_ids(user_id, &ids).await {
+                        log::error!("database error: {:?}", e);
+                        retry_later(&mut heap, state);
+                        continue;
+                    }
+                    state.failures = 0;
+                    state.pages += 1;
+                    state.next_cursor = next_cursor.clone();
+                    self.events.publish(WorkerEvent::SyncPageFetched {
+                        user_id,
+                        kind,
+                        pages: state.pages,
+                        fetched: ids.len(),
+                        next_cursor,
+                    });
+                    if state.next_cursor.is_some() {
+                        heap.push(Sortable {
+                            key: Reverse(timestamp),
+                            data: state,
+                        });
+                    } else {
+                        self.finish(&state).await;
+                    }
+                }
+                Err(Error::RateLimit(timestamp)) => {
+                    let sleep_duration = timestamp as i64 - current_seconds();
+                    log::info!("rate limit exceeded. sleep {} seconds.", sleep_duration);
+                    self.events.publish(WorkerEvent::RateLimited {
+                        user_id,
+                        worker: kind,
+                        until: timestamp as i64,
+                    });
+                    heap.push(Sortable {
+                        key: Reverse(timestamp as i64),
+                        data: state,
+                    });
+                }
+                Err(e) => {
+                    log::error!("twitter error: {:?}", e);
+                }
             }
         }
     }
-}
 
-#[async_trait]
-pub trait DataConnector {
-    async fn fetch_ids(
-        user_id: i64,
-        token: &Token,
-        next_cursor: i64,
-    ) -> Result<(Vec<i64>, i64), Error>;
-    async fn save_ids(&self, user_id: i64, ids: &[i64]);
-}
+    /// Restarts the synchronization of the requested users from the first page, ahead of the
+    /// others.
+    async fn restart_requested(&self, heap: &mut SyncQueue) {
+        let provider = self.credentials.provider();
+        let user_ids = match self.connector.find_sync_requests(provider).await {
+            Ok(user_ids) if user_ids.is_empty() => return,
+            Ok(user_ids) => user_ids,
+            Err(e) => {
+                log::error!("database error: {:?}", e);
+                return;
+            }
+        };
+        let started_at = match Relationship::sync_started_at(&self.pool).await {
+            Ok(started_at) => started_at,
+            Err(e) => {
+                log::error!("database error: {:?}", e);
+                return;
+            }
+        };
+        // Paused users and users without tokens are not synchronized.
+        let identities = match Identity::find_by_provider(&self.pool, provider).await {
+            Ok(identities) => identities,
+            Err(e) => {
+                log::error!("database error: {:?}", e);
+                return;
+            }
+        };
+        for identity in identities {
+            let user_id = identity.id;
+            if !user_ids.contains(&user_id) {
+                continue;
+            }
+            let token = match self.credentials.token(&self.pool, identity).await {
+                Ok(token) => token,
+                Err(e) => {
+                    log::error!("failed to get the token of {}: {:?}", user_id, e);
+                    continue;
+                }
+            };
+            log::info!(
+                "restarting the synchronization of {} for {}",
+                self.connector.kind().as_str(),
+                user_id
+            );
+            let others = std::mem::take(heap)
+                .into_vec()
+                .into_iter()
+                .filter(|entry| entry.data.user_id != user_id);
+            heap.extend(others);
+            heap.push(Sortable {
+                key: Reverse(0),
+                data: SyncState {
+                    user_id,
+                    token,
+                    next_cursor: None,
+                    pages: 0,
+                    started_at,
+                    failures: 0,
+                },
+            });
+            if let Err(e) = self.connector.delete_sync_request(user_id).await {
+                log::error!("database error: {:?}", e);
+            }
+        }
+    }
 
-pub struct FollowersDataConnector {
-    pool: PgPool,
-}
-impl FollowersDataConnector {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    async fn finish(&self, state: &SyncState) {
+        let user_id = state.user_id;
+        if let Err(e) = self.connector.finish_sync(user_id, state.started_at).await {
+            log::error!("database error: {:?}", e);
+            return;
+        }
+        self.events.publish(WorkerEvent::SyncCompleted {
+            user_id,
+            kind: self.connector.kind().as_str(),
+            pages: state.pages,
+        });
     }
 }
+struct SyncState {
+    user_id: i64,
+    token: Token,
+    next_cursor: Option<String>,
+    pages: u32,
+    started_at: OffsetDateTime,
+    /// Database errors in a row.
+    failures: u32,
+}
+
+/// Schedules the user again after a database error, waiting twice as long after each one.
+fn retry_later(heap: &mut SyncQueue, mut state: SyncState) {
+    let delay = (1 << state.failures.min(9)).min(MAX_RETRY_DELAY);
+    state.failures += 1;
+    heap.push(Sortable {
+        key: Reverse(current_seconds() + delay),
+        data: state,
+    });
+}
 
 #[async_trait]
-impl DataConnector for FollowersDataConnector {
+pub trait DataConnector: Send + Sync {
+    fn kind(&self) -> RelationKind;
+    /// Checks that the token is still accepted before the first page of a user is fetched.
+    async fn verify_token(&self, _token: &Token) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called before the first page of a user is fetched.
+    async fn start_sync(&self, _user_id: i64) -> Result<(), SqlError> {
+        Ok(())
+    }
+    /// `cursor` is `None` for the first page.
     async fn fetch_ids(
+        &self,
         user_id: i64,
         token: &Token,
-        next_cursor: i64,
-    ) -> Result<(Vec<i64>, i64), Error> {
-        fetch_ids(followers_ids, user_id, token, next_cursor).await
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error>;
+    async fn save_ids(&self, user_id: i64, ids: &[i64]) -> Result<(), SqlError>;
+    /// Called when all the pages have been saved, to drop the IDs which were not seen.
+    async fn finish_sync(&self, user_id: i64, started_at: OffsetDateTime) -> Result<(), SqlError>;
+    /// Checked before each request, so that a paused user stops in the middle of the round.
+    async fn is_active(&self, _user_id: i64) -> Result<bool, SqlError> {
+        Ok(true)
     }
-    async fn save_ids(&self, user_id: i64, ids: &[i64]) {
-        if let Err(e) = Relationship::save_followers(&self.pool, user_id, ids).await {
-            log::error!("database error: {:?}", e);
-            sleep(Duration::from_secs(5)).await;
-        }
+    /// Returns the users whose synchronization should restart from the first page.
+    async fn find_sync_requests(&self, _provider: &str) -> Result<Vec<i64>, SqlError> {
+        Ok(vec![])
+    }
+    /// Called once the synchronization of the user has restarted.
+    async fn delete_sync_request(&self, _user_id: i64) -> Result<(), SqlError> {
+        Ok(())
     }
 }
-pub struct FriendsDataConnector {
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ingestion {
+    /// Upserts every page, refreshing `updated_at` of every row.
+    Upsert,
+    /// COPYs the pages into a staging table which is merged when the synchronization finishes.
+    /// Much faster for accounts with millions of relationships.
+    Staged,
+}
+
+/// Stores an ID set fetched by the client in the relationship table.
+pub struct RelationDataConnector<T> {
+    client: T,
     pool: PgPool,
+    kind: RelationKind,
+    ingestion: Ingestion,
 }
-impl FriendsDataConnector {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+
+impl<T> RelationDataConnector<T> {
+    pub fn new(client: T, pool: PgPool, kind: RelationKind, ingestion: Ingestion) -> Self {
+        Self {
+            client,
+            pool,
+            kind,
+            ingestion,
+        }
     }
 }
 
 #[async_trait]
-impl DataConnector for FriendsDataConnector {
+impl<T: IdsClient> DataConnector for RelationDataConnector<T> {
+    fn kind(&self) -> RelationKind {
+        self.kind
+    }
+    async fn verify_token(&self, token: &Token) -> Result<(), Error> {
+        self.client.verify_token(token).await
+    }
+    async fn start_sync(&self, user_id: i64) -> Result<(), SqlError> {
+        match self.ingestion {
+            Ingestion::Upsert => Ok(()),
+            Ingestion::Staged => Relationship::clear_staged(&self.pool, user_id, self.kind).await,
+        }
+    }
     async fn fetch_ids(
+        &self,
         user_id: i64,
         token: &Token,
-        next_cursor: i64,
-    ) -> Result<(Vec<i64>, i64), Error> {
-        fetch_ids(friends_ids, user_id, token, next_cursor).await
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error> {
+        self.client
+            .fetch_ids(self.kind, user_id, token, cursor)
+            .await
     }
-    async fn save_ids(&self, user_id: i64, ids: &[i64]) {
-        if let Err(e) = Relationship::save_friends(&self.pool, user_id, ids).await {
-            log::error!("database error: {:?}", e);
-            sleep(Duration::from_secs(5)).await;
+    async fn save_ids(&self, user_id: i64, ids: &[i64]) -> Result<(), SqlError> {
+        match self.ingestion {
+            Ingestion::Upsert => Relationship::save(&self.pool, user_id, self.kind, ids).await,
+            Ingestion::Staged => Relationship::stage(&self.pool, user_id, self.kind, ids).await,
         }
     }
-}
-
-async fn fetch_ids<F>(
-    f: F,
-    user_id: i64,
-    token: &Token,
-    next_cursor: i64,
-) -> Result<(Vec<i64>, i64), Error>
-where
-    F: Fn(u64, &Token) -> CursorIter<IDCursor>,
-{
-    let result = {
-        let mut cursor = f(user_id as u64, token);
-        cursor.page_size = Some(5000);
-        cursor.next_cursor = next_cursor;
-        cursor.call()
-    };
-
-    match result.await {
-        Ok(response) => {
-            let next_cursor = response.next_cursor;
-            let ids = response.ids.iter().map(|&id| id as i64).collect();
-            Ok((ids, next_cursor))
+    async fn finish_sync(&self, user_id: i64, started_at: OffsetDateTime) -> Result<(), SqlError> {
+        match self.ingestion {
+            Ingestion::Upsert => {
+                Relationship::finish_sync(&self.pool, user_id, self.kind, started_at).await?
+            }
+            Ingestion::Staged => {
+                Relationship::merge_staged(&self.pool, user_id, self.kind, started_at).await?
+            }
+        }
+        RelationshipSync::record(&self.pool, user_id, self.kind).await?;
+        if matches!(self.kind, RelationKind::Follower | RelationKind::Friend) {
+            RelationshipSnapshot::record(&self.pool, user_id).await?;
         }
-        Err(e) => Err(e),
+        Ok(())
+    }
+    async fn is_active(&self, user_id: i64) -> Result<bool, SqlError> {
+        Identity::is_active(&self.pool, user_id).await
+    }
+    async fn find_sync_requests(&self, provider: &str) -> Result<Vec<i64>, SqlError> {
+        SyncRequest::find_by_provider(&self.pool, provider, self.kind).await
+    }
+    async fn delete_sync_request(&self, user_id: i64) -> Result<(), SqlError> {
+        SyncRequest::delete(&self.pool, user_id, self.kind).await
     }
 }
@@ -1,11 +1,59 @@
 mod id_sync;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub use id_sync::{FollowersDataConnector, FriendsDataConnector, IdSynchronizer};
+pub use id_sync::{DataConnector, IdSynchronizer, Ingestion, RelationDataConnector};
+
+mod activity;
+pub use activity::{ActivityTracker, UserActivity};
+
+mod audit;
+pub use audit::{AuditRetentionWorker, DEFAULT_AUDIT_LOG_RETENTION};
+
+mod bluesky;
+pub use bluesky::{BlueskyClient, BlueskySession, BLUESKY};
+
+mod client;
+pub use client::{
+    Credentials, FollowClient, IdsClient, IdsPage, LiveRelation, MessageClient, ModerationClient,
+};
+
+mod event;
+pub use event::{EventBus, WorkerEvent};
 
 mod follow_back;
 pub use follow_back::FollowBackWorker;
 
+mod http;
+
+mod mastodon;
+pub use mastodon::{instance_url, MastodonClient, MASTODON, MASTODON_SCOPES};
+
+mod moderation;
+pub use moderation::{finish_soft_block, moderate, undo_moderation, SpamModerationWorker};
+
+mod pacing;
+pub use pacing::{IntervalDistribution, Pacer, PacingPolicy, QuietHours};
+
+mod rules;
+pub use rules::{
+    load_targets, Attribute, Comparison, Condition, Decision, Explanation, Fact, Rule, RuleSet,
+    RuleTrace, Target,
+};
+
+mod supervisor;
+pub use supervisor::{RestartPolicy, Supervisor, SupervisorHandle, WorkerState, WorkerStatus};
+
+mod twitter;
+pub use twitter::TwitterClient;
+
+mod twitter_v2;
+pub use twitter_v2::{
+    pkce_challenge, pkce_verifier, OAuth2Config, OAuth2Token, TwitterV2Client, OAUTH2_SCOPES,
+};
+
+mod welcome;
+pub use welcome::{render_welcome_message, WelcomeMessageWorker, WELCOME_MESSAGE_LOOKBACK};
+
 pub(crate) struct Sortable<K, T> {
     key: K,
     data: T,
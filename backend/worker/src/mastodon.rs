@@ -0,0 +1,523 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{
+    ExternalAccount, MastodonApp, OffsetDateTime, PgPool, Profile, RelationKind,
+};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    header::LINK,
+    redirect::Policy,
+    RequestBuilder, StatusCode, Url,
+};
+use serde::Deserialize;
+
+use crate::{
+    current_seconds,
+    http::{
+        bearer, check, database_error, parse_created_at, request_error, send, to_i32,
+        RateLimitHeader, ResetFormat,
+    },
+    FollowClient, IdsClient, IdsPage, LiveRelation,
+};
+
+pub const MASTODON: &str = "mastodon";
+
+/// Mastodon sends the reset time as an ISO 8601 timestamp.
+const RATE_LIMIT: RateLimitHeader = RateLimitHeader {
+    name: "x-ratelimit-reset",
+    format: ResetFormat::Rfc3339,
+    default_wait: 5 * 60,
+};
+const BEARER_NEEDED: &str = "Mastodon needs an OAuth 2.0 token";
+pub const MASTODON_SCOPES: &str = "read:accounts read:follows read:blocks read:mutes write:follows";
+const CLIENT_NAME: &str = "fantastic-giggle";
+const RELATIONSHIP_LOOKUP_LIMIT: usize = 40;
+const ACCOUNT_LOOKUP_LIMIT: usize = 40;
+/// Anyone can make the server register an app on a new instance, so this is limited.
+const MAX_APP_REGISTRATIONS: usize = 20;
+const APP_REGISTRATION_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The Mastodon REST API. Accounts are local to a server, so they are stored as external
+/// accounts keyed by the base URL of the server.
+#[derive(Debug, Clone)]
+pub struct MastodonClient {
+    http: reqwest::Client,
+    pool: PgPool,
+    redirect_uri: String,
+    registrations: Arc<Mutex<VecDeque<Instant>>>,
+    pub page_size: u32,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    id: String,
+    #[serde(default)]
+    acct: String,
+    #[serde(default)]
+    display_name: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    avatar: String,
+    #[serde(default)]
+    locked: bool,
+    created_at: Option<String>,
+    #[serde(default)]
+    followers_count: i64,
+    #[serde(default)]
+    following_count: i64,
+}
+
+#[derive(Deserialize)]
+struct Relationship {
+    id: String,
+    following: bool,
+    followed_by: bool,
+    #[serde(default)]
+    requested: bool,
+}
+
+#[derive(Deserialize)]
+struct App {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Returns the base URL of a Mastodon server given as a domain or an https URL. Addresses of
+/// private networks are rejected, as the server is called on behalf of anyone who logs in.
+pub fn instance_url(instance: &str) -> Option<String> {
+    let instance = instance.trim();
+    let url = if instance.starts_with("https://") {
+        Url::parse(instance).ok()?
+    } else if instance.contains("://") {
+        return None;
+    } else {
+        Url::parse(&format!("https://{}", instance)).ok()?
+    };
+    let host = url.host_str()?;
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+        if !is_public(ip) {
+            return None;
+        }
+    }
+    Some(url.origin().ascii_serialization())
+}
+
+/// Whether the address is reachable on the internet, as opposed to the loopback, private and
+/// link-local networks.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // 100.64.0.0/10, the shared address space of carrier-grade NATs.
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // fc00::/7, unique local.
+                || (first & 0xfe00) == 0xfc00
+                // fe80::/10, link-local.
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolves host names to public addresses only, so that neither a DNS record nor a redirect
+/// can point the requests at the internal network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+                return Err(
+                    format!("{} does not resolve to a public address", name.as_str()).into(),
+                );
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+impl MastodonClient {
+    pub fn new(pool: PgPool, redirect_uri: String) -> Self {
+        let http = reqwest::Client::builder()
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(Policy::none())
+            .build()
+            .expect("failed to build the HTTP client");
+        Self {
+            http,
+            pool,
+            redirect_uri,
+            registrations: Arc::new(Mutex::new(VecDeque::new())),
+            page_size: 80,
+        }
+    }
+
+    /// Registers the OAuth application on the server. At most `MAX_APP_REGISTRATIONS` are made
+    /// in an hour.
+    pub async fn register_app(&self, instance: &str) -> Result<MastodonApp, Error> {
+        self.count_registration()?;
+        let request = self.http.post(format!("{}/api/v1/apps", instance)).form(&[
+            ("client_name", CLIENT_NAME),
+            ("redirect_uris", &self.redirect_uri),
+            ("scopes", MASTODON_SCOPES),
+        ]);
+        let app: App = send(request, &RATE_LIMIT).await?;
+        Ok(MastodonApp {
+            instance: instance.to_string(),
+            client_id: app.client_id,
+            client_secret: app.client_secret,
+            created_at: OffsetDateTime::now_utc(),
+        })
+    }
+
+    pub fn authorize_url(&self, app: &MastodonApp, state: &str) -> Result<String, Error> {
+        let url = format!("{}/oauth/authorize", app.instance);
+        Url::parse_with_params(
+            &url,
+            &[
+                ("response_type", "code"),
+                ("client_id", &app.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", MASTODON_SCOPES),
+                ("state", state),
+            ],
+        )
+        .map(String::from)
+        .map_err(|_| Error::InvalidResponse("invalid authorize url", Some(url)))
+    }
+
+    /// Returns the access token, which doesn't expire.
+    pub async fn exchange_code(&self, app: &MastodonApp, code: &str) -> Result<String, Error> {
+        let request = self
+            .http
+            .post(format!("{}/oauth/token", app.instance))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("client_id", &app.client_id),
+                ("client_secret", &app.client_secret),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", MASTODON_SCOPES),
+            ]);
+        let response: TokenResponse = send(request, &RATE_LIMIT).await?;
+        Ok(response.access_token)
+    }
+
+    /// Returns the ID of the owner of the token on the server.
+    pub async fn verify_credentials(&self, instance: &str, token: &Token) -> Result<String, Error> {
+        let url = format!("{}/api/v1/accounts/verify_credentials", instance);
+        let account: Account = send(self.get(url, token)?, &RATE_LIMIT).await?;
+        Ok(account.id)
+    }
+
+    /// Fetches a page of the accounts related to the account, and the `max_id` of the next page
+    /// taken from the `Link` header.
+    pub async fn fetch_accounts(
+        &self,
+        kind: RelationKind,
+        instance: &str,
+        account_id: &str,
+        token: &Token,
+        max_id: Option<&str>,
+    ) -> Result<(Vec<String>, Option<String>), Error> {
+        let path = match kind {
+            RelationKind::Follower => format!("/api/v1/accounts/{}/followers", account_id),
+            RelationKind::Friend => format!("/api/v1/accounts/{}/following", account_id),
+            RelationKind::Blocking => "/api/v1/blocks".to_string(),
+            RelationKind::Muting => "/api/v1/mutes".to_string(),
+            RelationKind::IncomingRequest => "/api/v1/follow_requests".to_string(),
+            RelationKind::OutgoingRequest | RelationKind::ListMembership => {
+                return Err(unsupported(kind))
+            }
+        };
+        let mut query = vec![("limit", self.page_size.to_string())];
+        if let Some(max_id) = max_id {
+            query.push(("max_id", max_id.to_string()));
+        }
+        let request = self
+            .get(format!("{}{}", instance, path), token)?
+            .query(&query);
+        let response = check(request.send().await.map_err(request_error)?, &RATE_LIMIT)?;
+        let next = response
+            .headers()
+            .get(LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_max_id);
+        let body = response.bytes().await.map_err(request_error)?;
+        let accounts: Vec<Account> = serde_json::from_slice(&body)?;
+        Ok((accounts.into_iter().map(|a| a.id).collect(), next))
+    }
+
+    /// Returns the relationships of the user with the given accounts by account ID.
+    pub async fn fetch_relations(
+        &self,
+        instance: &str,
+        token: &Token,
+        account_ids: &[String],
+    ) -> Result<BTreeMap<String, LiveRelation>, Error> {
+        let mut relations = BTreeMap::new();
+        for chunk in account_ids.chunks(RELATIONSHIP_LOOKUP_LIMIT) {
+            let query = chunk
+                .iter()
+                .map(|id| ("id[]", id.as_str()))
+                .collect::<Vec<_>>();
+            let request = self
+                .get(format!("{}/api/v1/accounts/relationships", instance), token)?
+                .query(&query);
+            let relationships: Vec<Relationship> = send(request, &RATE_LIMIT).await?;
+            relations.extend(relationships.into_iter().map(|r| {
+                let relation = LiveRelation {
+                    followed_by: r.followed_by,
+                    following: r.following || r.requested,
+                };
+                (r.id, relation)
+            }));
+        }
+        Ok(relations)
+    }
+
+    pub async fn follow_account(
+        &self,
+        instance: &str,
+        token: &Token,
+        account_id: &str,
+    ) -> Result<(), Error> {
+        let url = format!("{}/api/v1/accounts/{}/follow", instance, account_id);
+        let request = self
+            .http
+            .post(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?);
+        let _: Relationship = send(request, &RATE_LIMIT).await?;
+        Ok(())
+    }
+
+    fn count_registration(&self) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut registrations = self.registrations.lock().unwrap();
+        while registrations
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= APP_REGISTRATION_WINDOW)
+        {
+            registrations.pop_front();
+        }
+        if let Some(&oldest) = registrations.front() {
+            if registrations.len() >= MAX_APP_REGISTRATIONS {
+                let wait = APP_REGISTRATION_WINDOW.saturating_sub(now.duration_since(oldest));
+                let wait = i64::try_from(wait.as_secs()).unwrap_or(i64::MAX);
+                return Err(Error::RateLimit(to_i32(
+                    current_seconds().saturating_add(wait),
+                )));
+            }
+        }
+        registrations.push_back(now);
+        Ok(())
+    }
+
+    /// Fetches the accounts of a server in batches. Servers older than 4.3 don't have the batch
+    /// endpoint, so they are fetched one by one.
+    async fn fetch_accounts_by_ids(
+        &self,
+        instance: &str,
+        token: &Token,
+        account_ids: &[String],
+    ) -> Result<Vec<Account>, Error> {
+        let mut accounts = vec![];
+        for chunk in account_ids.chunks(ACCOUNT_LOOKUP_LIMIT) {
+            let query = chunk
+                .iter()
+                .map(|id| ("id[]", id.as_str()))
+                .collect::<Vec<_>>();
+            let request = self
+                .get(format!("{}/api/v1/accounts", instance), token)?
+                .query(&query);
+            match send::<Vec<Account>>(request, &RATE_LIMIT).await {
+                Ok(batch) => accounts.extend(batch),
+                Err(Error::BadStatus(StatusCode::NOT_FOUND)) => {
+                    for id in chunk {
+                        let url = format!("{}/api/v1/accounts/{}", instance, id);
+                        accounts.push(send(self.get(url, token)?, &RATE_LIMIT).await?);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(accounts)
+    }
+
+    async fn account(&self, id: i64) -> Result<ExternalAccount, Error> {
+        ExternalAccount::find_by_id(&self.pool, id)
+            .await
+            .map_err(database_error)?
+            .ok_or(Error::MissingValue("account"))
+    }
+
+    fn get(&self, url: String, token: &Token) -> Result<RequestBuilder, Error> {
+        Ok(self
+            .http
+            .get(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?))
+    }
+}
+
+#[async_trait]
+impl IdsClient for MastodonClient {
+    fn supports(&self, kind: RelationKind) -> bool {
+        !matches!(
+            kind,
+            RelationKind::OutgoingRequest | RelationKind::ListMembership
+        )
+    }
+
+    async fn fetch_ids(
+        &self,
+        kind: RelationKind,
+        user_id: i64,
+        token: &Token,
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error> {
+        let account = self.account(user_id).await?;
+        let (account_ids, next) = self
+            .fetch_accounts(kind, &account.instance, &account.external_id, token, cursor)
+            .await?;
+        let ids = ExternalAccount::resolve(&self.pool, MASTODON, &account.instance, &account_ids)
+            .await
+            .map_err(database_error)?;
+        Ok((ids, next))
+    }
+}
+
+#[async_trait]
+impl FollowClient for MastodonClient {
+    /// The accounts on other servers than the one of the user are left out.
+    async fn lookup_relations(
+        &self,
+        user_id: i64,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Option<BTreeMap<u64, LiveRelation>>, Error> {
+        let account = self.account(user_id).await?;
+        let ids = user_ids.iter().map(|&id| id as i64).collect::<Vec<_>>();
+        let internal_ids = ExternalAccount::find_by_ids(&self.pool, &ids)
+            .await
+            .map_err(database_error)?
+            .into_iter()
+            .filter(|target| target.instance == account.instance)
+            .map(|target| (target.external_id, target.id as u64))
+            .collect::<BTreeMap<_, _>>();
+        let account_ids = internal_ids.keys().cloned().collect::<Vec<_>>();
+        let relations = self
+            .fetch_relations(&account.instance, token, &account_ids)
+            .await?;
+        Ok(Some(
+            relations
+                .into_iter()
+                .filter_map(|(id, relation)| Some((*internal_ids.get(&id)?, relation)))
+                .collect(),
+        ))
+    }
+
+    async fn lookup_profiles(
+        &self,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Vec<Profile>, Error> {
+        let ids = user_ids.iter().map(|&id| id as i64).collect::<Vec<_>>();
+        let mut instances = BTreeMap::<String, BTreeMap<String, i64>>::new();
+        for target in ExternalAccount::find_by_ids(&self.pool, &ids)
+            .await
+            .map_err(database_error)?
+        {
+            instances
+                .entry(target.instance)
+                .or_default()
+                .insert(target.external_id, target.id);
+        }
+        let mut profiles = vec![];
+        for (instance, targets) in instances {
+            let account_ids = targets.keys().cloned().collect::<Vec<_>>();
+            for account in self
+                .fetch_accounts_by_ids(&instance, token, &account_ids)
+                .await?
+            {
+                if let Some(&id) = targets.get(&account.id) {
+                    profiles.push(to_profile(id, account));
+                }
+            }
+        }
+        Ok(profiles)
+    }
+
+    async fn follow(&self, _: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        let target = self.account(target_id as i64).await?;
+        self.follow_account(&target.instance, token, &target.external_id)
+            .await
+    }
+}
+
+/// Returns the `max_id` of the `rel="next"` link.
+fn next_max_id(link: &str) -> Option<String> {
+    link.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        if !params.contains("rel=\"next\"") {
+            return None;
+        }
+        let url = Url::parse(url.trim().trim_start_matches('<').trim_end_matches('>')).ok()?;
+        url.query_pairs()
+            .find(|(key, _)| key == "max_id")
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
+fn unsupported(kind: RelationKind) -> Error {
+    Error::InvalidResponse("not supported by Mastodon", Some(kind.as_str().to_string()))
+}
+
+fn to_profile(id: i64, account: Account) -> Profile {
+    let account_created_at = parse_created_at(account.created_at.as_deref());
+    Profile {
+        id,
+        screen_name: account.acct,
+        name: account.display_name,
+        description: account.note,
+        followers_count: to_i32(account.followers_count),
+        friends_count: to_i32(account.following_count),
+        default_profile_image: account.avatar.ends_with("/avatars/original/missing.png"),
+        protected: account.locked,
+        account_created_at,
+        updated_at: OffsetDateTime::now_utc(),
+    }
+}
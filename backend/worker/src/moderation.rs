@@ -0,0 +1,347 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{
+    AuditActor, AuditLog, AuditRecord, Identity, ModerationAction, ModerationKind, OffsetDateTime,
+    PgPool, Profile, RelationKind, Relationship, SpamCheck, UserSettings,
+};
+use tokio::time::sleep;
+
+use crate::{
+    Credentials, Decision, EventBus, FollowClient, ModerationClient, RuleSet, Target, WorkerEvent,
+};
+
+const CHECK_LIMIT: i64 = 100;
+const ACTION_INTERVAL: Duration = Duration::from_secs(10);
+const ROUND_PAUSE: Duration = Duration::from_secs(15 * 60);
+/// Only the followers gained recently are checked, not everyone in the history.
+const LOOKBACK: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// Applies the action on the provider and mirrors it in the synchronized relationships. A soft
+/// block only blocks here, and has to be saved before `finish_soft_block` unblocks, so that the
+/// follower is not left blocked when the unblock fails.
+pub async fn moderate<T: ModerationClient>(
+    client: &T,
+    pool: &PgPool,
+    user_id: i64,
+    token: &Token,
+    target_id: i64,
+    action: ModerationKind,
+) -> Result<(), Error> {
+    let target = target_id as u64;
+    match action {
+        ModerationKind::Mute => client.mute(user_id, token, target).await?,
+        ModerationKind::Block | ModerationKind::SoftBlock => {
+            client.block(user_id, token, target).await?
+        }
+    }
+
+    let result = async {
+        match action {
+            ModerationKind::Mute => {
+                Relationship::add(pool, user_id, RelationKind::Muting, target_id).await?;
+            }
+            ModerationKind::Block => {
+                Relationship::add(pool, user_id, RelationKind::Blocking, target_id).await?;
+                Relationship::remove(pool, user_id, RelationKind::Follower, target_id).await?;
+            }
+            ModerationKind::SoftBlock => {
+                Relationship::remove(pool, user_id, RelationKind::Follower, target_id).await?;
+            }
+        }
+        Ok::<_, fantastic_giggle_sql::Error>(())
+    };
+    if let Err(e) = result.await {
+        // The next synchronization fixes the relationships.
+        log::error!("database error: {:?}", e);
+    }
+    Ok(())
+}
+
+/// Unblocks the follower of the saved soft block `id`.
+pub async fn finish_soft_block<T: ModerationClient>(
+    client: &T,
+    pool: &PgPool,
+    user_id: i64,
+    token: &Token,
+    id: i64,
+    target_id: i64,
+) -> Result<(), Error> {
+    client.unblock(user_id, token, target_id as u64).await?;
+    if let Err(e) = ModerationAction::finish_unblock(pool, user_id, id).await {
+        // Unblocking again does no harm.
+        log::error!("database error: {:?}", e);
+    }
+    Ok(())
+}
+
+/// Reverts a mute or a block. A soft block leaves nothing to revert once the unblock went out,
+/// and is only unblocked before that.
+pub async fn undo_moderation<T: ModerationClient>(
+    client: &T,
+    pool: &PgPool,
+    token: &Token,
+    action: &ModerationAction,
+) -> Result<(), Error> {
+    let user_id = action.source_id;
+    let target = action.target_id as u64;
+    let kind = match ModerationKind::parse(&action.action) {
+        Some(ModerationKind::Mute) => {
+            client.unmute(user_id, token, target).await?;
+            RelationKind::Muting
+        }
+        Some(ModerationKind::Block) => {
+            client.unblock(user_id, token, target).await?;
+            RelationKind::Blocking
+        }
+        Some(ModerationKind::SoftBlock) if action.pending_unblock => {
+            return finish_soft_block(client, pool, user_id, token, action.id, action.target_id)
+                .await;
+        }
+        Some(ModerationKind::SoftBlock) | None => return Ok(()),
+    };
+    if let Err(e) = Relationship::remove(pool, user_id, kind, action.target_id).await {
+        log::error!("database error: {:?}", e);
+    }
+    Ok(())
+}
+
+/// Mutes or blocks the new followers who look like spam, by the rules of the user.
+pub struct SpamModerationWorker<T> {
+    pool: PgPool,
+    client: T,
+    credentials: Credentials,
+    events: EventBus,
+}
+
+impl<T: FollowClient + ModerationClient> SpamModerationWorker<T> {
+    pub fn new(pool: PgPool, client: T, credentials: Credentials, events: EventBus) -> Self {
+        Self {
+            pool,
+            client,
+            credentials,
+            events,
+        }
+    }
+    pub async fn run(&self) {
+        loop {
+            match Identity::find_by_provider(&self.pool, self.credentials.provider()).await {
+                Ok(identities) => {
+                    for identity in identities {
+                        if let Err(e) = self.check(identity).await {
+                            log::error!("{:?}", e);
+                        }
+                    }
+                }
+                Err(e) => log::error!("database error: {:?}", e),
+            }
+            sleep(ROUND_PAUSE).await;
+        }
+    }
+
+    fn publish_moderated(
+        &self,
+        user_id: i64,
+        target_id: i64,
+        action: ModerationKind,
+        dry_run: bool,
+    ) {
+        self.events.publish(WorkerEvent::Moderated {
+            user_id,
+            target_id,
+            action: action.as_str(),
+            dry_run,
+        });
+    }
+
+    async fn save_action(
+        &self,
+        user_id: i64,
+        target_id: i64,
+        action: ModerationKind,
+        reason: &str,
+        dry_run: bool,
+        error: Option<&str>,
+    ) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let id =
+            ModerationAction::save(&mut tx, user_id, target_id, action, reason, dry_run, error)
+                .await?;
+        let record = AuditRecord::new(user_id, AuditActor::Worker, action.into())
+            .target(target_id)
+            .parameters(serde_json::json!({ "reason": reason }).to_string())
+            .dry_run(dry_run)
+            .error(error);
+        AuditLog::append(&mut tx, &record).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Unblocks the followers left blocked by a failed unblock. Returns false when rate limited.
+    async fn retry_unblocks(
+        &self,
+        user_id: i64,
+        token: &Token,
+        pending: &[ModerationAction],
+    ) -> bool {
+        for action in pending {
+            let result = finish_soft_block(
+                &self.client,
+                &self.pool,
+                user_id,
+                token,
+                action.id,
+                action.target_id,
+            );
+            match result.await {
+                Ok(()) => log::info!("unblocked {} for {}", action.target_id, user_id),
+                Err(Error::RateLimit(timestamp)) => {
+                    self.publish_rate_limited(user_id, timestamp);
+                    return false;
+                }
+                Err(e) => log::error!("failed to unblock {}: {:?}", action.target_id, e),
+            }
+            sleep(ACTION_INTERVAL).await;
+        }
+        true
+    }
+
+    fn publish_rate_limited(&self, user_id: i64, timestamp: i32) {
+        log::info!("rate limit exceeded for {} until {}", user_id, timestamp);
+        self.events.publish(WorkerEvent::RateLimited {
+            user_id,
+            worker: "spam_moderation",
+            until: timestamp as i64,
+        });
+    }
+
+    async fn check(&self, identity: Identity) -> Result<()> {
+        let user_id = identity.id;
+        let settings = UserSettings::find_or_default(&self.pool, user_id).await?;
+        let action = ModerationKind::parse(&settings.spam_action);
+        let pending_unblocks = ModerationAction::find_pending_unblocks(&self.pool, user_id).await?;
+        let since = OffsetDateTime::now_utc() - LOOKBACK;
+        let target_ids = match action {
+            Some(_) => SpamCheck::find_pending(&self.pool, user_id, since, CHECK_LIMIT).await?,
+            None => Vec::new(),
+        };
+        if pending_unblocks.is_empty() && target_ids.is_empty() {
+            return Ok(());
+        }
+
+        let token = self.credentials.token(&self.pool, identity).await?;
+        // Even when the moderation has been turned off since.
+        if !self
+            .retry_unblocks(user_id, &token, &pending_unblocks)
+            .await
+        {
+            return Ok(());
+        }
+        let action = match action {
+            Some(action) if !target_ids.is_empty() => action,
+            _ => return Ok(()),
+        };
+        let ids = target_ids.iter().map(|&id| id as u64).collect::<Vec<_>>();
+        let profiles = self.client.lookup_profiles(&token, &ids).await?;
+        let rules = RuleSet::spam_from_settings(&settings);
+        let now = OffsetDateTime::now_utc();
+        for profile in &profiles {
+            Profile::save(&self.pool, profile).await?;
+            // Only the profile is scored, so that the blocks and mutes don't exclude anyone.
+            let target = Target {
+                profile: Some(profile.clone()),
+                ..Target::default()
+            };
+            let rejection = match rules.evaluate(&target, now) {
+                (Decision::Skip, Some(rule)) => rule.name.clone(),
+                _ => {
+                    SpamCheck::save(&self.pool, user_id, profile.id).await?;
+                    continue;
+                }
+            };
+
+            if settings.dry_run {
+                log::info!(
+                    "dry run: would {} {} for {}: {}",
+                    action.as_str(),
+                    profile.id,
+                    user_id,
+                    rejection
+                );
+                self.save_action(user_id, profile.id, action, &rejection, true, None)
+                    .await?;
+                self.publish_moderated(user_id, profile.id, action, true);
+            } else {
+                match moderate(
+                    &self.client,
+                    &self.pool,
+                    user_id,
+                    &token,
+                    profile.id,
+                    action,
+                )
+                .await
+                {
+                    Ok(()) => {
+                        log::info!("{} {}: {}", action.as_str(), profile.id, rejection);
+                        let id = self
+                            .save_action(user_id, profile.id, action, &rejection, false, None)
+                            .await?;
+                        self.publish_moderated(user_id, profile.id, action, false);
+                        if action == ModerationKind::SoftBlock {
+                            let result = finish_soft_block(
+                                &self.client,
+                                &self.pool,
+                                user_id,
+                                &token,
+                                id,
+                                profile.id,
+                            );
+                            match result.await {
+                                Ok(()) => {}
+                                Err(Error::RateLimit(timestamp)) => {
+                                    SpamCheck::save(&self.pool, user_id, profile.id).await?;
+                                    self.publish_rate_limited(user_id, timestamp);
+                                    return Ok(());
+                                }
+                                Err(e) => log::error!(
+                                    "failed to unblock {}, retrying in the next round: {:?}",
+                                    profile.id,
+                                    e
+                                ),
+                            }
+                        }
+                    }
+                    Err(Error::RateLimit(timestamp)) => {
+                        // Nothing went out, and the follower is checked again in the next round.
+                        self.publish_rate_limited(user_id, timestamp);
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        log::error!("failed to {} {}: {:?}", action.as_str(), profile.id, e);
+                        self.save_action(
+                            user_id,
+                            profile.id,
+                            action,
+                            &rejection,
+                            false,
+                            Some(&e.to_string()),
+                        )
+                        .await?;
+                    }
+                }
+                sleep(ACTION_INTERVAL).await;
+            }
+            SpamCheck::save(&self.pool, user_id, profile.id).await?;
+        }
+
+        // Suspended or deleted accounts are not returned, and don't need to be checked again.
+        for target_id in target_ids {
+            if !profiles.iter().any(|profile| profile.id == target_id) {
+                SpamCheck::save(&self.pool, user_id, target_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
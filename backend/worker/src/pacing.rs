@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use fantastic_giggle_sql::UserSettings;
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal};
+
+#[derive(Debug, Clone)]
+pub enum IntervalDistribution {
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Mostly close to the median, with a long tail of slower actions, as humans do.
+    LogNormal {
+        median: Duration,
+        sigma: f64,
+    },
+}
+
+impl IntervalDistribution {
+    fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        match self {
+            IntervalDistribution::Uniform { min, max } => sample_between(rng, *min, *max),
+            IntervalDistribution::LogNormal { median, sigma } => {
+                match LogNormal::new(median.as_secs_f64().ln(), *sigma) {
+                    // The tail can go beyond what a Duration holds.
+                    Ok(distribution) => Duration::try_from_secs_f64(distribution.sample(rng))
+                        .unwrap_or(Duration::MAX),
+                    Err(_) => *median,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PacingPolicy {
+    pub interval: IntervalDistribution,
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    /// Probability of taking a longer break after an action.
+    pub break_probability: f64,
+    pub break_min: Duration,
+    pub break_max: Duration,
+    /// Pause between two rounds of following back.
+    pub round_pause_min: Duration,
+    pub round_pause_max: Duration,
+}
+
+impl Default for PacingPolicy {
+    fn default() -> Self {
+        Self {
+            interval: IntervalDistribution::LogNormal {
+                median: Duration::from_secs(90),
+                sigma: 0.6,
+            },
+            min_interval: Duration::from_secs(30),
+            max_interval: Duration::from_secs(15 * 60),
+            break_probability: 0.05,
+            break_min: Duration::from_secs(10 * 60),
+            break_max: Duration::from_secs(45 * 60),
+            round_pause_min: Duration::from_secs(5 * 60),
+            round_pause_max: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl PacingPolicy {
+    pub fn round_pause<R: Rng>(&self, rng: &mut R) -> Duration {
+        sample_between(rng, self.round_pause_min, self.round_pause_max)
+    }
+}
+
+/// The hours of the day in the user's timezone during which nothing should happen.
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    start: u32,
+    end: u32,
+    timezone: Tz,
+}
+
+impl QuietHours {
+    pub fn from_settings(settings: &UserSettings) -> Option<Self> {
+        let (start, end) = match (settings.quiet_hours_start, settings.quiet_hours_end) {
+            (Some(start), Some(end)) if start != end => (start as u32, end as u32),
+            _ => return None,
+        };
+        let timezone = settings.timezone.parse::<Tz>().unwrap_or_else(|_| {
+            log::warn!(
+                "unknown timezone {} for {}, using UTC",
+                settings.timezone,
+                settings.user_id
+            );
+            Tz::UTC
+        });
+        Some(Self {
+            start,
+            end,
+            timezone,
+        })
+    }
+
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        let hour = time.with_timezone(&self.timezone).hour();
+        if self.start < self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            self.start <= hour || hour < self.end
+        }
+    }
+
+    /// Returns the first time at or after `time` which is outside the quiet hours.
+    pub fn next_active(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        // Walking minute by minute keeps DST transitions out of the picture.
+        let mut time = time;
+        for _ in 0..(24 * 60) {
+            if !self.contains(time) {
+                break;
+            }
+            time = time + chrono::Duration::minutes(1);
+        }
+        time
+    }
+}
+
+/// Decides when the next follow should happen for a user.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    policy: PacingPolicy,
+    quiet_hours: Option<QuietHours>,
+}
+
+impl Pacer {
+    pub fn new(policy: PacingPolicy, quiet_hours: Option<QuietHours>) -> Self {
+        Self {
+            policy,
+            quiet_hours,
+        }
+    }
+
+    /// Returns how long to wait before the first action.
+    pub fn initial_delay<R: Rng>(&self, now: DateTime<Utc>, rng: &mut R) -> Duration {
+        let jitter = sample_between(rng, Duration::ZERO, self.policy.min_interval);
+        self.defer_quiet_hours(now, jitter, rng)
+    }
+
+    /// Returns how long to wait after an action before the next one.
+    pub fn next_delay<R: Rng>(&self, now: DateTime<Utc>, rng: &mut R) -> Duration {
+        // Not `clamp`, which panics when the policy has min_interval > max_interval.
+        let mut delay = self
+            .policy
+            .interval
+            .sample(rng)
+            .min(self.policy.max_interval)
+            .max(self.policy.min_interval);
+        if rng.gen_bool(self.policy.break_probability.clamp(0.0, 1.0)) {
+            delay += sample_between(rng, self.policy.break_min, self.policy.break_max);
+        }
+        self.defer_quiet_hours(now, delay, rng)
+    }
+
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        self.quiet_hours
+            .as_ref()
+            .is_some_and(|quiet_hours| quiet_hours.contains(now))
+    }
+
+    fn defer_quiet_hours<R: Rng>(
+        &self,
+        now: DateTime<Utc>,
+        delay: Duration,
+        rng: &mut R,
+    ) -> Duration {
+        let quiet_hours = match &self.quiet_hours {
+            Some(quiet_hours) => quiet_hours,
+            None => return delay,
+        };
+        let at =
+            now + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+        if !quiet_hours.contains(at) {
+            return delay;
+        }
+
+        // Don't start exactly when the quiet hours end.
+        let wake_up = quiet_hours.next_active(at) - now;
+        let wake_up = wake_up.to_std().unwrap_or(delay);
+        wake_up + sample_between(rng, Duration::ZERO, self.policy.break_max)
+    }
+}
+
+fn sample_between<R: Rng>(rng: &mut R, min: Duration, max: Duration) -> Duration {
+    if min >= max {
+        return min;
+    }
+    let secs = rng.gen_range(min.as_secs_f64()..=max.as_secs_f64());
+    Duration::try_from_secs_f64(secs)
+        .unwrap_or(max)
+        .clamp(min, max)
+}
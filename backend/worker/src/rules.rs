@@ -0,0 +1,465 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use fantastic_giggle_sql::{
+    BlockList, OffsetDateTime, PgPool, Profile, RelationKind, Relationship, UserSettings, WhiteList,
+};
+use serde::{Deserialize, Serialize};
+
+const MAX_RULES: usize = 50;
+const MAX_DEPTH: usize = 8;
+const MAX_NAME_LENGTH: usize = 100;
+const RELATION_FACTS: [(RelationKind, Fact); 5] = [
+    (RelationKind::Follower, Fact::Follower),
+    (RelationKind::Friend, Fact::Friend),
+    (RelationKind::OutgoingRequest, Fact::OutgoingRequest),
+    (RelationKind::Blocking, Fact::Blocking),
+    (RelationKind::Muting, Fact::Muting),
+];
+/// The facts which skip a target whatever the rules say.
+const EXCLUSIONS: [Fact; 3] = [Fact::Blocklisted, Fact::Blocking, Fact::Muting];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Follow,
+    Skip,
+}
+
+/// What is known about a target without comparing numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fact {
+    /// The target follows the user.
+    Follower,
+    /// The user follows the target.
+    Friend,
+    /// The user requested to follow the protected target.
+    OutgoingRequest,
+    Blocking,
+    Muting,
+    Whitelisted,
+    Blocklisted,
+    DefaultProfileImage,
+    Protected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Attribute {
+    FollowersCount,
+    FriendsCount,
+    FriendsFollowersRatio,
+    AccountAgeDays,
+    /// Days since the target was first seen following the user.
+    DaysSinceFollow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+    #[serde(rename = "==")]
+    Equal,
+}
+
+/// A condition on a target, written in JSON like `{"all": [{"fact": "follower"}, {"not":
+/// {"bio_contains": "crypto"}}]}`. Comparisons of unknown attributes, like the profile of a
+/// target that hasn't been looked up, are false.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    Fact(Fact),
+    Compare {
+        attribute: Attribute,
+        op: Comparison,
+        value: f64,
+    },
+    /// Case-insensitive.
+    BioContains(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub when: Condition,
+    pub decision: Decision,
+}
+
+/// Rules evaluated in order. The first matching rule decides, and `default` decides when none
+/// matches. Blocklisted targets are skipped whatever the rules say.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+    #[serde(default = "default_decision")]
+    pub default: Decision,
+}
+
+fn default_decision() -> Decision {
+    Decision::Skip
+}
+
+/// The facts the rules are evaluated against.
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    pub facts: BTreeSet<Fact>,
+    pub followed_at: Option<OffsetDateTime>,
+    pub profile: Option<Profile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTrace {
+    pub name: String,
+    pub matched: bool,
+}
+
+/// How a decision was made. The rules after the matching one are not evaluated.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub decision: Decision,
+    /// The blocklist, block or mute which skipped the target before any rule.
+    pub excluded_by: Option<Fact>,
+    /// `None` when the default decided.
+    pub matched_rule: Option<String>,
+    pub rules: Vec<RuleTrace>,
+}
+
+/// The thresholds of the settings for telling bots and spam accounts apart from real people.
+struct Heuristics<'a> {
+    default_profile_image: bool,
+    min_followers_count: i32,
+    max_friends_followers_ratio: Option<f64>,
+    min_account_age_days: i32,
+    bio_keywords: &'a [String],
+}
+
+impl Heuristics<'_> {
+    /// Skips the accounts which don't pass the thresholds, leaving out the disabled ones.
+    fn rules(&self) -> Vec<Rule> {
+        let skip = |name: &str, when: Condition| Rule {
+            name: name.to_string(),
+            when,
+            decision: Decision::Skip,
+        };
+        let compare = |attribute: Attribute, op: Comparison, value: f64| Condition::Compare {
+            attribute,
+            op,
+            value,
+        };
+
+        let mut rules = vec![];
+        if self.default_profile_image {
+            rules.push(skip(
+                "default_profile_image",
+                Condition::Fact(Fact::DefaultProfileImage),
+            ));
+        }
+        if self.min_followers_count > 0 {
+            rules.push(skip(
+                "too_few_followers",
+                compare(
+                    Attribute::FollowersCount,
+                    Comparison::Less,
+                    self.min_followers_count as f64,
+                ),
+            ));
+        }
+        if let Some(max_ratio) = self.max_friends_followers_ratio {
+            rules.push(skip(
+                "friends_followers_ratio",
+                compare(
+                    Attribute::FriendsFollowersRatio,
+                    Comparison::Greater,
+                    max_ratio,
+                ),
+            ));
+        }
+        if self.min_account_age_days > 0 {
+            rules.push(skip(
+                "account_too_new",
+                compare(
+                    Attribute::AccountAgeDays,
+                    Comparison::Less,
+                    self.min_account_age_days as f64,
+                ),
+            ));
+        }
+        let keywords = self
+            .bio_keywords
+            .iter()
+            .filter(|keyword| !keyword.trim().is_empty())
+            .map(|keyword| Condition::BioContains(keyword.clone()))
+            .collect::<Vec<_>>();
+        if !keywords.is_empty() {
+            rules.push(skip("bio_keyword", Condition::Any(keywords)));
+        }
+        rules
+    }
+}
+
+impl RuleSet {
+    /// The rules used until the user saves their own: the candidate filter of the settings,
+    /// then following back the followers who are not followed yet.
+    pub fn from_settings(settings: &UserSettings) -> Self {
+        let mut rules = vec![Rule {
+            name: "already_following".to_string(),
+            when: Condition::Any(vec![
+                Condition::Fact(Fact::Friend),
+                Condition::Fact(Fact::OutgoingRequest),
+            ]),
+            decision: Decision::Skip,
+        }];
+        rules.extend(
+            Heuristics {
+                default_profile_image: settings.skip_default_profile_image,
+                min_followers_count: settings.min_followers_count,
+                max_friends_followers_ratio: settings.max_friends_followers_ratio,
+                min_account_age_days: settings.min_account_age_days,
+                bio_keywords: &settings.blocked_bio_keywords,
+            }
+            .rules(),
+        );
+        rules.push(Rule {
+            name: "follower".to_string(),
+            when: Condition::Fact(Fact::Follower),
+            decision: Decision::Follow,
+        });
+        Self {
+            rules,
+            default: Decision::Skip,
+        }
+    }
+
+    /// The rules for the new followers to mute or block. The followers a rule skips are spam,
+    /// and the rule tells why.
+    pub fn spam_from_settings(settings: &UserSettings) -> Self {
+        let rules = Heuristics {
+            default_profile_image: settings.spam_default_profile_image,
+            min_followers_count: settings.spam_min_followers_count,
+            max_friends_followers_ratio: settings.spam_max_friends_followers_ratio,
+            min_account_age_days: settings.spam_min_account_age_days,
+            bio_keywords: &settings.spam_bio_keywords,
+        }
+        .rules();
+        Self {
+            rules,
+            default: Decision::Follow,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rules.len() > MAX_RULES {
+            return Err(format!("at most {} rules are allowed", MAX_RULES));
+        }
+        for rule in &self.rules {
+            if rule.name.trim().is_empty() || rule.name.chars().count() > MAX_NAME_LENGTH {
+                return Err(format!(
+                    "rule names must be between 1 and {} characters",
+                    MAX_NAME_LENGTH
+                ));
+            }
+            rule.when
+                .validate(1)
+                .map_err(|e| format!("rule {}: {}", rule.name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the decision and the rule which made it. Excluded targets are skipped before any
+    /// rule.
+    pub fn evaluate(&self, target: &Target, now: OffsetDateTime) -> (Decision, Option<&Rule>) {
+        if target.exclusion().is_some() {
+            return (Decision::Skip, None);
+        }
+        match self
+            .rules
+            .iter()
+            .find(|rule| rule.when.matches(target, now))
+        {
+            Some(rule) => (rule.decision, Some(rule)),
+            None => (self.default, None),
+        }
+    }
+
+    pub fn explain(&self, target: &Target, now: OffsetDateTime) -> Explanation {
+        let excluded_by = target.exclusion();
+        if excluded_by.is_some() {
+            return Explanation {
+                decision: Decision::Skip,
+                excluded_by,
+                matched_rule: None,
+                rules: vec![],
+            };
+        }
+        let mut traces = vec![];
+        for rule in &self.rules {
+            let matched = rule.when.matches(target, now);
+            traces.push(RuleTrace {
+                name: rule.name.clone(),
+                matched,
+            });
+            if matched {
+                return Explanation {
+                    decision: rule.decision,
+                    excluded_by,
+                    matched_rule: Some(rule.name.clone()),
+                    rules: traces,
+                };
+            }
+        }
+        Explanation {
+            decision: self.default,
+            excluded_by,
+            matched_rule: None,
+            rules: traces,
+        }
+    }
+}
+
+impl Condition {
+    fn validate(&self, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(format!(
+                "conditions can be nested at most {} deep",
+                MAX_DEPTH
+            ));
+        }
+        match self {
+            Condition::All(conditions) | Condition::Any(conditions) => conditions
+                .iter()
+                .try_for_each(|condition| condition.validate(depth + 1)),
+            Condition::Not(condition) => condition.validate(depth + 1),
+            Condition::Fact(_) => Ok(()),
+            Condition::Compare { value, .. } if !value.is_finite() => {
+                Err("compared values must be finite".to_string())
+            }
+            Condition::Compare { .. } => Ok(()),
+            Condition::BioContains(keyword) if keyword.trim().is_empty() => {
+                Err("bio_contains must not be empty".to_string())
+            }
+            Condition::BioContains(_) => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, target: &Target, now: OffsetDateTime) -> bool {
+        match self {
+            Condition::All(conditions) => conditions.iter().all(|c| c.matches(target, now)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.matches(target, now)),
+            Condition::Not(condition) => !condition.matches(target, now),
+            Condition::Fact(fact) => target.has(*fact),
+            Condition::Compare {
+                attribute,
+                op,
+                value,
+            } => target
+                .attribute(*attribute, now)
+                .is_some_and(|actual| op.compare(actual, *value)),
+            Condition::BioContains(keyword) => target.profile.as_ref().is_some_and(|profile| {
+                profile
+                    .description
+                    .to_lowercase()
+                    .contains(&keyword.to_lowercase())
+            }),
+        }
+    }
+}
+
+impl Comparison {
+    fn compare(self, actual: f64, value: f64) -> bool {
+        match self {
+            Comparison::Less => actual < value,
+            Comparison::LessOrEqual => actual <= value,
+            Comparison::Greater => actual > value,
+            Comparison::GreaterOrEqual => actual >= value,
+            Comparison::Equal => actual == value,
+        }
+    }
+}
+
+impl Target {
+    pub fn has(&self, fact: Fact) -> bool {
+        match fact {
+            Fact::DefaultProfileImage => self
+                .profile
+                .as_ref()
+                .is_some_and(|profile| profile.default_profile_image),
+            Fact::Protected => self
+                .profile
+                .as_ref()
+                .is_some_and(|profile| profile.protected),
+            fact => self.facts.contains(&fact),
+        }
+    }
+
+    /// Returns the blocklist, block or mute the rules can't override.
+    pub fn exclusion(&self) -> Option<Fact> {
+        EXCLUSIONS.into_iter().find(|&fact| self.has(fact))
+    }
+
+    fn attribute(&self, attribute: Attribute, now: OffsetDateTime) -> Option<f64> {
+        let profile = self.profile.as_ref();
+        let value = match attribute {
+            Attribute::FollowersCount => profile?.followers_count as f64,
+            Attribute::FriendsCount => profile?.friends_count as f64,
+            Attribute::FriendsFollowersRatio => {
+                let profile = profile?;
+                profile.friends_count as f64 / profile.followers_count.max(1) as f64
+            }
+            Attribute::AccountAgeDays => (now - profile?.account_created_at).whole_days() as f64,
+            Attribute::DaysSinceFollow => (now - self.followed_at?).whole_days() as f64,
+        };
+        Some(value)
+    }
+}
+
+/// Loads the synchronized relationships, list memberships and cached profiles of the targets.
+pub async fn load_targets(
+    pool: &PgPool,
+    user_id: i64,
+    target_ids: &[i64],
+) -> Result<BTreeMap<i64, Target>, fantastic_giggle_sql::Error> {
+    let mut targets = target_ids
+        .iter()
+        .map(|&id| (id, Target::default()))
+        .collect::<BTreeMap<_, _>>();
+    for relationship in Relationship::find_by_target_ids(pool, user_id, target_ids).await? {
+        let target = match targets.get_mut(&relationship.target_id) {
+            Some(target) => target,
+            None => continue,
+        };
+        let fact = RELATION_FACTS
+            .iter()
+            .find(|(kind, _)| kind.as_str() == relationship.kind)
+            .map(|&(_, fact)| fact);
+        if let Some(fact) = fact {
+            if fact == Fact::Follower {
+                target.followed_at = Some(relationship.created_at);
+            }
+            target.facts.insert(fact);
+        }
+    }
+    for id in WhiteList::find_existing(pool, user_id, target_ids).await? {
+        if let Some(target) = targets.get_mut(&id) {
+            target.facts.insert(Fact::Whitelisted);
+        }
+    }
+    for id in BlockList::find_existing(pool, user_id, target_ids).await? {
+        if let Some(target) = targets.get_mut(&id) {
+            target.facts.insert(Fact::Blocklisted);
+        }
+    }
+    for profile in Profile::find_by_ids(pool, target_ids).await? {
+        if let Some(target) = targets.get_mut(&profile.id) {
+            target.profile = Some(profile);
+        }
+    }
+    Ok(targets)
+}
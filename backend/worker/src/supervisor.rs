@@ -0,0 +1,192 @@
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Error, Result};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// A worker which keeps running at least this long is considered healthy again,
+    /// and its backoff and restart count are reset.
+    pub healthy_after: Duration,
+    pub max_restarts: u32,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+            healthy_after: Duration::from_secs(10 * 60),
+            max_restarts: 10,
+        }
+    }
+}
+
+impl RestartPolicy {
+    fn backoff(&self, restarts: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(restarts.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkerState {
+    Running,
+    Restarting { at: SystemTime },
+    GaveUp,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerStatus {
+    pub name: String,
+    pub state: WorkerState,
+    pub started_at: SystemTime,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct SupervisorHandle {
+    statuses: Arc<Mutex<BTreeMap<String, WorkerStatus>>>,
+}
+
+impl SupervisorHandle {
+    pub fn statuses(&self) -> Vec<WorkerStatus> {
+        self.statuses.lock().unwrap().values().cloned().collect()
+    }
+
+    fn update<F: FnOnce(&mut WorkerStatus)>(&self, name: &str, f: F) {
+        if let Some(status) = self.statuses.lock().unwrap().get_mut(name) {
+            f(status);
+        }
+    }
+}
+
+pub struct Supervisor {
+    policy: RestartPolicy,
+    handle: SupervisorHandle,
+    gave_up_tx: UnboundedSender<Error>,
+    gave_up_rx: UnboundedReceiver<Error>,
+}
+
+impl Supervisor {
+    pub fn new(policy: RestartPolicy) -> Self {
+        let (gave_up_tx, gave_up_rx) = unbounded_channel();
+        Self {
+            policy,
+            handle: SupervisorHandle::default(),
+            gave_up_tx,
+            gave_up_rx,
+        }
+    }
+
+    pub fn handle(&self) -> SupervisorHandle {
+        self.handle.clone()
+    }
+
+    /// Runs the worker built by `factory` in the background, and builds a new one
+    /// whenever the previous one returns, fails or panics.
+    pub fn spawn<F, Fut>(&self, name: &str, factory: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.handle.statuses.lock().unwrap().insert(
+            name.to_string(),
+            WorkerStatus {
+                name: name.to_string(),
+                state: WorkerState::Running,
+                started_at: SystemTime::now(),
+                restarts: 0,
+                last_error: None,
+            },
+        );
+
+        let name = name.to_string();
+        let policy = self.policy.clone();
+        let handle = self.handle.clone();
+        let gave_up_tx = self.gave_up_tx.clone();
+        tokio::spawn(async move {
+            let mut restarts = 0;
+            loop {
+                let started = Instant::now();
+                handle.update(&name, |status| {
+                    status.state = WorkerState::Running;
+                    status.started_at = SystemTime::now();
+                });
+
+                let error = match tokio::spawn(factory()).await {
+                    Ok(Ok(())) => "worker exited".to_string(),
+                    Ok(Err(e)) => format!("{:?}", e),
+                    Err(e) if e.is_panic() => {
+                        format!("panicked: {}", panic_message(e.into_panic()))
+                    }
+                    Err(e) => format!("{:?}", e),
+                };
+                log::error!("worker {} stopped: {}", name, error);
+
+                if started.elapsed() >= policy.healthy_after {
+                    restarts = 0;
+                }
+                restarts += 1;
+                if restarts > policy.max_restarts {
+                    handle.update(&name, |status| {
+                        status.state = WorkerState::GaveUp;
+                        status.last_error = Some(error.clone());
+                    });
+                    let _ = gave_up_tx.send(anyhow!(
+                        "worker {} exceeded {} restarts: {}",
+                        name,
+                        policy.max_restarts,
+                        error
+                    ));
+                    return;
+                }
+
+                let backoff = policy.backoff(restarts);
+                log::info!("restarting worker {} in {:?}", name, backoff);
+                handle.update(&name, |status| {
+                    status.state = WorkerState::Restarting {
+                        at: SystemTime::now() + backoff,
+                    };
+                    status.restarts = restarts;
+                    status.last_error = Some(error);
+                });
+                sleep(backoff).await;
+            }
+        });
+    }
+
+    /// Waits until any of the workers exceeds the restart threshold.
+    pub async fn wait(mut self) -> Error {
+        self.gave_up_rx
+            .recv()
+            .await
+            .expect("the supervisor holds a sender")
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
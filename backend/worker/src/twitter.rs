@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use egg_mode::{
+    auth::verify_tokens,
+    direct::DraftMessage,
+    error::Error,
+    list::memberships,
+    user::{
+        block, blocks_ids, follow, followers_ids, friends_ids, incoming_requests, lookup, mute,
+        mutes_ids, outgoing_requests, relation_lookup, unblock, unmute, Connection, TwitterUser,
+    },
+    Token,
+};
+use fantastic_giggle_sql::{OffsetDateTime, Profile, RelationKind};
+
+use crate::{FollowClient, IdsClient, IdsPage, LiveRelation, MessageClient, ModerationClient};
+
+const USER_LOOKUP_LIMIT: usize = 100;
+
+/// The v1.1 API, through egg-mode.
+#[derive(Debug, Clone)]
+pub struct TwitterClient {
+    pub id_page_size: i32,
+    pub list_page_size: i32,
+}
+
+impl Default for TwitterClient {
+    fn default() -> Self {
+        Self {
+            id_page_size: 5000,
+            list_page_size: 1000,
+        }
+    }
+}
+
+#[async_trait]
+impl IdsClient for TwitterClient {
+    async fn verify_token(&self, token: &Token) -> Result<(), Error> {
+        verify_tokens(token).await?;
+        Ok(())
+    }
+
+    async fn fetch_ids(
+        &self,
+        kind: RelationKind,
+        user_id: i64,
+        token: &Token,
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error> {
+        let next_cursor = match cursor {
+            Some(cursor) => cursor
+                .parse::<i64>()
+                .map_err(|_| Error::InvalidResponse("invalid cursor", Some(cursor.to_string())))?,
+            None => -1,
+        };
+        let user_id = user_id as u64;
+        let mut cursor = match kind {
+            RelationKind::Follower => followers_ids(user_id, token),
+            RelationKind::Friend => friends_ids(user_id, token),
+            RelationKind::Blocking => blocks_ids(token),
+            RelationKind::Muting => mutes_ids(token),
+            RelationKind::IncomingRequest => incoming_requests(token),
+            RelationKind::OutgoingRequest => outgoing_requests(token),
+            RelationKind::ListMembership => {
+                let mut cursor = memberships(user_id, token);
+                cursor.page_size = Some(self.list_page_size);
+                cursor.next_cursor = next_cursor;
+                let response = cursor.call().await?;
+                let ids = response.lists.iter().map(|list| list.id as i64).collect();
+                return Ok((ids, next_page(response.next_cursor)));
+            }
+        };
+        cursor.page_size = Some(self.id_page_size);
+        cursor.next_cursor = next_cursor;
+        let response = cursor.call().await?;
+        let ids = response.ids.iter().map(|&id| id as i64).collect();
+        Ok((ids, next_page(response.next_cursor)))
+    }
+}
+
+#[async_trait]
+impl FollowClient for TwitterClient {
+    async fn lookup_relations(
+        &self,
+        _: i64,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Option<BTreeMap<u64, LiveRelation>>, Error> {
+        let mut relations = BTreeMap::new();
+        for chunk in user_ids.chunks(USER_LOOKUP_LIMIT) {
+            for relationship in relation_lookup(chunk.to_vec(), token).await?.response {
+                let mut relation = LiveRelation::default();
+                for connection in &relationship.connections {
+                    match connection {
+                        Connection::FollowingReceived | Connection::FollowedBy => {
+                            relation.followed_by = true;
+                        }
+                        Connection::Following | Connection::FollowingRequested => {
+                            relation.following = true;
+                        }
+                        _ => {}
+                    }
+                }
+                relations.insert(relationship.id, relation);
+            }
+        }
+        Ok(Some(relations))
+    }
+
+    async fn lookup_profiles(
+        &self,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Vec<Profile>, Error> {
+        let mut profiles = vec![];
+        for chunk in user_ids.chunks(USER_LOOKUP_LIMIT) {
+            let users = lookup(chunk.to_vec(), token).await?;
+            profiles.extend(users.iter().map(to_profile));
+        }
+        Ok(profiles)
+    }
+
+    async fn follow(&self, _: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        follow(target_id, false, token).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageClient for TwitterClient {
+    async fn send_message(
+        &self,
+        _: i64,
+        token: &Token,
+        recipient_id: u64,
+        text: &str,
+    ) -> Result<(), Error> {
+        DraftMessage::new(text.to_string(), recipient_id)
+            .send(token)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ModerationClient for TwitterClient {
+    async fn mute(&self, _: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        mute(target_id, token).await?;
+        Ok(())
+    }
+
+    async fn unmute(&self, _: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        unmute(target_id, token).await?;
+        Ok(())
+    }
+
+    async fn block(&self, _: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        block(target_id, token).await?;
+        Ok(())
+    }
+
+    async fn unblock(&self, _: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        unblock(target_id, token).await?;
+        Ok(())
+    }
+}
+
+fn next_page(next_cursor: i64) -> Option<String> {
+    if next_cursor == 0 {
+        None
+    } else {
+        Some(next_cursor.to_string())
+    }
+}
+
+fn to_profile(user: &TwitterUser) -> Profile {
+    let account_created_at = OffsetDateTime::from_unix_timestamp(user.created_at.timestamp())
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    Profile {
+        id: user.id as i64,
+        screen_name: user.screen_name.clone(),
+        name: user.name.clone(),
+        description: user.description.clone().unwrap_or_default(),
+        followers_count: user.followers_count,
+        friends_count: user.friends_count,
+        default_profile_image: user.default_profile_image,
+        protected: user.protected,
+        account_created_at,
+        updated_at: OffsetDateTime::now_utc(),
+    }
+}
@@ -0,0 +1,416 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{OffsetDateTime, Profile, RelationKind};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::{RequestBuilder, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    http::{self, bearer, parse_created_at, to_i32, RateLimitHeader, ResetFormat},
+    FollowClient, IdsClient, IdsPage, MessageClient, ModerationClient,
+};
+
+pub const OAUTH2_SCOPES: &str =
+    "tweet.read users.read follows.read follows.write block.read block.write mute.read mute.write list.read dm.read dm.write offline.access";
+const USER_FIELDS: &str = "created_at,description,profile_image_url,protected,public_metrics";
+const USER_LOOKUP_LIMIT: usize = 100;
+const LIST_PAGE_SIZE: u32 = 100;
+
+const RATE_LIMIT: RateLimitHeader = RateLimitHeader {
+    name: "x-rate-limit-reset",
+    format: ResetFormat::UnixSeconds,
+    default_wait: 15 * 60,
+};
+const BEARER_NEEDED: &str = "the v2 API needs an OAuth 2.0 token";
+
+#[derive(Debug, Clone)]
+pub struct OAuth2Config {
+    pub client_id: String,
+    /// Only confidential clients have a secret.
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub api_url: String,
+    pub authorize_url: String,
+}
+
+impl OAuth2Config {
+    pub fn new(client_id: String, client_secret: Option<String>, redirect_uri: String) -> Self {
+        Self {
+            client_id,
+            client_secret,
+            redirect_uri,
+            api_url: "https://api.twitter.com".to_string(),
+            authorize_url: "https://twitter.com/i/oauth2/authorize".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuth2Token {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: OffsetDateTime,
+}
+
+/// The v2 API with OAuth 2.0 user context tokens, which are passed around as `Token::Bearer`.
+#[derive(Debug, Clone)]
+pub struct TwitterV2Client {
+    http: reqwest::Client,
+    config: OAuth2Config,
+    pub page_size: u32,
+}
+
+#[derive(Deserialize)]
+struct Data<T> {
+    data: Option<T>,
+    #[serde(default)]
+    meta: Meta,
+}
+
+#[derive(Deserialize, Default)]
+struct Meta {
+    next_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Id {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: String,
+    username: String,
+    name: String,
+    #[serde(default)]
+    description: String,
+    created_at: Option<String>,
+    #[serde(default)]
+    protected: bool,
+    profile_image_url: Option<String>,
+    public_metrics: Option<PublicMetrics>,
+}
+
+#[derive(Deserialize)]
+struct PublicMetrics {
+    followers_count: i64,
+    following_count: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: u64,
+}
+
+/// Returns a random PKCE code verifier.
+pub fn pkce_verifier() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+/// Returns the S256 code challenge of a PKCE code verifier.
+pub fn pkce_challenge(verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+impl TwitterV2Client {
+    pub fn new(config: OAuth2Config) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            config,
+            page_size: 1000,
+        }
+    }
+
+    pub fn authorize_url(&self, state: &str, code_challenge: &str) -> Result<String, Error> {
+        Url::parse_with_params(
+            &self.config.authorize_url,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.config.redirect_uri),
+                ("scope", OAUTH2_SCOPES),
+                ("state", state),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map(String::from)
+        .map_err(|_| {
+            Error::InvalidResponse(
+                "invalid authorize url",
+                Some(self.config.authorize_url.clone()),
+            )
+        })
+    }
+
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<OAuth2Token, Error> {
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("code_verifier", code_verifier),
+        ])
+        .await
+    }
+
+    pub async fn refresh_token(&self, refresh_token: &str) -> Result<OAuth2Token, Error> {
+        let mut token = self
+            .request_token(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ])
+            .await?;
+        if token.refresh_token.is_empty() {
+            token.refresh_token = refresh_token.to_string();
+        }
+        Ok(token)
+    }
+
+    /// Returns the ID of the owner of the token.
+    pub async fn me(&self, token: &Token) -> Result<i64, Error> {
+        let response: Data<Id> = self.send(self.get("/2/users/me", token)?).await?;
+        let id = response.data.ok_or(Error::MissingValue("data"))?;
+        parse_id(&id.id)
+    }
+
+    async fn request_token(&self, params: &[(&str, &str)]) -> Result<OAuth2Token, Error> {
+        let mut params = params.to_vec();
+        let url = format!("{}/2/oauth2/token", self.config.api_url);
+        let request = match &self.config.client_secret {
+            Some(secret) => self
+                .http
+                .post(url)
+                .basic_auth(&self.config.client_id, Some(secret)),
+            None => {
+                params.push(("client_id", &self.config.client_id));
+                self.http.post(url)
+            }
+        };
+        let response: TokenResponse = self.send(request.form(&params)).await?;
+        Ok(OAuth2Token {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token.unwrap_or_default(),
+            expires_at: OffsetDateTime::now_utc() + Duration::from_secs(response.expires_in),
+        })
+    }
+
+    fn get(&self, path: &str, token: &Token) -> Result<RequestBuilder, Error> {
+        let url = format!("{}{}", self.config.api_url, path);
+        Ok(self
+            .http
+            .get(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?))
+    }
+
+    async fn create_relation(
+        &self,
+        user_id: i64,
+        endpoint: &str,
+        token: &Token,
+        target_id: u64,
+    ) -> Result<(), Error> {
+        let url = format!("{}/2/users/{}/{}", self.config.api_url, user_id, endpoint);
+        let request = self
+            .http
+            .post(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?)
+            .json(&json!({ "target_user_id": target_id.to_string() }));
+        let _: serde_json::Value = self.send(request).await?;
+        Ok(())
+    }
+
+    async fn delete_relation(
+        &self,
+        user_id: i64,
+        endpoint: &str,
+        token: &Token,
+        target_id: u64,
+    ) -> Result<(), Error> {
+        let url = format!(
+            "{}/2/users/{}/{}/{}",
+            self.config.api_url, user_id, endpoint, target_id
+        );
+        let request = self
+            .http
+            .delete(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?);
+        let _: serde_json::Value = self.send(request).await?;
+        Ok(())
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        http::send(request, &RATE_LIMIT).await
+    }
+}
+
+#[async_trait]
+impl IdsClient for TwitterV2Client {
+    fn supports(&self, kind: RelationKind) -> bool {
+        endpoint(kind).is_some()
+    }
+
+    async fn fetch_ids(
+        &self,
+        kind: RelationKind,
+        user_id: i64,
+        token: &Token,
+        cursor: Option<&str>,
+    ) -> Result<IdsPage, Error> {
+        let endpoint = endpoint(kind).ok_or(Error::InvalidResponse(
+            "not supported by the v2 API",
+            Some(kind.as_str().to_string()),
+        ))?;
+        let page_size = match kind {
+            RelationKind::ListMembership => LIST_PAGE_SIZE,
+            _ => self.page_size,
+        };
+        let mut query = vec![("max_results", page_size.to_string())];
+        if let Some(cursor) = cursor {
+            query.push(("pagination_token", cursor.to_string()));
+        }
+        let request = self
+            .get(&format!("/2/users/{}/{}", user_id, endpoint), token)?
+            .query(&query);
+        let response: Data<Vec<Id>> = self.send(request).await?;
+        let ids = response
+            .data
+            .unwrap_or_default()
+            .iter()
+            .map(|id| parse_id(&id.id))
+            .collect::<Result<_, _>>()?;
+        Ok((ids, response.meta.next_token))
+    }
+}
+
+/// The v2 API can't look up friendships, so the synchronized relationships are trusted.
+#[async_trait]
+impl FollowClient for TwitterV2Client {
+    async fn lookup_profiles(
+        &self,
+        token: &Token,
+        user_ids: &[u64],
+    ) -> Result<Vec<Profile>, Error> {
+        let mut profiles = vec![];
+        for chunk in user_ids.chunks(USER_LOOKUP_LIMIT) {
+            let ids = chunk
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let request = self
+                .get("/2/users", token)?
+                .query(&[("ids", ids.as_str()), ("user.fields", USER_FIELDS)]);
+            let response: Data<Vec<User>> = self.send(request).await?;
+            for user in response.data.unwrap_or_default() {
+                profiles.push(to_profile(user)?);
+            }
+        }
+        Ok(profiles)
+    }
+
+    async fn follow(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        let url = format!("{}/2/users/{}/following", self.config.api_url, user_id);
+        let request = self
+            .http
+            .post(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?)
+            .json(&json!({ "target_user_id": target_id.to_string() }));
+        let _: serde_json::Value = self.send(request).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageClient for TwitterV2Client {
+    async fn send_message(
+        &self,
+        _: i64,
+        token: &Token,
+        recipient_id: u64,
+        text: &str,
+    ) -> Result<(), Error> {
+        let url = format!(
+            "{}/2/dm_conversations/with/{}/messages",
+            self.config.api_url, recipient_id
+        );
+        let request = self
+            .http
+            .post(url)
+            .bearer_auth(bearer(token, BEARER_NEEDED)?)
+            .json(&json!({ "text": text }));
+        let _: serde_json::Value = self.send(request).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ModerationClient for TwitterV2Client {
+    async fn mute(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        self.create_relation(user_id, "muting", token, target_id)
+            .await
+    }
+
+    async fn unmute(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        self.delete_relation(user_id, "muting", token, target_id)
+            .await
+    }
+
+    async fn block(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        self.create_relation(user_id, "blocking", token, target_id)
+            .await
+    }
+
+    async fn unblock(&self, user_id: i64, token: &Token, target_id: u64) -> Result<(), Error> {
+        self.delete_relation(user_id, "blocking", token, target_id)
+            .await
+    }
+}
+
+fn endpoint(kind: RelationKind) -> Option<&'static str> {
+    match kind {
+        RelationKind::Follower => Some("followers"),
+        RelationKind::Friend => Some("following"),
+        RelationKind::Blocking => Some("blocking"),
+        RelationKind::Muting => Some("muting"),
+        RelationKind::ListMembership => Some("list_memberships"),
+        RelationKind::IncomingRequest | RelationKind::OutgoingRequest => None,
+    }
+}
+
+fn parse_id(id: &str) -> Result<i64, Error> {
+    id.parse()
+        .map_err(|_| Error::InvalidResponse("invalid id", Some(id.to_string())))
+}
+
+fn to_profile(user: User) -> Result<Profile, Error> {
+    let account_created_at = parse_created_at(user.created_at.as_deref());
+    let metrics = user.public_metrics.as_ref();
+    Ok(Profile {
+        id: parse_id(&user.id)?,
+        screen_name: user.username,
+        name: user.name,
+        description: user.description,
+        followers_count: metrics.map_or(0, |m| to_i32(m.followers_count)),
+        friends_count: metrics.map_or(0, |m| to_i32(m.following_count)),
+        default_profile_image: user
+            .profile_image_url
+            .is_some_and(|url| url.contains("default_profile_images")),
+        protected: user.protected,
+        account_created_at,
+        updated_at: OffsetDateTime::now_utc(),
+    })
+}
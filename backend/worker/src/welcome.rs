@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use egg_mode::error::Error;
+use fantastic_giggle_sql::{
+    AuditAction, AuditActor, AuditLog, AuditRecord, Identity, OffsetDateTime, PgPool, Profile,
+    UserSettings, WelcomeMessage,
+};
+use tokio::time::sleep;
+
+use crate::{Credentials, EventBus, FollowClient, MessageClient, WorkerEvent};
+
+/// Twitter allows 1000 direct messages a day. Leave some for the user.
+const DAILY_MESSAGE_CAP: i64 = 500;
+const MESSAGE_INTERVAL: Duration = Duration::from_secs(30);
+/// The length of a rate limit window, so that a rate-limited user is retried in the next round.
+const ROUND_PAUSE: Duration = Duration::from_secs(15 * 60);
+/// Only the followers gained recently are greeted, not everyone in the history.
+pub const WELCOME_MESSAGE_LOOKBACK: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Replaces `{name}` and `{screen_name}` in the template with the ones of the follower.
+pub fn render_welcome_message(template: &str, profile: &Profile) -> String {
+    // In one pass, so that placeholders in the names of the followers are kept as they are.
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(tail) = rest.strip_prefix("{name}") {
+            message.push_str(&profile.name);
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("{screen_name}") {
+            message.push_str(&profile.screen_name);
+            rest = tail;
+        } else {
+            message.push('{');
+            rest = &rest[1..];
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+/// Whether sending the message again would fail the same way, as opposed to failures of the
+/// network or of the servers.
+fn is_permanent(error: &Error) -> bool {
+    match error {
+        // 130: Over capacity. 131: Internal error.
+        Error::TwitterError(_, errors) => {
+            errors.errors.iter().all(|e| e.code != 130 && e.code != 131)
+        }
+        Error::BadStatus(status) => status.is_client_error(),
+        _ => false,
+    }
+}
+
+/// Sends the welcome message of the user to each new follower once.
+pub struct WelcomeMessageWorker<T> {
+    pool: PgPool,
+    client: T,
+    credentials: Credentials,
+    events: EventBus,
+}
+
+impl<T: FollowClient + MessageClient> WelcomeMessageWorker<T> {
+    pub fn new(pool: PgPool, client: T, credentials: Credentials, events: EventBus) -> Self {
+        Self {
+            pool,
+            client,
+            credentials,
+            events,
+        }
+    }
+    pub async fn run(&self) {
+        loop {
+            match Identity::find_by_provider(&self.pool, self.credentials.provider()).await {
+                Ok(identities) => {
+                    for identity in identities {
+                        if let Err(e) = self.greet(identity).await {
+                            log::error!("{:?}", e);
+                        }
+                    }
+                }
+                Err(e) => log::error!("database error: {:?}", e),
+            }
+            sleep(ROUND_PAUSE).await;
+        }
+    }
+
+    /// Records the outcome of a message in the audit log, with the message itself in the same
+    /// transaction.
+    async fn finish_message(
+        &self,
+        user_id: i64,
+        target_id: i64,
+        text: &str,
+        dry_run: bool,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let record = AuditRecord::new(user_id, AuditActor::Worker, AuditAction::DirectMessage)
+            .target(target_id)
+            .parameters(serde_json::json!({ "text": text }).to_string())
+            .dry_run(dry_run)
+            .error(error);
+        let mut tx = self.pool.begin().await?;
+        if dry_run {
+            WelcomeMessage::save_dry_run(&mut tx, user_id, target_id, text).await?;
+        } else {
+            WelcomeMessage::finish(&mut tx, user_id, target_id, error).await?;
+        }
+        AuditLog::append(&mut tx, &record).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn greet(&self, identity: Identity) -> Result<()> {
+        let user_id = identity.id;
+        let settings = UserSettings::find_or_default(&self.pool, user_id).await?;
+        if !settings.welcome_message_enabled || settings.welcome_message_template.is_empty() {
+            return Ok(());
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let sent_today = WelcomeMessage::count_since(&self.pool, user_id, now - DAY).await?;
+        let remaining = DAILY_MESSAGE_CAP - sent_today;
+        if remaining <= 0 {
+            log::info!("daily welcome message cap reached for {}", user_id);
+            return Ok(());
+        }
+        let target_ids = WelcomeMessage::find_pending(
+            &self.pool,
+            user_id,
+            now - WELCOME_MESSAGE_LOOKBACK,
+            settings.dry_run,
+            remaining,
+        )
+        .await?;
+        if target_ids.is_empty() {
+            return Ok(());
+        }
+
+        let token = self.credentials.token(&self.pool, identity).await?;
+        let target_ids = target_ids
+            .into_iter()
+            .map(|id| id as u64)
+            .collect::<Vec<_>>();
+        let profiles = self.client.lookup_profiles(&token, &target_ids).await?;
+        for profile in profiles {
+            Profile::save(&self.pool, &profile).await?;
+            let text = render_welcome_message(&settings.welcome_message_template, &profile);
+            if settings.dry_run {
+                log::info!("dry run: would greet {} for {}", profile.id, user_id);
+                self.finish_message(user_id, profile.id, &text, true, None)
+                    .await?;
+                self.events.publish(WorkerEvent::WelcomeMessageSent {
+                    user_id,
+                    target_id: profile.id,
+                    dry_run: true,
+                });
+                continue;
+            }
+
+            if !WelcomeMessage::save_pending(&self.pool, user_id, profile.id, &text).await? {
+                continue;
+            }
+            match self
+                .client
+                .send_message(user_id, &token, profile.id as u64, &text)
+                .await
+            {
+                Ok(()) => {
+                    log::info!("greeted {}", profile.id);
+                    self.finish_message(user_id, profile.id, &text, false, None)
+                        .await?;
+                    self.events.publish(WorkerEvent::WelcomeMessageSent {
+                        user_id,
+                        target_id: profile.id,
+                        dry_run: false,
+                    });
+                }
+                Err(Error::RateLimit(timestamp)) => {
+                    log::info!("rate limit exceeded for {} until {}", user_id, timestamp);
+                    WelcomeMessage::delete_pending(&self.pool, user_id, profile.id).await?;
+                    self.events.publish(WorkerEvent::RateLimited {
+                        user_id,
+                        worker: "welcome_message",
+                        until: timestamp as i64,
+                    });
+                    return Ok(());
+                }
+                Err(e) if is_permanent(&e) => {
+                    // Mostly followers who don't accept messages, so they are not retried.
+                    log::error!("failed to greet {}: {:?}", profile.id, e);
+                    self.finish_message(user_id, profile.id, &text, false, Some(&e.to_string()))
+                        .await?;
+                    self.events.publish(WorkerEvent::WelcomeMessageFailed {
+                        user_id,
+                        target_id: profile.id,
+                    });
+                }
+                Err(e) => {
+                    log::error!("failed to greet {}, retrying later: {:?}", profile.id, e);
+                    WelcomeMessage::delete_pending(&self.pool, user_id, profile.id).await?;
+                    return Ok(());
+                }
+            }
+            sleep(MESSAGE_INTERVAL).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+mod common;
+
+use egg_mode::error::Error;
+use fantastic_giggle_sql::RelationKind;
+use fantastic_giggle_worker::{BlueskyClient, LiveRelation};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use common::{header, query, token, unused_pool};
+
+const ALICE: &str = "did:plc:alice";
+
+fn client(server: &MockServer) -> BlueskyClient {
+    BlueskyClient::new(unused_pool(), server.uri())
+}
+
+fn jwt(exp: i64) -> String {
+    let payload = json!({ "sub": ALICE, "exp": exp }).to_string();
+    format!(
+        "eyJhbGciOiJIUzI1NiJ9.{}.signature",
+        base64::encode_config(payload, base64::URL_SAFE_NO_PAD)
+    )
+}
+
+fn actor(did: &str) -> Value {
+    json!({ "did": did, "handle": format!("{}.bsky.social", did) })
+}
+
+#[tokio::test]
+async fn creates_session_with_app_password() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/xrpc/com.atproto.server.createSession"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "did": ALICE,
+            "handle": "alice.bsky.social",
+            "accessJwt": jwt(1700000000),
+            "refreshJwt": "refresh_jwt"
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let session = client
+        .create_session("alice.bsky.social", "app-password")
+        .await
+        .unwrap();
+    assert_eq!(session.did, ALICE);
+    assert_eq!(session.access_jwt, jwt(1700000000));
+    assert_eq!(session.refresh_jwt, "refresh_jwt");
+    assert_eq!(session.expires_at.unix_timestamp(), 1700000000);
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(
+        body,
+        json!({ "identifier": "alice.bsky.social", "password": "app-password" })
+    );
+}
+
+#[tokio::test]
+async fn refreshes_session_with_refresh_jwt() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/xrpc/com.atproto.server.refreshSession"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "did": ALICE,
+            "handle": "alice.bsky.social",
+            "accessJwt": jwt(1700003600),
+            "refreshJwt": "new_refresh_jwt"
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let session = client.refresh_session("refresh_jwt").await.unwrap();
+    assert_eq!(session.refresh_jwt, "new_refresh_jwt");
+    assert_eq!(session.expires_at.unix_timestamp(), 1700003600);
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        header(&requests[0], "authorization").as_deref(),
+        Some("Bearer refresh_jwt")
+    );
+}
+
+#[tokio::test]
+async fn pages_followers_with_cursors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/xrpc/app.bsky.graph.getFollowers"))
+        .and(query_param("cursor", "NEXT"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "subject": actor(ALICE),
+            "followers": [actor("did:plc:carol")]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/xrpc/app.bsky.graph.getFollowers"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "subject": actor(ALICE),
+            "followers": [actor("did:plc:bob"), actor("did:plc:dave")],
+            "cursor": "NEXT"
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let first = client
+        .fetch_dids(RelationKind::Follower, ALICE, &token(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        first,
+        (
+            vec!["did:plc:bob".to_string(), "did:plc:dave".to_string()],
+            Some("NEXT".to_string())
+        )
+    );
+    let last = client
+        .fetch_dids(RelationKind::Follower, ALICE, &token(), Some("NEXT"))
+        .await
+        .unwrap();
+    assert_eq!(last, (vec!["did:plc:carol".to_string()], None));
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        query(&requests[0]),
+        vec![
+            ("limit".to_string(), "100".to_string()),
+            ("actor".to_string(), ALICE.to_string()),
+        ]
+    );
+    assert_eq!(
+        query(&requests[1]),
+        vec![
+            ("limit".to_string(), "100".to_string()),
+            ("actor".to_string(), ALICE.to_string()),
+            ("cursor".to_string(), "NEXT".to_string()),
+        ]
+    );
+    for request in &requests {
+        assert_eq!(
+            header(request, "authorization").as_deref(),
+            Some("Bearer access_token")
+        );
+    }
+}
+
+#[tokio::test]
+async fn fetches_follows_and_stops_on_empty_cursor() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/xrpc/app.bsky.graph.getFollows"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "subject": actor(ALICE),
+            "follows": [actor("did:plc:bob")],
+            "cursor": ""
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let page = client
+        .fetch_dids(RelationKind::Friend, ALICE, &token(), None)
+        .await
+        .unwrap();
+    assert_eq!(page, (vec!["did:plc:bob".to_string()], None));
+
+    let result = client
+        .fetch_dids(RelationKind::IncomingRequest, ALICE, &token(), None)
+        .await;
+    assert!(result.is_err());
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn maps_too_many_requests_to_rate_limit() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/xrpc/app.bsky.graph.getFollowers"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("ratelimit-reset", "1700000000")
+                .set_body_json(json!({ "error": "RateLimitExceeded" })),
+        )
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let result = client
+        .fetch_dids(RelationKind::Follower, ALICE, &token(), None)
+        .await;
+    assert!(matches!(result, Err(Error::RateLimit(1700000000))));
+}
+
+#[tokio::test]
+async fn saturates_resets_beyond_the_range_of_the_rate_limit() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/xrpc/app.bsky.graph.getFollowers"))
+        .respond_with(ResponseTemplate::new(429).insert_header("ratelimit-reset", "99999999999"))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let result = client
+        .fetch_dids(RelationKind::Follower, ALICE, &token(), None)
+        .await;
+    assert!(matches!(result, Err(Error::RateLimit(i32::MAX))));
+}
+
+#[tokio::test]
+async fn looks_up_relations() {
+    let server = MockServer::start().await;
+    let follow = |did: &str| format!("at://{}/app.bsky.graph.follow/3k", did);
+    Mock::given(method("GET"))
+        .and(path("/xrpc/app.bsky.actor.getProfiles"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "profiles": [
+                { "did": "did:plc:bob", "handle": "bob", "viewer": { "followedBy": follow("did:plc:bob") } },
+                { "did": "did:plc:carol", "handle": "carol", "viewer": { "followedBy": follow("did:plc:carol"), "following": follow(ALICE) } },
+                { "did": "did:plc:dave", "handle": "dave", "viewer": {} }
+            ]
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+    let dids = ["did:plc:bob", "did:plc:carol", "did:plc:dave"].map(String::from);
+
+    let relations = client.fetch_relations(&token(), &dids).await.unwrap();
+    let relation = |followed_by, following| LiveRelation {
+        followed_by,
+        following,
+    };
+    assert_eq!(
+        relations.into_iter().collect::<Vec<_>>(),
+        vec![
+            ("did:plc:bob".to_string(), relation(true, false)),
+            ("did:plc:carol".to_string(), relation(true, true)),
+            ("did:plc:dave".to_string(), relation(false, false)),
+        ]
+    );
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        query(&requests[0]),
+        dids.iter()
+            .map(|did| ("actors".to_string(), did.clone()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn follows_by_creating_a_follow_record() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/xrpc/com.atproto.repo.createRecord"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uri": "at://did:plc:alice/app.bsky.graph.follow/3k",
+            "cid": "bafyrei"
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    client
+        .follow_did(&token(), ALICE, "did:plc:bob")
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        header(&requests[0], "authorization").as_deref(),
+        Some("Bearer access_token")
+    );
+    let mut body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    let created_at = body["record"]
+        .as_object_mut()
+        .unwrap()
+        .remove("createdAt")
+        .unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(created_at.as_str().unwrap()).is_ok());
+    assert_eq!(
+        body,
+        json!({
+            "repo": ALICE,
+            "collection": "app.bsky.graph.follow",
+            "record": {
+                "$type": "app.bsky.graph.follow",
+                "subject": "did:plc:bob"
+            }
+        })
+    );
+}
@@ -0,0 +1,38 @@
+//! Helpers shared by the tests of the HTTP clients.
+#![allow(dead_code)]
+
+use egg_mode::Token;
+use fantastic_giggle_sql::PgPool;
+use wiremock::Request;
+
+/// A pool for the clients which don't touch the database in the tests.
+pub fn unused_pool() -> PgPool {
+    PgPool::connect_lazy("postgres://localhost/unused").unwrap()
+}
+
+pub fn token() -> Token {
+    Token::Bearer("access_token".to_string())
+}
+
+pub fn pairs(input: &[u8]) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(input)
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+pub fn query(request: &Request) -> Vec<(String, String)> {
+    pairs(request.url.query().unwrap_or_default().as_bytes())
+}
+
+/// The fields of a form-encoded body.
+pub fn form(request: &Request) -> Vec<(String, String)> {
+    pairs(&request.body)
+}
+
+pub fn header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers
+        .iter()
+        .find(|(key, _)| key.as_str() == name)
+        .map(|(_, values)| values.last().as_str().to_string())
+}
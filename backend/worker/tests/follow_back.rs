@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{Identity, OffsetDateTime, Profile, RelationKind, Relationship};
+use fantastic_giggle_test::connect_to_test_sql;
+use fantastic_giggle_worker::{
+    Credentials, EventBus, FollowBackWorker, FollowClient, LiveRelation, PacingPolicy,
+};
+
+const USER_ID: i64 = 3_400_001;
+/// Still follows the user and is not followed.
+const FOLLOWER: i64 = 3_400_011;
+/// Followed by the user since the last synchronization.
+const FOLLOWED: i64 = 3_400_012;
+/// Unfollowed the user since the last synchronization.
+const UNFOLLOWED: i64 = 3_400_013;
+const MUTED: i64 = 3_400_014;
+const BLOCKED: i64 = 3_400_015;
+
+/// Reports the relationships as they are now, unlike the synchronized ones.
+struct LiveClient;
+
+#[async_trait]
+impl FollowClient for LiveClient {
+    async fn lookup_relations(
+        &self,
+        _: i64,
+        _: &Token,
+        user_ids: &[u64],
+    ) -> Result<Option<BTreeMap<u64, LiveRelation>>, Error> {
+        Ok(Some(
+            user_ids
+                .iter()
+                .map(|&id| {
+                    let relation = LiveRelation {
+                        followed_by: id != UNFOLLOWED as u64,
+                        following: id == FOLLOWED as u64,
+                    };
+                    (id, relation)
+                })
+                .collect(),
+        ))
+    }
+
+    async fn lookup_profiles(&self, _: &Token, user_ids: &[u64]) -> Result<Vec<Profile>, Error> {
+        Ok(user_ids
+            .iter()
+            .map(|&id| Profile {
+                id: id as i64,
+                screen_name: id.to_string(),
+                name: id.to_string(),
+                description: String::new(),
+                followers_count: 100,
+                friends_count: 100,
+                default_profile_image: false,
+                protected: false,
+                account_created_at: OffsetDateTime::UNIX_EPOCH,
+                updated_at: OffsetDateTime::now_utc(),
+            })
+            .collect())
+    }
+
+    async fn follow(&self, _: i64, _: &Token, _: u64) -> Result<(), Error> {
+        Err(Error::MissingValue("candidates never follow"))
+    }
+}
+
+fn identity() -> Identity {
+    Identity {
+        id: USER_ID,
+        account_id: USER_ID,
+        provider: "mastodon".to_string(),
+        access_key: "access_key".to_string(),
+        access_secret: String::new(),
+        token_expires_at: None,
+    }
+}
+
+#[tokio::test]
+async fn candidates_are_checked_against_the_live_relationships() {
+    let pool = connect_to_test_sql().await.unwrap();
+    Identity::save(&pool, identity()).await.unwrap();
+    let followers = [FOLLOWER, FOLLOWED, UNFOLLOWED, MUTED, BLOCKED];
+    Relationship::save(&pool, USER_ID, RelationKind::Follower, &followers)
+        .await
+        .unwrap();
+    Relationship::save(&pool, USER_ID, RelationKind::Muting, &[MUTED])
+        .await
+        .unwrap();
+    Relationship::save(&pool, USER_ID, RelationKind::Blocking, &[BLOCKED])
+        .await
+        .unwrap();
+
+    let worker = FollowBackWorker::new(
+        pool,
+        LiveClient,
+        Credentials::Mastodon,
+        EventBus::default(),
+        PacingPolicy::default(),
+    );
+    let candidates = worker.candidates(identity()).await.unwrap();
+    assert_eq!(candidates, vec![FOLLOWER as u64]);
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use egg_mode::{error::Error, KeyPair, Token};
+use fantastic_giggle_sql::{Error as SqlError, OffsetDateTime, PgPool, RelationKind};
+use fantastic_giggle_worker::{
+    Credentials, DataConnector, EventBus, IdSynchronizer, IdsPage, WorkerEvent,
+};
+use tokio::sync::broadcast::Receiver;
+
+type Page = Result<IdsPage, Error>;
+
+#[derive(Default)]
+struct Log {
+    pages: BTreeMap<i64, VecDeque<Page>>,
+    fetched: Vec<(i64, Option<String>)>,
+    saved: Vec<(i64, Vec<i64>)>,
+    finished: Vec<i64>,
+    /// Saves to fail before they succeed again.
+    failing_saves: u32,
+    fail_finish: bool,
+    verified: Vec<String>,
+    /// Pauses the user once a page of them was fetched.
+    pause_on_fetch: bool,
+    paused: Vec<i64>,
+}
+
+#[derive(Clone, Default)]
+struct InMemoryConnector {
+    log: Arc<Mutex<Log>>,
+}
+
+impl InMemoryConnector {
+    fn push_page(&self, user_id: i64, page: Page) {
+        let mut log = self.log.lock().unwrap();
+        log.pages.entry(user_id).or_default().push_back(page);
+    }
+}
+
+#[async_trait]
+impl DataConnector for InMemoryConnector {
+    fn kind(&self) -> RelationKind {
+        RelationKind::Follower
+    }
+    async fn verify_token(&self, token: &Token) -> Result<(), Error> {
+        let key = match token {
+            Token::Access { access, .. } => access.key.to_string(),
+            Token::Bearer(token) => token.clone(),
+        };
+        self.log.lock().unwrap().verified.push(key.clone());
+        if key == "revoked" {
+            return Err(Error::InvalidResponse("invalid token", None));
+        }
+        Ok(())
+    }
+    async fn fetch_ids(&self, user_id: i64, _: &Token, cursor: Option<&str>) -> Page {
+        let mut log = self.log.lock().unwrap();
+        log.fetched.push((user_id, cursor.map(String::from)));
+        if log.pause_on_fetch {
+            log.paused.push(user_id);
+        }
+        log.pages
+            .get_mut(&user_id)
+            .and_then(|pages| pages.pop_front())
+            .expect("unexpected fetch")
+    }
+    async fn is_active(&self, user_id: i64) -> Result<bool, SqlError> {
+        Ok(!self.log.lock().unwrap().paused.contains(&user_id))
+    }
+    async fn save_ids(&self, user_id: i64, ids: &[i64]) -> Result<(), SqlError> {
+        let mut log = self.log.lock().unwrap();
+        if log.failing_saves > 0 {
+            log.failing_saves -= 1;
+            return Err(SqlError::PoolTimedOut);
+        }
+        log.saved.push((user_id, ids.to_vec()));
+        Ok(())
+    }
+    async fn finish_sync(&self, user_id: i64, _: OffsetDateTime) -> Result<(), SqlError> {
+        let mut log = self.log.lock().unwrap();
+        if log.fail_finish {
+            return Err(SqlError::PoolTimedOut);
+        }
+        log.finished.push(user_id);
+        Ok(())
+    }
+}
+
+fn token() -> Token {
+    Token::Access {
+        consumer: KeyPair::new("consumer_key", "consumer_secret"),
+        access: KeyPair::new("access_key", "access_secret"),
+    }
+}
+
+fn revoked_token() -> Token {
+    Token::Access {
+        consumer: KeyPair::new("consumer_key", "consumer_secret"),
+        access: KeyPair::new("revoked", "access_secret"),
+    }
+}
+
+fn synchronizer(connector: &InMemoryConnector) -> (IdSynchronizer<InMemoryConnector>, EventBus) {
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let events = EventBus::default();
+    let synchronizer = IdSynchronizer::new(
+        Credentials::OAuth1(KeyPair::new("consumer_key", "consumer_secret")),
+        pool,
+        connector.clone(),
+        events.clone(),
+    );
+    (synchronizer, events)
+}
+
+fn drain(receiver: &mut Receiver<WorkerEvent>) -> Vec<WorkerEvent> {
+    let mut events = vec![];
+    while let Ok(event) = receiver.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[tokio::test(start_paused = true)]
+async fn follows_cursors_until_exhausted() {
+    let connector = InMemoryConnector::default();
+    connector.push_page(1, Ok((vec![10, 11], Some("100".to_string()))));
+    connector.push_page(1, Ok((vec![12], None)));
+    let (synchronizer, events) = synchronizer(&connector);
+    let mut receiver = events.subscribe();
+
+    synchronizer
+        .sync(vec![(1, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.fetched, vec![(1, None), (1, Some("100".to_string()))]);
+    assert_eq!(log.saved, vec![(1, vec![10, 11]), (1, vec![12])]);
+    assert_eq!(log.finished, vec![1]);
+
+    let events = drain(&mut receiver);
+    assert_eq!(events.len(), 3);
+    assert!(matches!(
+        events[2],
+        WorkerEvent::SyncCompleted {
+            user_id: 1,
+            kind: "follower",
+            pages: 2
+        }
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn reschedules_rate_limited_users() {
+    let connector = InMemoryConnector::default();
+    let reset = now() as i32;
+    connector.push_page(1, Err(Error::RateLimit(reset)));
+    connector.push_page(1, Ok((vec![10], None)));
+    let (synchronizer, events) = synchronizer(&connector);
+    let mut receiver = events.subscribe();
+
+    synchronizer
+        .sync(vec![(1, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.fetched, vec![(1, None), (1, None)]);
+    assert_eq!(log.finished, vec![1]);
+
+    let events = drain(&mut receiver);
+    assert!(matches!(
+        events[0],
+        WorkerEvent::RateLimited { user_id: 1, until, .. } if until == reset as i64
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn drops_user_on_twitter_error() {
+    let connector = InMemoryConnector::default();
+    connector.push_page(1, Ok((vec![10], Some("100".to_string()))));
+    connector.push_page(1, Err(Error::InvalidResponse("broken", None)));
+    connector.push_page(2, Ok((vec![20], None)));
+    let (synchronizer, _) = synchronizer(&connector);
+
+    synchronizer
+        .sync(vec![(1, token()), (2, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.fetched.iter().filter(|(id, _)| *id == 1).count(), 2);
+    assert_eq!(log.finished, vec![2]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_user_after_database_error() {
+    let connector = InMemoryConnector::default();
+    connector.log.lock().unwrap().failing_saves = 1;
+    connector.push_page(1, Ok((vec![10], Some("100".to_string()))));
+    connector.push_page(1, Ok((vec![10], Some("100".to_string()))));
+    connector.push_page(1, Ok((vec![11], None)));
+    let (synchronizer, events) = synchronizer(&connector);
+    let mut receiver = events.subscribe();
+
+    synchronizer
+        .sync(vec![(1, token())], OffsetDateTime::now_utc())
+        .await;
+
+    // The page which failed to be saved is fetched again.
+    let log = connector.log.lock().unwrap();
+    assert_eq!(
+        log.fetched,
+        vec![(1, None), (1, None), (1, Some("100".to_string()))]
+    );
+    assert_eq!(log.saved, vec![(1, vec![10]), (1, vec![11])]);
+    assert_eq!(log.finished, vec![1]);
+    assert_eq!(drain(&mut receiver).len(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn does_not_report_completion_when_finishing_fails() {
+    let connector = InMemoryConnector::default();
+    connector.log.lock().unwrap().fail_finish = true;
+    connector.push_page(1, Ok((vec![10], None)));
+    let (synchronizer, events) = synchronizer(&connector);
+    let mut receiver = events.subscribe();
+
+    synchronizer
+        .sync(vec![(1, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let events = drain(&mut receiver);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], WorkerEvent::SyncPageFetched { .. }));
+}
+
+#[tokio::test(start_paused = true)]
+async fn verifies_tokens_once_before_the_first_page() {
+    let connector = InMemoryConnector::default();
+    connector.push_page(1, Ok((vec![10], Some("100".to_string()))));
+    connector.push_page(1, Ok((vec![11], None)));
+    let (synchronizer, _) = synchronizer(&connector);
+
+    synchronizer
+        .sync(vec![(1, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.verified, vec!["access_key".to_string()]);
+    assert_eq!(log.finished, vec![1]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn drops_user_whose_token_is_rejected() {
+    let connector = InMemoryConnector::default();
+    connector.push_page(2, Ok((vec![20], None)));
+    let (synchronizer, events) = synchronizer(&connector);
+    let mut receiver = events.subscribe();
+
+    synchronizer
+        .sync(
+            vec![(1, revoked_token()), (2, token())],
+            OffsetDateTime::now_utc(),
+        )
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.fetched, vec![(2, None)]);
+    assert_eq!(log.finished, vec![2]);
+    assert!(drain(&mut receiver)
+        .iter()
+        .all(|event| !matches!(event, WorkerEvent::SyncPageFetched { user_id: 1, .. })));
+}
+
+#[tokio::test(start_paused = true)]
+async fn stops_users_paused_in_the_middle_of_the_round() {
+    let connector = InMemoryConnector::default();
+    connector.log.lock().unwrap().pause_on_fetch = true;
+    connector.push_page(1, Ok((vec![10], Some("100".to_string()))));
+    connector.push_page(1, Ok((vec![11], None)));
+    let (synchronizer, _) = synchronizer(&connector);
+
+    synchronizer
+        .sync(vec![(1, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.fetched, vec![(1, None)]);
+    assert_eq!(log.saved, vec![(1, vec![10])]);
+    assert!(log.finished.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn does_not_start_paused_users() {
+    let connector = InMemoryConnector::default();
+    connector.log.lock().unwrap().paused.push(1);
+    connector.push_page(2, Ok((vec![20], None)));
+    let (synchronizer, _) = synchronizer(&connector);
+
+    synchronizer
+        .sync(vec![(1, token()), (2, token())], OffsetDateTime::now_utc())
+        .await;
+
+    let log = connector.log.lock().unwrap();
+    assert_eq!(log.verified.len(), 1);
+    assert_eq!(log.fetched, vec![(2, None)]);
+    assert_eq!(log.finished, vec![2]);
+}
@@ -0,0 +1,351 @@
+mod common;
+
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::RelationKind;
+use fantastic_giggle_worker::{instance_url, LiveRelation, MastodonClient, MASTODON_SCOPES};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use common::{form, header, pairs, query, token, unused_pool};
+
+const REDIRECT_URI: &str = "http://localhost:8080/api/mastodon/callback";
+
+fn client() -> MastodonClient {
+    MastodonClient::new(unused_pool(), REDIRECT_URI.to_string())
+}
+
+fn account(id: &str) -> serde_json::Value {
+    json!({ "id": id, "username": id, "acct": id })
+}
+
+#[test]
+fn normalizes_instance_urls() {
+    assert_eq!(
+        instance_url("mastodon.social").as_deref(),
+        Some("https://mastodon.social")
+    );
+    assert_eq!(
+        instance_url(" https://mastodon.social/@alice ").as_deref(),
+        Some("https://mastodon.social")
+    );
+    assert_eq!(
+        instance_url("https://1.1.1.1/").as_deref(),
+        Some("https://1.1.1.1")
+    );
+    assert_eq!(instance_url("https://"), None);
+}
+
+#[test]
+fn rejects_plain_http_and_private_instances() {
+    assert_eq!(instance_url("http://mastodon.social"), None);
+    assert_eq!(instance_url("ftp://mastodon.social"), None);
+    assert_eq!(instance_url("http://127.0.0.1:3000/"), None);
+    assert_eq!(instance_url("127.0.0.1:3000"), None);
+    assert_eq!(instance_url("https://10.0.0.1"), None);
+    assert_eq!(instance_url("https://192.168.1.1"), None);
+    assert_eq!(instance_url("https://169.254.169.254"), None);
+    assert_eq!(instance_url("https://100.64.0.1"), None);
+    assert_eq!(instance_url("https://[::1]"), None);
+    assert_eq!(instance_url("https://[fd00::1]"), None);
+    assert_eq!(instance_url("https://[fe80::1]"), None);
+    assert_eq!(instance_url("https://[::ffff:127.0.0.1]"), None);
+}
+
+#[tokio::test]
+async fn rejects_host_names_of_private_addresses() {
+    let client = client();
+    let result = client.register_app("https://localhost").await;
+    assert!(matches!(result, Err(Error::InvalidResponse(_, _))));
+}
+
+#[tokio::test]
+async fn limits_app_registrations() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/apps"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "client_id": "client_id",
+            "client_secret": "client_secret"
+        })))
+        .mount(&server)
+        .await;
+    let client = client();
+
+    for _ in 0..20 {
+        client.register_app(&server.uri()).await.unwrap();
+    }
+    let result = client.register_app(&server.uri()).await;
+    assert!(matches!(result, Err(Error::RateLimit(_))));
+    assert_eq!(server.received_requests().await.unwrap().len(), 20);
+}
+
+#[tokio::test]
+async fn registers_app_and_logs_in() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/apps"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "1",
+            "name": "fantastic-giggle",
+            "client_id": "client_id",
+            "client_secret": "client_secret"
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "access_token",
+            "token_type": "Bearer",
+            "scope": MASTODON_SCOPES,
+            "created_at": 1573979017
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/verify_credentials"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(account("109")))
+        .mount(&server)
+        .await;
+    let client = client();
+
+    let app = client.register_app(&server.uri()).await.unwrap();
+    assert_eq!(app.instance, server.uri());
+    assert_eq!(app.client_id, "client_id");
+    assert_eq!(app.client_secret, "client_secret");
+
+    let authorize_url = client.authorize_url(&app, "STATE").unwrap();
+    let authorize_url = url::Url::parse(&authorize_url).unwrap();
+    assert_eq!(authorize_url.path(), "/oauth/authorize");
+    assert_eq!(
+        pairs(authorize_url.query().unwrap().as_bytes()),
+        vec![
+            ("response_type".to_string(), "code".to_string()),
+            ("client_id".to_string(), "client_id".to_string()),
+            ("redirect_uri".to_string(), REDIRECT_URI.to_string()),
+            ("scope".to_string(), MASTODON_SCOPES.to_string()),
+            ("state".to_string(), "STATE".to_string()),
+        ]
+    );
+
+    let access_token = client.exchange_code(&app, "CODE").await.unwrap();
+    assert_eq!(access_token, "access_token");
+    let account_id = client
+        .verify_credentials(&app.instance, &Token::Bearer(access_token))
+        .await
+        .unwrap();
+    assert_eq!(account_id, "109");
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(
+        form(&requests[0]),
+        vec![
+            ("client_name".to_string(), "fantastic-giggle".to_string()),
+            ("redirect_uris".to_string(), REDIRECT_URI.to_string()),
+            ("scopes".to_string(), MASTODON_SCOPES.to_string()),
+        ]
+    );
+    assert_eq!(
+        form(&requests[1]),
+        vec![
+            ("grant_type".to_string(), "authorization_code".to_string()),
+            ("code".to_string(), "CODE".to_string()),
+            ("client_id".to_string(), "client_id".to_string()),
+            ("client_secret".to_string(), "client_secret".to_string()),
+            ("redirect_uri".to_string(), REDIRECT_URI.to_string()),
+            ("scope".to_string(), MASTODON_SCOPES.to_string()),
+        ]
+    );
+    assert_eq!(
+        header(&requests[2], "authorization").as_deref(),
+        Some("Bearer access_token")
+    );
+}
+
+#[tokio::test]
+async fn follows_pagination_links() {
+    let server = MockServer::start().await;
+    let next = format!(
+        "<{}/api/v1/accounts/1/followers?limit=80&max_id=200>; rel=\"next\", <{}/api/v1/accounts/1/followers?limit=80&since_id=300>; rel=\"prev\"",
+        server.uri(),
+        server.uri()
+    );
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/1/followers"))
+        .and(query_param("max_id", "200"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([account("12")])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/1/followers"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("link", next.as_str())
+                .set_body_json(json!([account("10"), account("11")])),
+        )
+        .mount(&server)
+        .await;
+    let client = client();
+
+    let first = client
+        .fetch_accounts(RelationKind::Follower, &server.uri(), "1", &token(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        first,
+        (
+            vec!["10".to_string(), "11".to_string()],
+            Some("200".to_string())
+        )
+    );
+    let last = client
+        .fetch_accounts(
+            RelationKind::Follower,
+            &server.uri(),
+            "1",
+            &token(),
+            Some("200"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(last, (vec!["12".to_string()], None));
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        query(&requests[0]),
+        vec![("limit".to_string(), "80".to_string())]
+    );
+    assert_eq!(
+        query(&requests[1]),
+        vec![
+            ("limit".to_string(), "80".to_string()),
+            ("max_id".to_string(), "200".to_string()),
+        ]
+    );
+    for request in &requests {
+        assert_eq!(
+            header(request, "authorization").as_deref(),
+            Some("Bearer access_token")
+        );
+    }
+}
+
+#[tokio::test]
+async fn fetches_following() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/1/following"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([account("20")])))
+        .mount(&server)
+        .await;
+    let client = client();
+
+    let page = client
+        .fetch_accounts(RelationKind::Friend, &server.uri(), "1", &token(), None)
+        .await
+        .unwrap();
+    assert_eq!(page, (vec!["20".to_string()], None));
+
+    let result = client
+        .fetch_accounts(
+            RelationKind::OutgoingRequest,
+            &server.uri(),
+            "1",
+            &token(),
+            None,
+        )
+        .await;
+    assert!(result.is_err());
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn maps_too_many_requests_to_rate_limit() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/1/followers"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("x-ratelimit-reset", "2023-11-14T22:13:20.000Z"),
+        )
+        .mount(&server)
+        .await;
+    let client = client();
+
+    let result = client
+        .fetch_accounts(RelationKind::Follower, &server.uri(), "1", &token(), None)
+        .await;
+    assert!(matches!(result, Err(Error::RateLimit(1700000000))));
+}
+
+#[tokio::test]
+async fn looks_up_relations() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/relationships"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "id": "10", "following": false, "followed_by": true, "requested": false },
+            { "id": "11", "following": true, "followed_by": true, "requested": false },
+            { "id": "12", "following": false, "followed_by": true, "requested": true },
+            { "id": "13", "following": false, "followed_by": false, "requested": false }
+        ])))
+        .mount(&server)
+        .await;
+    let client = client();
+    let ids = ["10", "11", "12", "13"].map(String::from);
+
+    let relations = client
+        .fetch_relations(&server.uri(), &token(), &ids)
+        .await
+        .unwrap();
+    let relation = |followed_by, following| LiveRelation {
+        followed_by,
+        following,
+    };
+    assert_eq!(
+        relations.into_iter().collect::<Vec<_>>(),
+        vec![
+            ("10".to_string(), relation(true, false)),
+            ("11".to_string(), relation(true, true)),
+            ("12".to_string(), relation(true, true)),
+            ("13".to_string(), relation(false, false)),
+        ]
+    );
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        query(&requests[0]),
+        ids.iter()
+            .map(|id| ("id[]".to_string(), id.clone()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn follows_account() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/10/follow"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "10", "following": true, "followed_by": true, "requested": false
+        })))
+        .mount(&server)
+        .await;
+    let client = client();
+
+    client
+        .follow_account(&server.uri(), &token(), "10")
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        header(&requests[0], "authorization").as_deref(),
+        Some("Bearer access_token")
+    );
+}
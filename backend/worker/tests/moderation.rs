@@ -0,0 +1,252 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use egg_mode::{error::Error, Token};
+use fantastic_giggle_sql::{ModerationAction, ModerationKind, PgPool};
+use fantastic_giggle_test::connect_to_test_sql;
+use fantastic_giggle_worker::{finish_soft_block, moderate, undo_moderation, ModerationClient};
+
+#[derive(Clone, Default)]
+struct FakeClient {
+    calls: Arc<Mutex<Vec<(&'static str, u64)>>>,
+    unblock_error: Option<fn() -> Error>,
+}
+
+impl FakeClient {
+    fn failing_unblock(error: fn() -> Error) -> Self {
+        Self {
+            unblock_error: Some(error),
+            ..Self::default()
+        }
+    }
+
+    fn calls(&self) -> Vec<(&'static str, u64)> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn call(&self, name: &'static str, target_id: u64) {
+        self.calls.lock().unwrap().push((name, target_id));
+    }
+}
+
+#[async_trait]
+impl ModerationClient for FakeClient {
+    async fn mute(&self, _: i64, _: &Token, target_id: u64) -> Result<(), Error> {
+        self.call("mute", target_id);
+        Ok(())
+    }
+    async fn unmute(&self, _: i64, _: &Token, target_id: u64) -> Result<(), Error> {
+        self.call("unmute", target_id);
+        Ok(())
+    }
+    async fn block(&self, _: i64, _: &Token, target_id: u64) -> Result<(), Error> {
+        self.call("block", target_id);
+        Ok(())
+    }
+    async fn unblock(&self, _: i64, _: &Token, target_id: u64) -> Result<(), Error> {
+        self.call("unblock", target_id);
+        match self.unblock_error {
+            Some(error) => Err(error()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn token() -> Token {
+    Token::Bearer("access_token".to_string())
+}
+
+fn rate_limit() -> Error {
+    Error::RateLimit(0)
+}
+
+fn server_error() -> Error {
+    Error::InvalidResponse("server error", None)
+}
+
+async fn clear(pool: &PgPool, source_id: i64) {
+    sqlx::query(r#"DELETE FROM "moderation_action" WHERE source_id=$1"#)
+        .bind(source_id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Blocks the follower and saves the soft block the way the worker does.
+async fn soft_block(client: &FakeClient, pool: &PgPool, source_id: i64, target_id: i64) -> i64 {
+    moderate(
+        client,
+        pool,
+        source_id,
+        &token(),
+        target_id,
+        ModerationKind::SoftBlock,
+    )
+    .await
+    .unwrap();
+    ModerationAction::save(
+        pool,
+        source_id,
+        target_id,
+        ModerationKind::SoftBlock,
+        "spam",
+        false,
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+async fn pending_unblocks(pool: &PgPool, source_id: i64) -> Vec<i64> {
+    ModerationAction::find_pending_unblocks(pool, source_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|action| action.target_id)
+        .collect()
+}
+
+#[tokio::test]
+async fn soft_block_is_pending_until_unblocked() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let source_id = 2_000_001;
+    clear(&pool, source_id).await;
+
+    let client = FakeClient::default();
+    let id = soft_block(&client, &pool, source_id, 10).await;
+    assert_eq!(client.calls(), vec![("block", 10)]);
+    assert_eq!(pending_unblocks(&pool, source_id).await, vec![10]);
+
+    finish_soft_block(&client, &pool, source_id, &token(), id, 10)
+        .await
+        .unwrap();
+    assert_eq!(client.calls(), vec![("block", 10), ("unblock", 10)]);
+    assert!(pending_unblocks(&pool, source_id).await.is_empty());
+
+    // The follower is removed, and there is nothing left to undo.
+    let action = ModerationAction::find_by_id(&pool, source_id, id)
+        .await
+        .unwrap()
+        .unwrap();
+    undo_moderation(&client, &pool, &token(), &action)
+        .await
+        .unwrap();
+    assert_eq!(client.calls().len(), 2);
+
+    clear(&pool, source_id).await;
+}
+
+#[tokio::test]
+async fn failed_unblock_is_kept_for_a_retry() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let source_id = 2_000_002;
+    clear(&pool, source_id).await;
+
+    for (target_id, error) in [(10, rate_limit as fn() -> Error), (11, server_error)] {
+        let client = FakeClient::failing_unblock(error);
+        let id = soft_block(&client, &pool, source_id, target_id).await;
+        let result = finish_soft_block(&client, &pool, source_id, &token(), id, target_id).await;
+        assert!(result.is_err());
+    }
+    assert_eq!(pending_unblocks(&pool, source_id).await, vec![10, 11]);
+
+    let client = FakeClient::default();
+    for action in ModerationAction::find_pending_unblocks(&pool, source_id)
+        .await
+        .unwrap()
+    {
+        finish_soft_block(
+            &client,
+            &pool,
+            source_id,
+            &token(),
+            action.id,
+            action.target_id,
+        )
+        .await
+        .unwrap();
+    }
+    assert_eq!(client.calls(), vec![("unblock", 10), ("unblock", 11)]);
+    assert!(pending_unblocks(&pool, source_id).await.is_empty());
+
+    clear(&pool, source_id).await;
+}
+
+#[tokio::test]
+async fn undo_unblocks_a_pending_soft_block() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let source_id = 2_000_003;
+    clear(&pool, source_id).await;
+
+    let client = FakeClient::failing_unblock(server_error);
+    let id = soft_block(&client, &pool, source_id, 10).await;
+    assert!(
+        finish_soft_block(&client, &pool, source_id, &token(), id, 10)
+            .await
+            .is_err()
+    );
+
+    let action = ModerationAction::find_by_id(&pool, source_id, id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(action.pending_unblock);
+    let client = FakeClient::default();
+    undo_moderation(&client, &pool, &token(), &action)
+        .await
+        .unwrap();
+    assert_eq!(client.calls(), vec![("unblock", 10)]);
+    assert!(pending_unblocks(&pool, source_id).await.is_empty());
+
+    clear(&pool, source_id).await;
+}
+
+#[tokio::test]
+async fn undo_reverts_mutes_and_blocks() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let source_id = 2_000_004;
+    clear(&pool, source_id).await;
+
+    let client = FakeClient::default();
+    for (target_id, kind) in [(10, ModerationKind::Mute), (11, ModerationKind::Block)] {
+        moderate(&client, &pool, source_id, &token(), target_id, kind)
+            .await
+            .unwrap();
+        let id = ModerationAction::save(&pool, source_id, target_id, kind, "spam", false, None)
+            .await
+            .unwrap();
+        let action = ModerationAction::find_by_id(&pool, source_id, id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!action.pending_unblock);
+        undo_moderation(&client, &pool, &token(), &action)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        client.calls(),
+        vec![("mute", 10), ("unmute", 10), ("block", 11), ("unblock", 11)]
+    );
+    assert!(pending_unblocks(&pool, source_id).await.is_empty());
+
+    clear(&pool, source_id).await;
+}
+
+#[tokio::test]
+async fn only_soft_blocks_which_went_out_are_pending() {
+    let pool = connect_to_test_sql().await.unwrap();
+    let source_id = 2_000_005;
+    clear(&pool, source_id).await;
+
+    let kind = ModerationKind::SoftBlock;
+    ModerationAction::save(&pool, source_id, 10, kind, "spam", true, None)
+        .await
+        .unwrap();
+    ModerationAction::save(&pool, source_id, 11, kind, "spam", false, Some("failed"))
+        .await
+        .unwrap();
+    assert!(pending_unblocks(&pool, source_id).await.is_empty());
+
+    clear(&pool, source_id).await;
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use fantastic_giggle_sql::UserSettings;
+use fantastic_giggle_worker::{IntervalDistribution, Pacer, PacingPolicy, QuietHours};
+use rand::{rngs::StdRng, SeedableRng};
+
+fn quiet_hours(start: i16, end: i16, timezone: &str) -> QuietHours {
+    let mut settings = UserSettings::default_for(1);
+    settings.quiet_hours_start = Some(start);
+    settings.quiet_hours_end = Some(end);
+    settings.timezone = timezone.to_string();
+    QuietHours::from_settings(&settings).unwrap()
+}
+
+fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.ymd(2022, 7, 1).and_hms(hour, minute, 0)
+}
+
+#[test]
+fn quiet_hours_wrap_past_midnight() {
+    let quiet_hours = quiet_hours(22, 6, "UTC");
+    assert!(!quiet_hours.contains(at(21, 59)));
+    assert!(quiet_hours.contains(at(22, 0)));
+    assert!(quiet_hours.contains(at(23, 59)));
+    assert!(quiet_hours.contains(at(0, 0)));
+    assert!(quiet_hours.contains(at(5, 59)));
+    assert!(!quiet_hours.contains(at(6, 0)));
+    assert!(!quiet_hours.contains(at(12, 0)));
+}
+
+#[test]
+fn quiet_hours_within_a_day() {
+    let quiet_hours = quiet_hours(1, 5, "UTC");
+    assert!(!quiet_hours.contains(at(0, 59)));
+    assert!(quiet_hours.contains(at(1, 0)));
+    assert!(quiet_hours.contains(at(4, 59)));
+    assert!(!quiet_hours.contains(at(5, 0)));
+    assert!(!quiet_hours.contains(at(23, 0)));
+}
+
+#[test]
+fn quiet_hours_use_the_timezone() {
+    // 22:00-06:00 in Tokyo is 13:00-21:00 in UTC.
+    let quiet_hours = quiet_hours(22, 6, "Asia/Tokyo");
+    assert!(!quiet_hours.contains(at(12, 59)));
+    assert!(quiet_hours.contains(at(13, 0)));
+    assert!(quiet_hours.contains(at(20, 59)));
+    assert!(!quiet_hours.contains(at(21, 0)));
+    assert!(!quiet_hours.contains(at(23, 0)));
+}
+
+#[test]
+fn next_active_crosses_midnight() {
+    let quiet_hours = quiet_hours(22, 6, "UTC");
+    assert_eq!(
+        quiet_hours.next_active(at(23, 30)),
+        Utc.ymd(2022, 7, 2).and_hms(6, 0, 0)
+    );
+    assert_eq!(quiet_hours.next_active(at(3, 0)), at(6, 0));
+    assert_eq!(quiet_hours.next_active(at(12, 0)), at(12, 0));
+}
+
+#[test]
+fn equal_bounds_are_not_quiet_hours() {
+    let mut settings = UserSettings::default_for(1);
+    settings.quiet_hours_start = Some(3);
+    settings.quiet_hours_end = Some(3);
+    assert!(QuietHours::from_settings(&settings).is_none());
+}
+
+#[test]
+fn next_delay_skips_quiet_hours_past_midnight() {
+    let policy = PacingPolicy::default();
+    let pacer = Pacer::new(policy.clone(), Some(quiet_hours(22, 6, "UTC")));
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+        let delay = pacer.next_delay(at(22, 30), &mut rng);
+        assert!(delay >= Duration::from_secs(7 * 60 * 60 + 30 * 60));
+        assert!(delay <= Duration::from_secs(7 * 60 * 60 + 31 * 60) + policy.break_max);
+    }
+}
+
+#[test]
+fn next_delay_stays_within_the_policy() {
+    let policy = PacingPolicy {
+        interval: IntervalDistribution::LogNormal {
+            median: Duration::from_secs(90),
+            sigma: 1000.0,
+        },
+        break_probability: 0.0,
+        ..PacingPolicy::default()
+    };
+    let pacer = Pacer::new(policy.clone(), None);
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+        let delay = pacer.next_delay(at(12, 0), &mut rng);
+        assert!(policy.min_interval <= delay && delay <= policy.max_interval);
+    }
+}
+
+#[test]
+fn equal_or_inverted_bounds_do_not_panic() {
+    let policy = PacingPolicy {
+        interval: IntervalDistribution::Uniform {
+            min: Duration::from_secs(60),
+            max: Duration::from_secs(60),
+        },
+        min_interval: Duration::from_secs(120),
+        max_interval: Duration::from_secs(30),
+        break_probability: 1.0,
+        break_min: Duration::from_secs(10),
+        break_max: Duration::from_secs(10),
+        round_pause_min: Duration::from_secs(5),
+        round_pause_max: Duration::from_secs(5),
+    };
+    let pacer = Pacer::new(policy.clone(), None);
+    let mut rng = StdRng::seed_from_u64(0);
+    assert_eq!(policy.round_pause(&mut rng), Duration::from_secs(5));
+    assert!(pacer.initial_delay(at(12, 0), &mut rng) <= policy.min_interval);
+    assert_eq!(
+        pacer.next_delay(at(12, 0), &mut rng),
+        Duration::from_secs(130)
+    );
+}
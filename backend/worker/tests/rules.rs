@@ -0,0 +1,356 @@
+use std::time::Duration;
+
+use fantastic_giggle_sql::{OffsetDateTime, Profile, UserSettings};
+use fantastic_giggle_worker::{
+    Attribute, Comparison, Condition, Decision, Fact, Rule, RuleSet, Target,
+};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn now() -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap()
+}
+
+fn profile() -> Profile {
+    Profile {
+        id: 1,
+        screen_name: "alice".to_string(),
+        name: "Alice".to_string(),
+        description: "Rust and Coffee".to_string(),
+        followers_count: 10,
+        friends_count: 50,
+        default_profile_image: false,
+        protected: false,
+        account_created_at: now() - DAY * 30,
+        updated_at: now(),
+    }
+}
+
+fn target(facts: &[Fact]) -> Target {
+    Target {
+        facts: facts.iter().copied().collect(),
+        followed_at: None,
+        profile: Some(profile()),
+    }
+}
+
+fn compare(attribute: Attribute, op: Comparison, value: f64) -> Condition {
+    Condition::Compare {
+        attribute,
+        op,
+        value,
+    }
+}
+
+fn rule(name: &str, when: Condition, decision: Decision) -> Rule {
+    Rule {
+        name: name.to_string(),
+        when,
+        decision,
+    }
+}
+
+fn rules() -> RuleSet {
+    RuleSet {
+        rules: vec![
+            rule("following", Condition::Fact(Fact::Friend), Decision::Skip),
+            rule(
+                "whitelisted",
+                Condition::Fact(Fact::Whitelisted),
+                Decision::Follow,
+            ),
+            rule(
+                "popular",
+                compare(Attribute::FollowersCount, Comparison::GreaterOrEqual, 10.0),
+                Decision::Follow,
+            ),
+        ],
+        default: Decision::Skip,
+    }
+}
+
+#[test]
+fn matches_facts() {
+    let target = target(&[Fact::Follower]);
+    assert!(Condition::Fact(Fact::Follower).matches(&target, now()));
+    assert!(!Condition::Fact(Fact::Friend).matches(&target, now()));
+    assert!(!Condition::Fact(Fact::DefaultProfileImage).matches(&target, now()));
+
+    let mut protected = target.clone();
+    protected.profile.as_mut().unwrap().protected = true;
+    assert!(Condition::Fact(Fact::Protected).matches(&protected, now()));
+}
+
+#[test]
+fn matches_combinations() {
+    let target = target(&[Fact::Follower]);
+    let follower = || Condition::Fact(Fact::Follower);
+    let friend = || Condition::Fact(Fact::Friend);
+    assert!(Condition::All(vec![follower()]).matches(&target, now()));
+    assert!(!Condition::All(vec![follower(), friend()]).matches(&target, now()));
+    assert!(Condition::Any(vec![friend(), follower()]).matches(&target, now()));
+    assert!(!Condition::Any(vec![friend()]).matches(&target, now()));
+    assert!(Condition::Not(Box::new(friend())).matches(&target, now()));
+    // Empty combinations are vacuously true and false.
+    assert!(Condition::All(vec![]).matches(&target, now()));
+    assert!(!Condition::Any(vec![]).matches(&target, now()));
+}
+
+#[test]
+fn matches_comparisons() {
+    let target = target(&[]);
+    let matches = |attribute, op, value| compare(attribute, op, value).matches(&target, now());
+    assert!(matches(Attribute::FollowersCount, Comparison::Equal, 10.0));
+    assert!(matches(
+        Attribute::FollowersCount,
+        Comparison::LessOrEqual,
+        10.0
+    ));
+    assert!(!matches(Attribute::FollowersCount, Comparison::Less, 10.0));
+    assert!(matches(Attribute::FriendsCount, Comparison::Greater, 49.0));
+    assert!(matches(
+        Attribute::FriendsFollowersRatio,
+        Comparison::Equal,
+        5.0
+    ));
+    assert!(matches(Attribute::AccountAgeDays, Comparison::Equal, 30.0));
+
+    // The ratio of an account without followers is its number of friends.
+    let mut no_followers = target.clone();
+    no_followers.profile.as_mut().unwrap().followers_count = 0;
+    assert!(
+        compare(Attribute::FriendsFollowersRatio, Comparison::Equal, 50.0)
+            .matches(&no_followers, now())
+    );
+}
+
+#[test]
+fn unknown_attributes_compare_false() {
+    let unknown = Target::default();
+    for attribute in [
+        Attribute::FollowersCount,
+        Attribute::FriendsCount,
+        Attribute::FriendsFollowersRatio,
+        Attribute::AccountAgeDays,
+        Attribute::DaysSinceFollow,
+    ] {
+        for op in [Comparison::Less, Comparison::GreaterOrEqual] {
+            assert!(!compare(attribute, op, 0.0).matches(&unknown, now()));
+        }
+    }
+    assert!(!Condition::BioContains("rust".to_string()).matches(&unknown, now()));
+}
+
+#[test]
+fn matches_days_since_follow() {
+    let mut target = target(&[Fact::Follower]);
+    target.followed_at = Some(now() - DAY * 3 - Duration::from_secs(60));
+    let since_follow = |op, value| compare(Attribute::DaysSinceFollow, op, value);
+    assert!(since_follow(Comparison::Equal, 3.0).matches(&target, now()));
+    assert!(since_follow(Comparison::Greater, 2.0).matches(&target, now()));
+}
+
+#[test]
+fn matches_bio_case_insensitively() {
+    let target = target(&[]);
+    assert!(Condition::BioContains("coffee".to_string()).matches(&target, now()));
+    assert!(Condition::BioContains("RUST AND".to_string()).matches(&target, now()));
+    assert!(!Condition::BioContains("tea".to_string()).matches(&target, now()));
+}
+
+#[test]
+fn evaluate_uses_the_first_matching_rule() {
+    let rules = rules();
+    let (decision, rule) = rules.evaluate(&target(&[Fact::Friend, Fact::Whitelisted]), now());
+    assert_eq!(decision, Decision::Skip);
+    assert_eq!(rule.unwrap().name, "following");
+
+    let (decision, rule) = rules.evaluate(&target(&[Fact::Whitelisted]), now());
+    assert_eq!(decision, Decision::Follow);
+    assert_eq!(rule.unwrap().name, "whitelisted");
+
+    let (decision, rule) = rules.evaluate(&Target::default(), now());
+    assert_eq!(decision, Decision::Skip);
+    assert!(rule.is_none());
+
+    let rules = RuleSet {
+        rules: vec![],
+        default: Decision::Follow,
+    };
+    assert_eq!(
+        rules.evaluate(&Target::default(), now()).0,
+        Decision::Follow
+    );
+}
+
+#[test]
+fn blocklisted_blocked_and_muted_targets_are_skipped_whatever_the_rules() {
+    let rules = RuleSet {
+        rules: vec![rule("everyone", Condition::All(vec![]), Decision::Follow)],
+        default: Decision::Follow,
+    };
+    for fact in [Fact::Blocklisted, Fact::Blocking, Fact::Muting] {
+        let target = target(&[Fact::Follower, Fact::Whitelisted, fact]);
+        assert_eq!(rules.evaluate(&target, now()), (Decision::Skip, None));
+
+        let explanation = rules.explain(&target, now());
+        assert_eq!(explanation.decision, Decision::Skip);
+        assert_eq!(explanation.excluded_by, Some(fact));
+        assert!(explanation.matched_rule.is_none());
+        assert!(explanation.rules.is_empty());
+    }
+}
+
+#[test]
+fn explain_stops_at_the_matching_rule() {
+    let rules = rules();
+    let explanation = rules.explain(&target(&[Fact::Whitelisted]), now());
+    assert_eq!(explanation.decision, Decision::Follow);
+    assert!(explanation.excluded_by.is_none());
+    assert_eq!(explanation.matched_rule.as_deref(), Some("whitelisted"));
+    let traces = explanation
+        .rules
+        .iter()
+        .map(|trace| (trace.name.as_str(), trace.matched))
+        .collect::<Vec<_>>();
+    assert_eq!(traces, vec![("following", false), ("whitelisted", true)]);
+
+    let explanation = rules.explain(&Target::default(), now());
+    assert_eq!(explanation.decision, Decision::Skip);
+    assert!(explanation.matched_rule.is_none());
+    assert_eq!(explanation.rules.len(), 3);
+    assert!(explanation.rules.iter().all(|trace| !trace.matched));
+}
+
+#[test]
+fn validate_accepts_reasonable_rules() {
+    assert!(rules().validate().is_ok());
+    let settings = UserSettings::default_for(1);
+    assert!(RuleSet::from_settings(&settings).validate().is_ok());
+}
+
+#[test]
+fn validate_rejects_invalid_rules() {
+    let single = |rule| RuleSet {
+        rules: vec![rule],
+        default: Decision::Skip,
+    };
+    let follower = || Condition::Fact(Fact::Follower);
+
+    assert!(single(rule(" ", follower(), Decision::Follow))
+        .validate()
+        .is_err());
+    assert!(single(rule(&"a".repeat(101), follower(), Decision::Follow))
+        .validate()
+        .is_err());
+    let infinite = compare(Attribute::FollowersCount, Comparison::Less, f64::INFINITY);
+    assert!(single(rule("infinite", infinite, Decision::Skip))
+        .validate()
+        .is_err());
+    let empty = Condition::Not(Box::new(Condition::BioContains(" ".to_string())));
+    assert!(single(rule("empty", empty, Decision::Skip))
+        .validate()
+        .is_err());
+
+    let mut deep = follower();
+    for _ in 0..8 {
+        deep = Condition::Not(Box::new(deep));
+    }
+    assert!(single(rule("deep", deep, Decision::Skip))
+        .validate()
+        .is_err());
+
+    let many = RuleSet {
+        rules: (0..51)
+            .map(|i| rule(&i.to_string(), follower(), Decision::Follow))
+            .collect(),
+        default: Decision::Skip,
+    };
+    assert!(many.validate().is_err());
+}
+
+#[test]
+fn parses_json_rules() {
+    let rules: RuleSet = serde_json::from_str(
+        r#"{"rules": [{"name": "bots", "when": {"all": [{"fact": "follower"}, {"not": {"bio_contains": "crypto"}}, {"compare": {"attribute": "followers_count", "op": ">=", "value": 5}}]}, "decision": "follow"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(rules.default, Decision::Skip);
+    assert!(rules.validate().is_ok());
+    assert_eq!(
+        rules.evaluate(&target(&[Fact::Follower]), now()).0,
+        Decision::Follow
+    );
+}
+
+#[test]
+fn default_rules_follow_back_followers_who_pass_the_settings() {
+    let mut settings = UserSettings::default_for(1);
+    let rules = RuleSet::from_settings(&settings);
+    assert_eq!(
+        rules.evaluate(&target(&[Fact::Follower]), now()).0,
+        Decision::Follow
+    );
+    assert_eq!(rules.evaluate(&target(&[]), now()).0, Decision::Skip);
+    for following in [Fact::Friend, Fact::OutgoingRequest] {
+        let (decision, rule) = rules.evaluate(&target(&[Fact::Follower, following]), now());
+        assert_eq!(decision, Decision::Skip);
+        assert_eq!(rule.unwrap().name, "already_following");
+    }
+
+    let skipped_by = |settings: &UserSettings| {
+        RuleSet::from_settings(settings)
+            .evaluate(&target(&[Fact::Follower]), now())
+            .1
+            .map(|rule| rule.name.clone())
+    };
+    settings.min_followers_count = 11;
+    assert_eq!(skipped_by(&settings).as_deref(), Some("too_few_followers"));
+    settings.min_followers_count = 10;
+    settings.max_friends_followers_ratio = Some(4.0);
+    assert_eq!(
+        skipped_by(&settings).as_deref(),
+        Some("friends_followers_ratio")
+    );
+    settings.max_friends_followers_ratio = Some(5.0);
+    settings.min_account_age_days = 31;
+    assert_eq!(skipped_by(&settings).as_deref(), Some("account_too_new"));
+    settings.min_account_age_days = 30;
+    settings.blocked_bio_keywords = vec!["".to_string(), "COFFEE".to_string()];
+    assert_eq!(skipped_by(&settings).as_deref(), Some("bio_keyword"));
+    settings.blocked_bio_keywords = vec!["tea".to_string()];
+    assert_eq!(skipped_by(&settings).as_deref(), Some("follower"));
+}
+
+#[test]
+fn spam_rules_skip_the_followers_who_fail_the_spam_settings() {
+    let mut settings = UserSettings::default_for(1);
+    let spam_rule = |settings: &UserSettings| match RuleSet::spam_from_settings(settings)
+        .evaluate(&target(&[]), now())
+    {
+        (Decision::Skip, rule) => rule.map(|rule| rule.name.clone()),
+        (Decision::Follow, _) => None,
+    };
+    assert_eq!(spam_rule(&settings), None);
+
+    settings.spam_min_followers_count = 11;
+    assert_eq!(spam_rule(&settings).as_deref(), Some("too_few_followers"));
+    settings.spam_min_followers_count = 10;
+    settings.spam_max_friends_followers_ratio = Some(4.0);
+    assert_eq!(
+        spam_rule(&settings).as_deref(),
+        Some("friends_followers_ratio")
+    );
+    settings.spam_max_friends_followers_ratio = Some(5.0);
+    settings.spam_min_account_age_days = 31;
+    assert_eq!(spam_rule(&settings).as_deref(), Some("account_too_new"));
+    settings.spam_min_account_age_days = 30;
+    settings.spam_bio_keywords = vec!["".to_string(), "COFFEE".to_string()];
+    assert_eq!(spam_rule(&settings).as_deref(), Some("bio_keyword"));
+    settings.spam_bio_keywords = vec!["tea".to_string()];
+    assert_eq!(spam_rule(&settings), None);
+
+    // The follow-back thresholds don't make anyone spam.
+    settings.min_followers_count = 100;
+    assert_eq!(spam_rule(&settings), None);
+}
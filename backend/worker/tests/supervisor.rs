@@ -0,0 +1,102 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use fantastic_giggle_worker::{RestartPolicy, Supervisor, WorkerState};
+use tokio::time::{sleep, Instant};
+
+fn policy(max_restarts: u32) -> RestartPolicy {
+    RestartPolicy {
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(4),
+        healthy_after: Duration::from_secs(60),
+        max_restarts,
+    }
+}
+
+/// Spawns a worker which records when it starts, and runs `run` with the number of the start.
+fn spawn_failing<F>(supervisor: &Supervisor, run: F) -> Arc<Mutex<Vec<Instant>>>
+where
+    F: Fn(usize) -> Duration + Send + Sync + 'static,
+{
+    let starts = Arc::new(Mutex::new(vec![]));
+    let recorded = starts.clone();
+    let run = Arc::new(run);
+    supervisor.spawn("worker", move || {
+        let starts = recorded.clone();
+        let run = run.clone();
+        async move {
+            let start = {
+                let mut starts = starts.lock().unwrap();
+                starts.push(Instant::now());
+                starts.len()
+            };
+            sleep(run(start)).await;
+            Err(anyhow!("failure {}", start))
+        }
+    });
+    starts
+}
+
+fn gaps(starts: &Mutex<Vec<Instant>>) -> Vec<u64> {
+    starts
+        .lock()
+        .unwrap()
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).as_secs())
+        .collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn doubles_the_backoff_up_to_the_cap_and_gives_up() {
+    let supervisor = Supervisor::new(policy(5));
+    let handle = supervisor.handle();
+    let starts = spawn_failing(&supervisor, |_| Duration::ZERO);
+
+    let error = supervisor.wait().await.to_string();
+    assert!(error.contains("exceeded 5 restarts"), "{}", error);
+    assert!(error.contains("failure 6"), "{}", error);
+    assert_eq!(gaps(&starts), vec![1, 2, 4, 4, 4]);
+
+    let status = &handle.statuses()[0];
+    assert_eq!(status.state, WorkerState::GaveUp);
+    assert_eq!(status.restarts, 5);
+    assert!(status.last_error.as_ref().unwrap().starts_with("failure 6"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn resets_the_backoff_after_a_healthy_run() {
+    let supervisor = Supervisor::new(policy(3));
+    let starts = spawn_failing(&supervisor, |start| match start {
+        3 => Duration::from_secs(60),
+        _ => Duration::ZERO,
+    });
+
+    supervisor.wait().await;
+    // The third run was healthy, so the restarts after it count from one again.
+    assert_eq!(gaps(&starts), vec![1, 2, 61, 2, 4]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn restarts_panicking_workers() {
+    let supervisor = Supervisor::new(policy(1));
+    let handle = supervisor.handle();
+    let starts = Arc::new(Mutex::new(0));
+    let recorded = starts.clone();
+    supervisor.spawn("worker", move || {
+        *recorded.lock().unwrap() += 1;
+        async { panic!("boom") }
+    });
+
+    sleep(Duration::from_millis(500)).await;
+    let status = &handle.statuses()[0];
+    assert!(matches!(status.state, WorkerState::Restarting { .. }));
+    assert_eq!(status.restarts, 1);
+    assert_eq!(status.last_error.as_deref(), Some("panicked: boom"));
+
+    let error = supervisor.wait().await.to_string();
+    assert!(error.contains("panicked: boom"), "{}", error);
+    assert_eq!(*starts.lock().unwrap(), 2);
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use egg_mode::error::Error;
+use fantastic_giggle_sql::RelationKind;
+use fantastic_giggle_worker::{FollowClient, IdsClient, OAuth2Config, TwitterV2Client};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use common::{form, header, query, token};
+
+async fn client(server: &MockServer, client_secret: Option<&str>) -> TwitterV2Client {
+    let mut config = OAuth2Config::new(
+        "client_id".to_string(),
+        client_secret.map(String::from),
+        "http://localhost:8080/api/oauth2/callback".to_string(),
+    );
+    config.api_url = server.uri();
+    TwitterV2Client::new(config)
+}
+
+#[tokio::test]
+async fn pages_followers_with_pagination_tokens() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/1/followers"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{ "id": "10", "name": "a", "username": "a" }, { "id": "11" }],
+            "meta": { "result_count": 2, "next_token": "NEXT" }
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/1/followers"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{ "id": "12" }],
+            "meta": { "result_count": 1 }
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server, None).await;
+
+    let first = client
+        .fetch_ids(RelationKind::Follower, 1, &token(), None)
+        .await
+        .unwrap();
+    assert_eq!(first, (vec![10, 11], Some("NEXT".to_string())));
+    let last = client
+        .fetch_ids(RelationKind::Follower, 1, &token(), Some("NEXT"))
+        .await
+        .unwrap();
+    assert_eq!(last, (vec![12], None));
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        query(&requests[0]),
+        vec![("max_results".to_string(), "1000".to_string())]
+    );
+    assert_eq!(
+        query(&requests[1]),
+        vec![
+            ("max_results".to_string(), "1000".to_string()),
+            ("pagination_token".to_string(), "NEXT".to_string()),
+        ]
+    );
+    for request in &requests {
+        assert_eq!(
+            header(request, "authorization"),
+            Some("Bearer access_token".to_string())
+        );
+    }
+}
+
+#[tokio::test]
+async fn fetches_following_and_skips_unsupported_kinds() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/1/following"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "meta": {} })))
+        .mount(&server)
+        .await;
+    let client = client(&server, None).await;
+
+    let page = client
+        .fetch_ids(RelationKind::Friend, 1, &token(), None)
+        .await
+        .unwrap();
+    assert_eq!(page, (vec![], None));
+    assert!(!client.supports(RelationKind::IncomingRequest));
+    assert!(!client.supports(RelationKind::OutgoingRequest));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn maps_too_many_requests_to_rate_limit() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/1/followers"))
+        .respond_with(ResponseTemplate::new(429).insert_header("x-rate-limit-reset", "1700000000"))
+        .mount(&server)
+        .await;
+    let client = client(&server, None).await;
+
+    let result = client
+        .fetch_ids(RelationKind::Follower, 1, &token(), None)
+        .await;
+    assert!(matches!(result, Err(Error::RateLimit(1700000000))));
+}
+
+#[tokio::test]
+async fn follows_with_target_user_id() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/2/users/1/following"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "following": true, "pending_follow": false }
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server, None).await;
+
+    client.follow(1, &token(), 7).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        header(&requests[0], "authorization"),
+        Some("Bearer access_token".to_string())
+    );
+    assert_eq!(
+        header(&requests[0], "content-type"),
+        Some("application/json".to_string())
+    );
+    let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body, json!({ "target_user_id": "7" }));
+}
+
+#[tokio::test]
+async fn exchanges_code_with_pkce_verifier() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/2/oauth2/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "token_type": "bearer",
+            "access_token": "new_access",
+            "refresh_token": "new_refresh",
+            "expires_in": 7200,
+            "scope": "follows.read"
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server, None).await;
+
+    let token = client.exchange_code("CODE", "VERIFIER").await.unwrap();
+    assert_eq!(token.access_token, "new_access");
+    assert_eq!(token.refresh_token, "new_refresh");
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(header(&requests[0], "authorization"), None);
+    assert_eq!(
+        form(&requests[0]),
+        vec![
+            ("grant_type".to_string(), "authorization_code".to_string()),
+            ("code".to_string(), "CODE".to_string()),
+            (
+                "redirect_uri".to_string(),
+                "http://localhost:8080/api/oauth2/callback".to_string()
+            ),
+            ("code_verifier".to_string(), "VERIFIER".to_string()),
+            ("client_id".to_string(), "client_id".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn refreshes_with_client_secret_and_keeps_refresh_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/2/oauth2/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "new_access",
+            "expires_in": 7200
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server, Some("secret")).await;
+
+    let token = client.refresh_token("old_refresh").await.unwrap();
+    assert_eq!(token.access_token, "new_access");
+    assert_eq!(token.refresh_token, "old_refresh");
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        header(&requests[0], "authorization"),
+        Some(format!("Basic {}", base64::encode("client_id:secret")))
+    );
+    assert_eq!(
+        form(&requests[0]),
+        vec![
+            ("grant_type".to_string(), "refresh_token".to_string()),
+            ("refresh_token".to_string(), "old_refresh".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn looks_up_profiles() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{
+                "id": "10",
+                "username": "alice",
+                "name": "Alice",
+                "description": "hello",
+                "created_at": "2020-01-02T03:04:05.000Z",
+                "protected": true,
+                "profile_image_url": "https://abs.twimg.com/sticky/default_profile_images/default_profile_normal.png",
+                "public_metrics": { "followers_count": 3, "following_count": 4, "tweet_count": 5 }
+            }]
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server, None).await;
+
+    let profiles = client.lookup_profiles(&token(), &[10, 11]).await.unwrap();
+    assert_eq!(profiles.len(), 1);
+    let profile = &profiles[0];
+    assert_eq!(profile.id, 10);
+    assert_eq!(profile.screen_name, "alice");
+    assert_eq!(profile.name, "Alice");
+    assert_eq!(profile.description, "hello");
+    assert_eq!(profile.followers_count, 3);
+    assert_eq!(profile.friends_count, 4);
+    assert!(profile.default_profile_image);
+    assert!(profile.protected);
+    assert_eq!(profile.account_created_at.unix_timestamp(), 1577934245);
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        query(&requests[0]),
+        vec![
+            ("ids".to_string(), "10,11".to_string()),
+            (
+                "user.fields".to_string(),
+                "created_at,description,profile_image_url,protected,public_metrics".to_string()
+            ),
+        ]
+    );
+}
@@ -0,0 +1,49 @@
+use fantastic_giggle_sql::{OffsetDateTime, Profile};
+use fantastic_giggle_worker::render_welcome_message;
+
+fn profile(name: &str, screen_name: &str) -> Profile {
+    Profile {
+        id: 1,
+        screen_name: screen_name.to_string(),
+        name: name.to_string(),
+        description: String::new(),
+        followers_count: 0,
+        friends_count: 0,
+        default_profile_image: false,
+        protected: false,
+        account_created_at: OffsetDateTime::UNIX_EPOCH,
+        updated_at: OffsetDateTime::UNIX_EPOCH,
+    }
+}
+
+#[test]
+fn replaces_the_placeholders() {
+    let profile = profile("Alice", "alice");
+    assert_eq!(
+        render_welcome_message("Hi {name} (@{screen_name})! Thanks, {name}.", &profile),
+        "Hi Alice (@alice)! Thanks, Alice."
+    );
+}
+
+#[test]
+fn keeps_templates_without_placeholders() {
+    let profile = profile("Alice", "alice");
+    assert_eq!(
+        render_welcome_message("Thanks for following!", &profile),
+        "Thanks for following!"
+    );
+    assert_eq!(render_welcome_message("", &profile), "");
+    assert_eq!(
+        render_welcome_message("{unknown} {NAME}", &profile),
+        "{unknown} {NAME}"
+    );
+}
+
+#[test]
+fn does_not_expand_placeholders_in_the_profile() {
+    let profile = profile("{screen_name}", "alice");
+    assert_eq!(
+        render_welcome_message("Hi {screen_name}, {name}", &profile),
+        "Hi alice, {screen_name}"
+    );
+}
@@ -0,0 +1,25 @@
+-- The synchronized ID sets, which had a table per kind, share one table keyed by the kind.
+CREATE TABLE "relationship" (
+    source_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    target_id BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, kind, target_id)
+);
+CREATE INDEX "relationship_source_id_kind_created_at" ON "relationship" (source_id, kind, created_at, target_id);
+CREATE UNLOGGED TABLE "relationship_staging" (
+    source_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    target_id BIGINT NOT NULL
+);
+CREATE INDEX "relationship_staging_source_id_kind_target_id" ON "relationship_staging" (source_id, kind, target_id);
+-- When the rows were first seen wasn't recorded, so the last time they were seen stands in.
+INSERT INTO "relationship" (source_id, kind, target_id, created_at, updated_at)
+SELECT source_id, 'follower', target_id, updated_at, updated_at
+FROM "follower";
+INSERT INTO "relationship" (source_id, kind, target_id, created_at, updated_at)
+SELECT source_id, 'friend', target_id, updated_at, updated_at
+FROM "friend";
+DROP TABLE "follower";
+DROP TABLE "friend";
@@ -0,0 +1,58 @@
+-- The users become identities, each linked to an account of its own.
+CREATE TABLE "account" (
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+    -- `user` or `admin`.
+    role TEXT NOT NULL DEFAULT 'user',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- A provider account linked to an account of the app. Relationships, lists and settings are
+-- keyed by the ID of the identity.
+CREATE TABLE "identity" (
+    id BIGINT NOT NULL PRIMARY KEY,
+    account_id BIGINT NOT NULL,
+    provider TEXT NOT NULL DEFAULT 'twitter',
+    access_key TEXT NOT NULL,
+    access_secret TEXT NOT NULL,
+    token_expires_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX "identity_account_id" ON "identity" (account_id);
+CREATE INDEX "identity_provider" ON "identity" (provider);
+WITH users AS (
+    SELECT id, access_key, access_secret, nextval(pg_get_serial_sequence('"account"', 'id')) AS account_id
+    FROM "user"
+),
+accounts AS (
+    INSERT INTO "account" (id)
+    SELECT account_id
+    FROM users
+)
+INSERT INTO "identity" (id, account_id, provider, access_key, access_secret)
+SELECT id, account_id, 'twitter', access_key, access_secret
+FROM users;
+DROP TABLE "user";
+-- Twitter IDs are used as they are. Accounts of the other providers get negative IDs, so that
+-- they never collide with Twitter IDs.
+CREATE SEQUENCE "external_account_id_seq" INCREMENT BY -1 MAXVALUE -1;
+CREATE TABLE "external_account" (
+    id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('external_account_id_seq'),
+    provider TEXT NOT NULL,
+    -- The base URL of the server, for the providers whose IDs are local to a server.
+    instance TEXT NOT NULL DEFAULT '',
+    external_id TEXT NOT NULL,
+    UNIQUE (provider, instance, external_id)
+);
+CREATE TABLE "mastodon_app" (
+    instance TEXT NOT NULL PRIMARY KEY,
+    client_id TEXT NOT NULL,
+    client_secret TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE "session" (
+    id TEXT NOT NULL PRIMARY KEY,
+    account_id BIGINT NOT NULL,
+    -- The identity selected in the session.
+    identity_id BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+CREATE INDEX "session_expires_at" ON "session" (expires_at);
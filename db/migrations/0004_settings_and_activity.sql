@@ -0,0 +1,204 @@
+CREATE TABLE "user_settings" (
+    user_id BIGINT NOT NULL PRIMARY KEY,
+    follow_back_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    dry_run BOOLEAN NOT NULL DEFAULT FALSE,
+    daily_follow_cap INTEGER NOT NULL DEFAULT 400,
+    skip_default_profile_image BOOLEAN NOT NULL DEFAULT FALSE,
+    min_followers_count INTEGER NOT NULL DEFAULT 0,
+    max_friends_followers_ratio DOUBLE PRECISION,
+    min_account_age_days INTEGER NOT NULL DEFAULT 0,
+    blocked_bio_keywords TEXT[] NOT NULL DEFAULT '{}',
+    quiet_hours_start SMALLINT,
+    quiet_hours_end SMALLINT,
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    welcome_message_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    welcome_message_template TEXT NOT NULL DEFAULT '',
+    spam_action TEXT NOT NULL DEFAULT 'none',
+    spam_default_profile_image BOOLEAN NOT NULL DEFAULT FALSE,
+    spam_min_followers_count INTEGER NOT NULL DEFAULT 0,
+    spam_max_friends_followers_ratio DOUBLE PRECISION,
+    spam_min_account_age_days INTEGER NOT NULL DEFAULT 0,
+    spam_bio_keywords TEXT[] NOT NULL DEFAULT '{}'
+);
+CREATE TABLE "follow_action" (
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    dry_run BOOLEAN NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX "follow_action_source_id_created_at" ON "follow_action" (source_id, created_at);
+CREATE TABLE "profile" (
+    id BIGINT NOT NULL PRIMARY KEY,
+    screen_name TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    followers_count INTEGER NOT NULL,
+    friends_count INTEGER NOT NULL,
+    default_profile_image BOOLEAN NOT NULL,
+    protected BOOLEAN NOT NULL,
+    account_created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX "profile_lower_screen_name" ON "profile" (LOWER(screen_name));
+CREATE TABLE "relationship_history" (
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    event TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX "relationship_history_source_id_created_at" ON "relationship_history" (source_id, created_at);
+CREATE TABLE "relationship_snapshot" (
+    source_id BIGINT NOT NULL,
+    date DATE NOT NULL,
+    follower_count INTEGER NOT NULL,
+    friend_count INTEGER NOT NULL,
+    mutual_count INTEGER NOT NULL,
+    followers_gained INTEGER NOT NULL,
+    followers_lost INTEGER NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, date)
+);
+CREATE TABLE "blocklist" (
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    PRIMARY KEY (source_id, target_id)
+);
+CREATE INDEX "blocklist_source_id" ON "blocklist" (source_id);
+-- New followers reported by the webhook, to be checked before the others.
+CREATE TABLE "follow_back_request" (
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, target_id)
+);
+-- Each follower is greeted at most once. Dry runs are replaced by the real message.
+CREATE TABLE "welcome_message" (
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    text TEXT NOT NULL,
+    dry_run BOOLEAN NOT NULL,
+    -- Saved before sending, so that a crash while sending never sends the message twice.
+    pending BOOLEAN NOT NULL DEFAULT FALSE,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, target_id)
+);
+CREATE INDEX "welcome_message_source_id_created_at" ON "welcome_message" (source_id, created_at);
+-- New followers checked against the spam rules, so that each is checked once.
+CREATE TABLE "spam_check" (
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, target_id)
+);
+CREATE TABLE "moderation_action" (
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+    source_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    -- `mute`, `block` or `soft_block`.
+    action TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    dry_run BOOLEAN NOT NULL,
+    error TEXT,
+    -- A soft block whose unblock hasn't gone out yet, which leaves the follower blocked.
+    pending_unblock BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    undone_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX "moderation_action_source_id_created_at" ON "moderation_action" (source_id, created_at);
+-- The follow-back rules of the users who replaced the default ones.
+CREATE TABLE "follow_back_rules" (
+    user_id BIGINT NOT NULL PRIMARY KEY,
+    rules JSONB NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- When each relation kind of each user was last synchronized completely.
+CREATE TABLE "relationship_sync" (
+    source_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    finished_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, kind)
+);
+-- Append-only, enforced by the triggers below. Entries older than the retention are moved to
+-- "audit_log_archive".
+CREATE TABLE "audit_log" (
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    -- `worker`, `user` or `admin`.
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target_id BIGINT,
+    parameters JSONB NOT NULL,
+    dry_run BOOLEAN NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX "audit_log_user_id_id" ON "audit_log" (user_id, id);
+CREATE INDEX "audit_log_created_at" ON "audit_log" (created_at);
+CREATE TABLE "audit_log_archive" (
+    id BIGINT NOT NULL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target_id BIGINT,
+    parameters JSONB NOT NULL,
+    dry_run BOOLEAN NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    archived_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- Audit entries can't be changed, and only leave "audit_log" for "audit_log_archive": deleting
+-- an entry archives it.
+CREATE FUNCTION "audit_log_reject_change"() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION '% is append-only', TG_TABLE_NAME;
+END;
+$$ LANGUAGE plpgsql;
+CREATE FUNCTION "audit_log_archive_deleted"() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO "audit_log_archive"
+    (
+        id,
+        user_id,
+        actor,
+        action,
+        target_id,
+        parameters,
+        dry_run,
+        error,
+        created_at
+    )
+    VALUES (
+        OLD.id,
+        OLD.user_id,
+        OLD.actor,
+        OLD.action,
+        OLD.target_id,
+        OLD.parameters,
+        OLD.dry_run,
+        OLD.error,
+        OLD.created_at
+    );
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER "audit_log_append_only" BEFORE UPDATE OR TRUNCATE ON "audit_log"
+    FOR EACH STATEMENT EXECUTE FUNCTION "audit_log_reject_change"();
+CREATE TRIGGER "audit_log_archive_on_delete" BEFORE DELETE ON "audit_log"
+    FOR EACH ROW EXECUTE FUNCTION "audit_log_archive_deleted"();
+CREATE TRIGGER "audit_log_archive_append_only" BEFORE UPDATE OR DELETE OR TRUNCATE ON "audit_log_archive"
+    FOR EACH STATEMENT EXECUTE FUNCTION "audit_log_reject_change"();
+-- Identities whose workers were paused by an admin.
+CREATE TABLE "paused_user" (
+    user_id BIGINT NOT NULL PRIMARY KEY,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- Relation kinds to synchronize again from the first page, requested by an admin.
+CREATE TABLE "sync_request" (
+    source_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, kind)
+);